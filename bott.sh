@@ -15,6 +15,18 @@ function bott_init() {
 	export bott_last_other_response=""
 	export bott_last_other_exit_code=0
 	export bott_context=""
+	export bott_shell_commands=""
+	# groups the usage of this session in bott usage
+	export bott_session="$(date +%Y%m%d%H%M%S)-$RANDOM"
+}
+# Aliases and functions aren't on $PATH, bott needs them to tell valid answers like `ll` from typos
+function bott_list_shell_commands() {
+	if [ -n "$ZSH_VERSION" ]; then
+		print -rl -- ${(k)aliases} ${(k)functions}
+	else
+		compgen -a
+		compgen -A function
+	fi
 }
 function bott_execute_code() {
 	bott_last_run_executed_code=$1
@@ -26,40 +38,6 @@ function bott_execute_code() {
 	fi
 	return $bott_last_run_exit_code
 }
-function bott_get_distro() {
-	local d=""
-	if [[ -f /etc/os-release ]]; then
-		# On Linux systems
-		source /etc/os-release
-		d=$ID
-	else
-		# On systems other than Linux (e.g. Mac or FreeBSD)
-		d=$(uname)
-	fi
-
-	case $d in
-	"raspbian")
-		echo Raspbian
-		;;
-	"fedora")
-		echo Fedora
-		;;
-	"ubuntu")
-		echo Ubuntu
-		;;
-	"Darwin")
-		echo macOS
-		;;
-	esac
-}
-function bott_get_shell() {
-	if [ -z "${SHELL}" ]; then
-		_SHELL=$(ps -o args= -p "$$" | cut -d- -f2)
-	else
-		_SHELL=$SHELL
-	fi
-	echo "$_SHELL"
-}
 function bott!() {
 	local subcommand=$1
 	case $subcommand in
@@ -70,10 +48,14 @@ function bott!() {
 		return "$bott_last_run_exit_code"
 		;;
 	"query")
-		local distro="$(bott_get_distro)"
-		local shell="$(bott_get_shell)"
 		local query="${*/"query"/""}"
-		local code_to_exec="bott_ query -d \"$distro\" -s \"$shell\" -q \"$query\""
+		local flags=""
+		if [[ "$query" =~ ^\ --no-cache ]]; then
+			query="${query/" --no-cache"/""}"
+			flags="--no-cache"
+		fi
+		bott_shell_commands=$(bott_list_shell_commands | tr '\n' ' ')
+		local code_to_exec="bott_ query $flags -q \"$query\""
 		bott_last_query_response=$(eval "$code_to_exec")
 		bott_last_query_exit_code=$?
 		if [ $bott_last_query_exit_code -ne 0 ]; then
@@ -86,8 +68,28 @@ function bott!() {
 			echo "Didnt get your question. Please try asking only questions related to bash commands"
 			return 1
 		fi
+		local warnings=$(echo "$bott_last_query_response" | awk -v RS="<WARNINGS>" -v ORS="" 'NR>1{gsub(/<\/WARNINGS>.*/, ""); print}')
+		local explanation=$(echo "$bott_last_query_response" | awk -v RS="<EXPLANATION>" -v ORS="" 'NR>1{gsub(/<\/EXPLANATION>.*/, ""); print}')
+		local assumptions=$(echo "$bott_last_query_response" | awk -v RS="<ASSUMPTIONS>" -v ORS="" 'NR>1{gsub(/<\/ASSUMPTIONS>.*/, ""); print}')
+		local cached=$(echo "$bott_last_query_response" | awk -v RS="<CACHED>" -v ORS="" 'NR>1{gsub(/<\/CACHED>.*/, ""); print}')
 		bott_context="$context"
+		if [ -n "$cached" ]; then
+			echo "Cached answer from $cached ago, ask with bott! query --no-cache for a fresh one"
+		fi
+		if [ -n "$warnings" ]; then
+			echo "$warnings" | while read -r warning; do
+				echo "Warning: $warning"
+			done
+		fi
 		echo "Answer: $answer"
+		if [ -n "$explanation" ]; then
+			echo "Explanation: $explanation"
+		fi
+		if [ -n "$assumptions" ]; then
+			echo "$assumptions" | while read -r assumption; do
+				echo "Assumes: $assumption"
+			done
+		fi
 		if bott_ confirm -q "Do you want to run the command?"; then
 			bott_execute_code $answer
 			echo $bott_last_run_output
@@ -99,9 +101,7 @@ function bott!() {
 		echo "session cleared"
 		;;
 	"debug")
-		local distro="$(bott_get_distro)"
-		local shell="$(bott_get_shell)"
-		local code_to_exec="bott_ debug -d \"$distro\" -s \"$shell\""
+		local code_to_exec="bott_ debug"
 		bott_last_debug_response=$(eval "$code_to_exec")
 		bott_last_debug_exit_code=$?
 		if [ $bott_last_debug_exit_code -ne 0 ]; then
@@ -116,12 +116,25 @@ function bott!() {
 		bott_last_other_response=$(eval "$code_to_exec" 2>&1)
 		bott_last_other_exit_code=$?
 		echo "$bott_last_other_response"
-		local query="${*/"config"/""}"
-		if [ $bott_last_other_exit_code -eq 0 ] && [[ ! "$query" =~ ^\ get ]]; then
-			bott_init
-			echo "config set and session cleared"
-		fi
-
+		return $bott_last_other_exit_code
+		;;
+	"models")
+		# not captured, choosing a model is interactive
+		eval "bott_ $*"
+		bott_last_other_exit_code=$?
+		return $bott_last_other_exit_code
+		;;
+	"examples")
+		# not evaluated, examples are full of quotes and spaces
+		bott_last_other_response=$("$BOTT_EXECUTABLE_DIR/bott" "$@" 2>&1)
+		bott_last_other_exit_code=$?
+		echo "$bott_last_other_response"
+		return $bott_last_other_exit_code
+		;;
+	"prompt")
+		# not captured, editing opens an editor
+		eval "bott_ $*"
+		bott_last_other_exit_code=$?
 		return $bott_last_other_exit_code
 		;;
 	*)
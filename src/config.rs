@@ -1,64 +1,413 @@
 use crate::errors::BottError;
 use crate::keychain::Keychain;
+use crate::llm::openai::RESPONSE_FORMATS;
+use crate::llm::LLMS;
 use crate::result::BottResult;
 use directories::UserDirs;
+use regex::Regex;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::env;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+// Sampling settings sent along with each request, unset ones are left to the provider
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct GenerationOptions {
+    // ask for a json answer with the command and what it does instead of a bash block
+    pub structured: Option<bool>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub num_ctx: Option<u32>,
+    pub seed: Option<i64>,
+    pub stop: Option<Vec<String>>,
+    pub keep_alive: Option<String>,
+}
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct OllamaOptions {
-    model: String,
+    pub model: String,
+    #[serde(flatten)]
+    pub generation: GenerationOptions,
+}
+impl Default for OllamaOptions {
+    fn default() -> Self {
+        Self {
+            model: String::from("codellama:7b-instruct"),
+            generation: GenerationOptions::default(),
+        }
+    }
 }
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct OpenaiOptions {
-    model: String,
+    pub model: String,
+    #[serde(flatten)]
+    pub generation: GenerationOptions,
+}
+impl Default for OpenaiOptions {
+    fn default() -> Self {
+        Self {
+            model: String::from("gpt-4"),
+            generation: GenerationOptions::default(),
+        }
+    }
+}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AnthropicOptions {
+    pub model: String,
+}
+impl Default for AnthropicOptions {
+    fn default() -> Self {
+        Self {
+            model: String::from("claude-3-5-sonnet-latest"),
+        }
+    }
+}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GeminiOptions {
+    pub model: String,
+}
+impl Default for GeminiOptions {
+    fn default() -> Self {
+        Self {
+            model: String::from("gemini-1.5-pro"),
+        }
+    }
+}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AzureOptions {
+    endpoint: Option<String>,
+    deployment: Option<String>,
+    api_version: Option<String>,
+    // what the model behind the deployment supports for structured answers
+    response_format: Option<String>,
+    #[serde(flatten)]
+    pub generation: GenerationOptions,
+}
+impl Default for AzureOptions {
+    fn default() -> Self {
+        Self {
+            endpoint: None,
+            deployment: None,
+            api_version: Some(String::from("2024-02-01")),
+            response_format: None,
+            generation: GenerationOptions::default(),
+        }
+    }
+}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LlamacppOptions {
+    pub url: String,
+    pub api: String,
+}
+impl Default for LlamacppOptions {
+    fn default() -> Self {
+        Self {
+            url: String::from("http://localhost:8080"),
+            api: String::from("chat"),
+        }
+    }
+}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LocalOptions {
+    pub model_path: Option<String>,
+    pub tokenizer_path: Option<String>,
+    pub template: String,
+}
+impl Default for LocalOptions {
+    fn default() -> Self {
+        Self {
+            model_path: None,
+            tokenizer_path: None,
+            template: String::from("llama2"),
+        }
+    }
+}
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MockOptions {
+    pub script: Option<String>,
+}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HttpOptions {
+    pub connect_timeout: usize,
+    pub read_timeout: usize,
+    pub retries: usize,
+}
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            connect_timeout: 10,
+            read_timeout: 60,
+            retries: 3,
+        }
+    }
+}
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ProxyOptions {
+    pub url: Option<String>,
+    pub no_proxy: Option<String>,
+}
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TlsOptions {
+    pub ca_bundle: Option<String>,
+}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ContextOptions {
+    pub project: bool,
+    pub git: bool,
+    pub manifests: bool,
+    pub listing: bool,
+}
+impl Default for ContextOptions {
+    fn default() -> Self {
+        Self {
+            project: false,
+            git: true,
+            manifests: true,
+            listing: true,
+        }
+    }
+}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HistoryOptions {
+    pub enabled: bool,
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+    pub examples: usize,
+}
+impl Default for HistoryOptions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            allow: vec![],
+            deny: vec![],
+            examples: 5,
+        }
+    }
+}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExamplesOptions {
+    pub enabled: bool,
+    pub count: usize,
+    // prefer examples whose question is like the query
+    pub similar: bool,
+}
+impl Default for ExamplesOptions {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            count: 3,
+            similar: true,
+        }
+    }
+}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CacheOptions {
+    // seconds an answer is reused for, 0 turns the cache off
+    pub ttl: usize,
+}
+impl Default for CacheOptions {
+    fn default() -> Self {
+        Self { ttl: 86400 }
+    }
+}
+// US dollars per million tokens
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Price {
+    pub input: f64,
+    pub output: f64,
+}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UsageOptions {
+    pub prices: BTreeMap<String, Price>,
+    pub monthly_budget: Option<f64>,
+    // warn or block once the budget is spent
+    pub budget_action: String,
+}
+impl Default for UsageOptions {
+    fn default() -> Self {
+        // list prices of the default hosted models, local models cost nothing
+        let prices = [
+            ("gpt-4", 30.0, 60.0),
+            ("gpt-4o", 2.5, 10.0),
+            ("gpt-4o-mini", 0.15, 0.6),
+            ("claude-3-5-sonnet-latest", 3.0, 15.0),
+            ("gemini-1.5-pro", 1.25, 5.0),
+        ]
+        .into_iter()
+        .map(|(m, input, output)| (m.to_string(), Price { input, output }))
+        .collect::<BTreeMap<String, Price>>();
+        Self {
+            prices,
+            monthly_budget: None,
+            budget_action: String::from("warn"),
+        }
+    }
 }
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct BottConfig {
     version: String,
     llm: String,
+    llm_timeout: Option<usize>,
     ollama_options: Option<OllamaOptions>,
     openai_options: Option<OpenaiOptions>,
+    anthropic_options: Option<AnthropicOptions>,
+    gemini_options: Option<GeminiOptions>,
+    azure_options: Option<AzureOptions>,
+    llamacpp_options: Option<LlamacppOptions>,
+    local_options: Option<LocalOptions>,
+    mock_options: Option<MockOptions>,
+    context_options: Option<ContextOptions>,
+    history_options: Option<HistoryOptions>,
+    examples_options: Option<ExamplesOptions>,
+    http_options: Option<HttpOptions>,
+    proxy_options: Option<ProxyOptions>,
+    tls_options: Option<TlsOptions>,
+    usage_options: Option<UsageOptions>,
+    cache_options: Option<CacheOptions>,
 }
 impl Default for BottConfig {
     fn default() -> Self {
         Self {
             version: String::from("0.1.0"),
             llm: String::from("ollama"),
-            ollama_options: Some(OllamaOptions {
-                model: String::from("codellama:7b-instruct"),
-            }),
-            openai_options: Some(OpenaiOptions {
-                model: String::from("gpt-4"),
-            }),
+            llm_timeout: Some(180),
+            ollama_options: Some(OllamaOptions::default()),
+            openai_options: Some(OpenaiOptions::default()),
+            anthropic_options: Some(AnthropicOptions::default()),
+            gemini_options: Some(GeminiOptions::default()),
+            azure_options: Some(AzureOptions::default()),
+            llamacpp_options: Some(LlamacppOptions::default()),
+            local_options: Some(LocalOptions::default()),
+            mock_options: Some(MockOptions::default()),
+            context_options: Some(ContextOptions::default()),
+            history_options: Some(HistoryOptions::default()),
+            examples_options: Some(ExamplesOptions::default()),
+            http_options: Some(HttpOptions::default()),
+            proxy_options: Some(ProxyOptions::default()),
+            tls_options: Some(TlsOptions::default()),
+            usage_options: Some(UsageOptions::default()),
+            cache_options: Some(CacheOptions::default()),
         }
     }
 }
-impl BottConfig {
-    fn get_path() -> BottResult<String> {
-        let mut home = Path::new("");
-        let mut _user_dirs: UserDirs;
-        if let Some(user_dirs) = UserDirs::new() {
-            _user_dirs = user_dirs.clone();
-            home = _user_dirs.home_dir();
+fn parse_bool(key: &str, value: &str) -> BottResult<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(BottError::ConfigValueErr(key.to_string())),
+    }
+}
+fn parse_usize(key: &str, value: &str) -> BottResult<usize> {
+    value
+        .parse::<usize>()
+        .map_err(|_| BottError::ConfigValueErr(key.to_string()))
+}
+fn parse_number<T: std::str::FromStr + PartialOrd>(
+    key: &str,
+    value: &str,
+    min: T,
+    max: T,
+) -> BottResult<T> {
+    match value.parse::<T>() {
+        Ok(v) if v >= min && v <= max => Ok(v),
+        _ => Err(BottError::ConfigValueErr(key.to_string())),
+    }
+}
+// Sets one of the generation options, the key is the part after the llm namespace
+fn set_generation_option(
+    options: &mut GenerationOptions,
+    key: &str,
+    value: &str,
+) -> BottResult<()> {
+    let (_, name) = key.split_once(':').unwrap();
+    match name {
+        "structured" => options.structured = Some(parse_bool(key, value)?),
+        "temperature" => options.temperature = Some(parse_number(key, value, 0.0, 2.0)?),
+        "top_p" => options.top_p = Some(parse_number(key, value, 0.0, 1.0)?),
+        "max_tokens" => options.max_tokens = Some(parse_number(key, value, 1, u32::MAX)?),
+        "num_ctx" => options.num_ctx = Some(parse_number(key, value, 1, u32::MAX)?),
+        "seed" => options.seed = Some(parse_number(key, value, i64::MIN, i64::MAX)?),
+        "stop" => {
+            let stop = value
+                .split(',')
+                .filter(|s| !s.is_empty())
+                .map(|s| s.replace("\\n", "\n"))
+                .collect::<Vec<String>>();
+            options.stop = Some(stop).filter(|s| !s.is_empty());
         }
+        _ => {
+            // how long ollama keeps the model loaded: 30s, 5m, 1h, 0 to unload, -1 forever
+            let re = Regex::new(r"^-?\d+(\.\d+)?(ms|s|m|h)?$").unwrap();
+            if !re.is_match(value) {
+                return Err(BottError::ConfigValueErr(key.to_string()));
+            }
+            options.keep_alive = Some(value.to_string());
+        }
+    }
+    Ok(())
+}
+fn get_generation_option(options: &GenerationOptions, key: &str) -> Option<String> {
+    let (_, name) = key.split_once(':').unwrap();
+    match name {
+        "structured" => Some(options.structured.unwrap_or_default().to_string()),
+        "temperature" => options.temperature.map(|v| v.to_string()),
+        "top_p" => options.top_p.map(|v| v.to_string()),
+        "max_tokens" => options.max_tokens.map(|v| v.to_string()),
+        "num_ctx" => options.num_ctx.map(|v| v.to_string()),
+        "seed" => options.seed.map(|v| v.to_string()),
+        "stop" => options
+            .stop
+            .as_ref()
+            .map(|s| s.join(",").replace('\n', "\\n")),
+        _ => options.keep_alive.clone(),
+    }
+}
+fn delete_generation_option(options: &mut GenerationOptions, key: &str) {
+    let (_, name) = key.split_once(':').unwrap();
+    match name {
+        "structured" => options.structured = None,
+        "temperature" => options.temperature = None,
+        "top_p" => options.top_p = None,
+        "max_tokens" => options.max_tokens = None,
+        "num_ctx" => options.num_ctx = None,
+        "seed" => options.seed = None,
+        "stop" => options.stop = None,
+        _ => options.keep_alive = None,
+    }
+}
+// Parses a comma separated list of regular expressions
+fn parse_patterns(key: &str, value: &str) -> BottResult<Vec<String>> {
+    let patterns = value
+        .split(',')
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .collect::<Vec<String>>();
+    if patterns.iter().any(|p| Regex::new(p).is_err()) {
+        return Err(BottError::ConfigValueErr(key.to_string()));
+    }
+    Ok(patterns)
+}
+impl BottConfig {
+    pub fn get_dir() -> PathBuf {
         let bott_dir_env = env::var("BOTT_DIR").unwrap_or(String::from(""));
         if !bott_dir_env.is_empty() {
-            return match Path::new(bott_dir_env.as_str())
-                .join(Path::new("config.yml"))
-                .into_os_string()
-                .into_string()
-            {
-                Ok(s) => Ok(s),
-                Err(_) => Err(BottError::ConfigPathErr),
-            };
-        }
-        let bott_directory = Path::new(".bott/config.yml");
-        return match home.join(bott_directory).into_os_string().into_string() {
+            return PathBuf::from(bott_dir_env);
+        }
+        let mut home = PathBuf::new();
+        if let Some(user_dirs) = UserDirs::new() {
+            home = user_dirs.home_dir().to_path_buf();
+        }
+        home.join(".bott")
+    }
+    fn get_path() -> BottResult<String> {
+        match BottConfig::get_dir()
+            .join(Path::new("config.yml"))
+            .into_os_string()
+            .into_string()
+        {
             Ok(s) => Ok(s),
             Err(_) => Err(BottError::ConfigPathErr),
-        };
+        }
     }
     pub fn load() -> BottResult<BottConfig> {
         let bott_config_path = BottConfig::get_path()?;
@@ -66,49 +415,306 @@ impl BottConfig {
         if let Ok(config) = confy::load_path::<BottConfig>(bott_config_path) {
             return Ok(config);
         }
-        return Err(BottError::ConfigLoadErr);
+        Err(BottError::ConfigLoadErr)
     }
     pub fn save(&self) -> BottResult<()> {
         let bott_config_path = BottConfig::get_path()?;
         let _config = self.clone();
-        return match confy::store_path(bott_config_path, _config) {
+        match confy::store_path(bott_config_path, _config) {
             Ok(_) => Ok(()),
             Err(e) => {
                 print!("err is {:?}", e);
                 Err(BottError::ConfigStoreErr)
             }
-        };
+        }
+    }
+    pub fn get_llm_timeout(&self) -> usize {
+        self.llm_timeout
+            .or(BottConfig::default().llm_timeout)
+            .unwrap_or_default()
+    }
+    pub fn get_ollama_options(&self) -> OllamaOptions {
+        self.ollama_options.clone().unwrap_or_default()
+    }
+    pub fn get_openai_options(&self) -> OpenaiOptions {
+        self.openai_options.clone().unwrap_or_default()
+    }
+    // configs written before anthropic was supported don't have these options
+    pub fn get_anthropic_options(&self) -> AnthropicOptions {
+        self.anthropic_options.clone().unwrap_or_default()
+    }
+    pub fn get_gemini_options(&self) -> GeminiOptions {
+        self.gemini_options.clone().unwrap_or_default()
+    }
+    pub fn get_azure_options(&self) -> AzureOptions {
+        self.azure_options.clone().unwrap_or_default()
+    }
+    pub fn get_llamacpp_options(&self) -> LlamacppOptions {
+        self.llamacpp_options.clone().unwrap_or_default()
+    }
+    pub fn get_local_options(&self) -> LocalOptions {
+        self.local_options.clone().unwrap_or_default()
+    }
+    pub fn get_mock_options(&self) -> MockOptions {
+        self.mock_options.clone().unwrap_or_default()
+    }
+    pub fn get_context_options(&self) -> ContextOptions {
+        self.context_options.clone().unwrap_or_default()
+    }
+    pub fn get_history_options(&self) -> HistoryOptions {
+        self.history_options.clone().unwrap_or_default()
+    }
+    pub fn get_examples_options(&self) -> ExamplesOptions {
+        self.examples_options.clone().unwrap_or_default()
+    }
+    pub fn get_http_options(&self) -> HttpOptions {
+        self.http_options.clone().unwrap_or_default()
+    }
+    pub fn get_proxy_options(&self) -> ProxyOptions {
+        self.proxy_options.clone().unwrap_or_default()
+    }
+    pub fn get_tls_options(&self) -> TlsOptions {
+        self.tls_options.clone().unwrap_or_default()
+    }
+    pub fn get_usage_options(&self) -> UsageOptions {
+        self.usage_options.clone().unwrap_or_default()
+    }
+    pub fn get_cache_options(&self) -> CacheOptions {
+        self.cache_options.clone().unwrap_or_default()
     }
     pub fn set_key(&mut self, key: &str, value: &str) -> BottResult<()> {
         match key {
             "llm" => {
-                self.llm = String::from(value);
+                // an ordered list of llms, later ones are used when earlier ones can't answer
+                let llms = value
+                    .split(',')
+                    .map(|l| l.trim())
+                    .filter(|l| !l.is_empty())
+                    .collect::<Vec<&str>>();
+                if llms.is_empty() || llms.iter().any(|l| !LLMS.contains(l)) {
+                    return Err(BottError::ConfigValueErr(key.to_string()));
+                }
+                self.llm = llms.join(",");
+                self.save()?;
+            }
+            "llm:timeout" => {
+                self.llm_timeout = Some(parse_usize(key, value)?);
                 self.save()?;
             }
             "ollama:model" => {
-                self.ollama_options = Some(OllamaOptions {
+                let mut options = self.get_ollama_options();
+                options.model = value.to_string();
+                self.ollama_options = Some(options);
+                self.save()?;
+            }
+            "openai:model" => {
+                let mut options = self.get_openai_options();
+                options.model = value.to_string();
+                self.openai_options = Some(options);
+                self.save()?;
+            }
+            "ollama:structured" | "ollama:temperature" | "ollama:top_p" | "ollama:max_tokens"
+            | "ollama:num_ctx" | "ollama:seed" | "ollama:stop" | "ollama:keep_alive" => {
+                let mut options = self.get_ollama_options();
+                set_generation_option(&mut options.generation, key, value)?;
+                self.ollama_options = Some(options);
+                self.save()?;
+            }
+            "openai:structured" | "openai:temperature" | "openai:top_p" | "openai:max_tokens"
+            | "openai:seed" | "openai:stop" => {
+                let mut options = self.get_openai_options();
+                set_generation_option(&mut options.generation, key, value)?;
+                self.openai_options = Some(options);
+                self.save()?;
+            }
+            "azure:structured" | "azure:temperature" | "azure:top_p" | "azure:max_tokens"
+            | "azure:seed" | "azure:stop" => {
+                let mut options = self.get_azure_options();
+                set_generation_option(&mut options.generation, key, value)?;
+                self.azure_options = Some(options);
+                self.save()?;
+            }
+            "anthropic:model" => {
+                self.anthropic_options = Some(AnthropicOptions {
                     model: value.to_string(),
                 });
                 self.save()?;
             }
-            "openai:model" => {
-                self.openai_options = Some(OpenaiOptions {
+            "gemini:model" => {
+                self.gemini_options = Some(GeminiOptions {
                     model: value.to_string(),
                 });
                 self.save()?;
             }
-            "openai:api_key" => {
-                let (namespace, key) = key.split_once(":").unwrap();
+            "azure:endpoint" | "azure:deployment" | "azure:api_version" => {
+                let mut options = self.get_azure_options();
+                let value = Some(value.to_string());
+                match key {
+                    "azure:endpoint" => options.endpoint = value,
+                    "azure:deployment" => options.deployment = value,
+                    _ => options.api_version = value,
+                }
+                self.azure_options = Some(options);
+                self.save()?;
+            }
+            "azure:response_format" => {
+                if !RESPONSE_FORMATS.contains(&value) {
+                    return Err(BottError::ConfigValueErr(key.to_string()));
+                }
+                let mut options = self.get_azure_options();
+                options.response_format = Some(value.to_string());
+                self.azure_options = Some(options);
+                self.save()?;
+            }
+            "openai:api_key" | "anthropic:api_key" | "gemini:api_key" | "azure:api_key" => {
+                let (namespace, key) = key.split_once(':').unwrap();
                 let keychain = Keychain::load(namespace);
                 keychain.set(key, value)?;
             }
+            "llamacpp:url" | "llamacpp:api" => {
+                let mut options = self.get_llamacpp_options();
+                match key {
+                    "llamacpp:url" => options.url = value.trim_end_matches('/').to_string(),
+                    _ => match value {
+                        "chat" | "completion" => options.api = value.to_string(),
+                        _ => return Err(BottError::ConfigValueErr(key.to_string())),
+                    },
+                }
+                self.llamacpp_options = Some(options);
+                self.save()?;
+            }
+            "local:model_path" | "local:tokenizer_path" | "local:template" => {
+                let mut options = self.get_local_options();
+                match key {
+                    "local:model_path" => options.model_path = Some(value.to_string()),
+                    "local:tokenizer_path" => options.tokenizer_path = Some(value.to_string()),
+                    _ => match value {
+                        "llama2" | "chatml" => options.template = value.to_string(),
+                        _ => return Err(BottError::ConfigValueErr(key.to_string())),
+                    },
+                }
+                self.local_options = Some(options);
+                self.save()?;
+            }
+            "mock:script" => {
+                self.mock_options = Some(MockOptions {
+                    script: Some(value.to_string()),
+                });
+                self.save()?;
+            }
+            "context:project" | "context:git" | "context:manifests" | "context:listing" => {
+                let enabled = parse_bool(key, value)?;
+                let mut options = self.get_context_options();
+                match key {
+                    "context:project" => options.project = enabled,
+                    "context:git" => options.git = enabled,
+                    "context:manifests" => options.manifests = enabled,
+                    _ => options.listing = enabled,
+                }
+                self.context_options = Some(options);
+                self.save()?;
+            }
+            "history:enabled" | "history:allow" | "history:deny" | "history:examples" => {
+                let mut options = self.get_history_options();
+                match key {
+                    "history:enabled" => options.enabled = parse_bool(key, value)?,
+                    "history:allow" => options.allow = parse_patterns(key, value)?,
+                    "history:deny" => options.deny = parse_patterns(key, value)?,
+                    _ => options.examples = parse_usize(key, value)?,
+                }
+                self.history_options = Some(options);
+                self.save()?;
+            }
+            "examples:enabled" | "examples:count" | "examples:similar" => {
+                let mut options = self.get_examples_options();
+                match key {
+                    "examples:enabled" => options.enabled = parse_bool(key, value)?,
+                    "examples:count" => options.count = parse_usize(key, value)?,
+                    _ => options.similar = parse_bool(key, value)?,
+                }
+                self.examples_options = Some(options);
+                self.save()?;
+            }
+            "http:connect_timeout" | "http:read_timeout" | "http:retries" => {
+                let value = parse_usize(key, value)?;
+                let mut options = self.get_http_options();
+                match key {
+                    "http:connect_timeout" => options.connect_timeout = value,
+                    "http:read_timeout" => options.read_timeout = value,
+                    _ => options.retries = value,
+                }
+                self.http_options = Some(options);
+                self.save()?;
+            }
+            "proxy:url" | "proxy:no_proxy" => {
+                let mut options = self.get_proxy_options();
+                match key {
+                    "proxy:url" => {
+                        if reqwest::Proxy::all(value).is_err() {
+                            return Err(BottError::ConfigValueErr(key.to_string()));
+                        }
+                        options.url = Some(value.to_string());
+                    }
+                    _ => options.no_proxy = Some(value.to_string()),
+                }
+                self.proxy_options = Some(options);
+                self.save()?;
+            }
+            "tls:ca_bundle" => {
+                if !Path::new(value).is_file() {
+                    return Err(BottError::ConfigValueErr(key.to_string()));
+                }
+                self.tls_options = Some(TlsOptions {
+                    ca_bundle: Some(value.to_string()),
+                });
+                self.save()?;
+            }
+            "cache:ttl" => {
+                self.cache_options = Some(CacheOptions {
+                    ttl: parse_usize(key, value)?,
+                });
+                self.save()?;
+            }
+            "budget:monthly" | "budget:action" => {
+                let mut options = self.get_usage_options();
+                match key {
+                    "budget:monthly" => {
+                        options.monthly_budget = Some(parse_number(key, value, 0.0, f64::MAX)?)
+                    }
+                    _ => match value {
+                        "warn" | "block" => options.budget_action = value.to_string(),
+                        _ => return Err(BottError::ConfigValueErr(key.to_string())),
+                    },
+                }
+                self.usage_options = Some(options);
+                self.save()?;
+            }
+            // price:MODEL takes the input and output price per million tokens, like 2.5,10
+            _ if key.starts_with("price:") => {
+                let (_, model) = key.split_once(':').unwrap();
+                let price = match value.split_once(',') {
+                    Some((input, output)) => Price {
+                        input: parse_number(key, input.trim(), 0.0, f64::MAX)?,
+                        output: parse_number(key, output.trim(), 0.0, f64::MAX)?,
+                    },
+                    None => return Err(BottError::ConfigValueErr(key.to_string())),
+                };
+                if model.is_empty() {
+                    return Err(BottError::ConfigValueErr(key.to_string()));
+                }
+                let mut options = self.get_usage_options();
+                options.prices.insert(model.to_string(), price);
+                self.usage_options = Some(options);
+                self.save()?;
+            }
             _ => unimplemented!(),
         };
         Ok(())
     }
     pub fn get_key(&mut self, key: &str) -> BottResult<Option<String>> {
-        return match key {
+        match key {
             "llm" => Ok(Some(self.llm.clone())),
+            "llm:timeout" => Ok(Some(self.get_llm_timeout().to_string())),
             "ollama:model" => {
                 if let Some(options) = self.ollama_options.clone() {
                     return Ok(Some(options.model));
@@ -121,32 +727,277 @@ impl BottConfig {
                 }
                 Ok(None)
             }
-            "openai:api_key" => {
-                let (namespace, key) = key.split_once(":").unwrap();
+            "ollama:structured" | "ollama:temperature" | "ollama:top_p" | "ollama:max_tokens"
+            | "ollama:num_ctx" | "ollama:seed" | "ollama:stop" | "ollama:keep_alive" => Ok(
+                get_generation_option(&self.get_ollama_options().generation, key),
+            ),
+            "openai:structured" | "openai:temperature" | "openai:top_p" | "openai:max_tokens"
+            | "openai:seed" | "openai:stop" => Ok(get_generation_option(
+                &self.get_openai_options().generation,
+                key,
+            )),
+            "azure:structured" | "azure:temperature" | "azure:top_p" | "azure:max_tokens"
+            | "azure:seed" | "azure:stop" => Ok(get_generation_option(
+                &self.get_azure_options().generation,
+                key,
+            )),
+            "anthropic:model" => Ok(Some(self.get_anthropic_options().model)),
+            "gemini:model" => Ok(Some(self.get_gemini_options().model)),
+            "azure:endpoint" | "azure:deployment" | "azure:api_version" => {
+                let options = self.get_azure_options();
+                Ok(match key {
+                    "azure:endpoint" => options.endpoint,
+                    "azure:deployment" => options.deployment,
+                    // a deleted api version goes back to the default
+                    _ => options.api_version.or(AzureOptions::default().api_version),
+                })
+            }
+            // deployment names don't tell the model, json mode works on most of them
+            "azure:response_format" => Ok(Some(
+                self.get_azure_options()
+                    .response_format
+                    .unwrap_or(String::from("json_object")),
+            )),
+            "openai:api_key" | "anthropic:api_key" | "gemini:api_key" | "azure:api_key" => {
+                let (namespace, key) = key.split_once(':').unwrap();
                 let keychain = Keychain::load(namespace);
                 Ok(keychain.get(key)?)
             }
+            "llamacpp:url" => Ok(Some(self.get_llamacpp_options().url)),
+            "llamacpp:api" => Ok(Some(self.get_llamacpp_options().api)),
+            "local:model_path" => Ok(self.get_local_options().model_path),
+            "local:tokenizer_path" => Ok(self.get_local_options().tokenizer_path),
+            "local:template" => Ok(Some(self.get_local_options().template)),
+            "mock:script" => Ok(self.get_mock_options().script),
+            "context:project" => Ok(Some(self.get_context_options().project.to_string())),
+            "context:git" => Ok(Some(self.get_context_options().git.to_string())),
+            "context:manifests" => Ok(Some(self.get_context_options().manifests.to_string())),
+            "context:listing" => Ok(Some(self.get_context_options().listing.to_string())),
+            "history:enabled" => Ok(Some(self.get_history_options().enabled.to_string())),
+            "history:allow" => Ok(Some(self.get_history_options().allow.join(","))),
+            "history:deny" => Ok(Some(self.get_history_options().deny.join(","))),
+            "history:examples" => Ok(Some(self.get_history_options().examples.to_string())),
+            "examples:enabled" => Ok(Some(self.get_examples_options().enabled.to_string())),
+            "examples:count" => Ok(Some(self.get_examples_options().count.to_string())),
+            "examples:similar" => Ok(Some(self.get_examples_options().similar.to_string())),
+            "http:connect_timeout" => Ok(Some(self.get_http_options().connect_timeout.to_string())),
+            "http:read_timeout" => Ok(Some(self.get_http_options().read_timeout.to_string())),
+            "http:retries" => Ok(Some(self.get_http_options().retries.to_string())),
+            "proxy:url" => Ok(self.get_proxy_options().url),
+            "proxy:no_proxy" => Ok(self.get_proxy_options().no_proxy),
+            "tls:ca_bundle" => Ok(self.get_tls_options().ca_bundle),
+            "cache:ttl" => Ok(Some(self.get_cache_options().ttl.to_string())),
+            "budget:monthly" => Ok(self
+                .get_usage_options()
+                .monthly_budget
+                .map(|b| b.to_string())),
+            "budget:action" => Ok(Some(self.get_usage_options().budget_action)),
+            _ if key.starts_with("price:") => {
+                let (_, model) = key.split_once(':').unwrap();
+                Ok(self
+                    .get_usage_options()
+                    .prices
+                    .get(model)
+                    .map(|p| format!("{},{}", p.input, p.output)))
+            }
             _ => unimplemented!(),
-        };
+        }
     }
     pub fn delete_key(&mut self, key: &str) -> BottResult<()> {
-        return match key {
+        match key {
+            "llm:timeout" => {
+                self.llm_timeout = None;
+                self.save()?;
+                Ok(())
+            }
             "ollama:model" => {
-                self.ollama_options = None;
+                let mut options = self.get_ollama_options();
+                options.model = OllamaOptions::default().model;
+                self.ollama_options = Some(options);
                 self.save()?;
                 Ok(())
             }
             "openai:model" => {
-                self.openai_options = None;
+                let mut options = self.get_openai_options();
+                options.model = OpenaiOptions::default().model;
+                self.openai_options = Some(options);
                 self.save()?;
                 Ok(())
             }
-            "openai:api_key" => {
-                let (namespace, key) = key.split_once(":").unwrap();
+            "ollama:structured" | "ollama:temperature" | "ollama:top_p" | "ollama:max_tokens"
+            | "ollama:num_ctx" | "ollama:seed" | "ollama:stop" | "ollama:keep_alive" => {
+                let mut options = self.get_ollama_options();
+                delete_generation_option(&mut options.generation, key);
+                self.ollama_options = Some(options);
+                self.save()?;
+                Ok(())
+            }
+            "openai:structured" | "openai:temperature" | "openai:top_p" | "openai:max_tokens"
+            | "openai:seed" | "openai:stop" => {
+                let mut options = self.get_openai_options();
+                delete_generation_option(&mut options.generation, key);
+                self.openai_options = Some(options);
+                self.save()?;
+                Ok(())
+            }
+            "azure:structured" | "azure:temperature" | "azure:top_p" | "azure:max_tokens"
+            | "azure:seed" | "azure:stop" => {
+                let mut options = self.get_azure_options();
+                delete_generation_option(&mut options.generation, key);
+                self.azure_options = Some(options);
+                self.save()?;
+                Ok(())
+            }
+            "anthropic:model" => {
+                self.anthropic_options = None;
+                self.save()?;
+                Ok(())
+            }
+            "gemini:model" => {
+                self.gemini_options = None;
+                self.save()?;
+                Ok(())
+            }
+            "azure:endpoint" | "azure:deployment" | "azure:api_version" => {
+                let mut options = self.get_azure_options();
+                match key {
+                    "azure:endpoint" => options.endpoint = None,
+                    "azure:deployment" => options.deployment = None,
+                    _ => options.api_version = None,
+                }
+                self.azure_options = Some(options);
+                self.save()?;
+                Ok(())
+            }
+            "azure:response_format" => {
+                let mut options = self.get_azure_options();
+                options.response_format = None;
+                self.azure_options = Some(options);
+                self.save()?;
+                Ok(())
+            }
+            "openai:api_key" | "anthropic:api_key" | "gemini:api_key" | "azure:api_key" => {
+                let (namespace, key) = key.split_once(':').unwrap();
                 let keychain = Keychain::load(namespace);
                 Ok(keychain.delete(key)?)
             }
+            "llamacpp:url" | "llamacpp:api" => {
+                let defaults = LlamacppOptions::default();
+                let mut options = self.get_llamacpp_options();
+                match key {
+                    "llamacpp:url" => options.url = defaults.url,
+                    _ => options.api = defaults.api,
+                }
+                self.llamacpp_options = Some(options);
+                self.save()?;
+                Ok(())
+            }
+            "local:model_path" | "local:tokenizer_path" | "local:template" => {
+                let defaults = LocalOptions::default();
+                let mut options = self.get_local_options();
+                match key {
+                    "local:model_path" => options.model_path = defaults.model_path,
+                    "local:tokenizer_path" => options.tokenizer_path = defaults.tokenizer_path,
+                    _ => options.template = defaults.template,
+                }
+                self.local_options = Some(options);
+                self.save()?;
+                Ok(())
+            }
+            "mock:script" => {
+                self.mock_options = Some(MockOptions::default());
+                self.save()?;
+                Ok(())
+            }
+            "context:project" | "context:git" | "context:manifests" | "context:listing" => {
+                let defaults = ContextOptions::default();
+                let mut options = self.get_context_options();
+                match key {
+                    "context:project" => options.project = defaults.project,
+                    "context:git" => options.git = defaults.git,
+                    "context:manifests" => options.manifests = defaults.manifests,
+                    _ => options.listing = defaults.listing,
+                }
+                self.context_options = Some(options);
+                self.save()?;
+                Ok(())
+            }
+            "history:enabled" | "history:allow" | "history:deny" | "history:examples" => {
+                let defaults = HistoryOptions::default();
+                let mut options = self.get_history_options();
+                match key {
+                    "history:enabled" => options.enabled = defaults.enabled,
+                    "history:allow" => options.allow = defaults.allow,
+                    "history:deny" => options.deny = defaults.deny,
+                    _ => options.examples = defaults.examples,
+                }
+                self.history_options = Some(options);
+                self.save()?;
+                Ok(())
+            }
+            "examples:enabled" | "examples:count" | "examples:similar" => {
+                let defaults = ExamplesOptions::default();
+                let mut options = self.get_examples_options();
+                match key {
+                    "examples:enabled" => options.enabled = defaults.enabled,
+                    "examples:count" => options.count = defaults.count,
+                    _ => options.similar = defaults.similar,
+                }
+                self.examples_options = Some(options);
+                self.save()?;
+                Ok(())
+            }
+            "http:connect_timeout" | "http:read_timeout" | "http:retries" => {
+                let defaults = HttpOptions::default();
+                let mut options = self.get_http_options();
+                match key {
+                    "http:connect_timeout" => options.connect_timeout = defaults.connect_timeout,
+                    "http:read_timeout" => options.read_timeout = defaults.read_timeout,
+                    _ => options.retries = defaults.retries,
+                }
+                self.http_options = Some(options);
+                self.save()?;
+                Ok(())
+            }
+            "proxy:url" | "proxy:no_proxy" => {
+                let mut options = self.get_proxy_options();
+                match key {
+                    "proxy:url" => options.url = None,
+                    _ => options.no_proxy = None,
+                }
+                self.proxy_options = Some(options);
+                self.save()?;
+                Ok(())
+            }
+            "tls:ca_bundle" => {
+                self.tls_options = Some(TlsOptions::default());
+                self.save()?;
+                Ok(())
+            }
+            "cache:ttl" => {
+                self.cache_options = Some(CacheOptions::default());
+                self.save()?;
+                Ok(())
+            }
+            "budget:monthly" | "budget:action" => {
+                let mut options = self.get_usage_options();
+                match key {
+                    "budget:monthly" => options.monthly_budget = None,
+                    _ => options.budget_action = UsageOptions::default().budget_action,
+                }
+                self.usage_options = Some(options);
+                self.save()?;
+                Ok(())
+            }
+            _ if key.starts_with("price:") => {
+                let (_, model) = key.split_once(':').unwrap();
+                let mut options = self.get_usage_options();
+                options.prices.remove(model);
+                self.usage_options = Some(options);
+                self.save()?;
+                Ok(())
+            }
             _ => unimplemented!(),
-        };
+        }
     }
 }
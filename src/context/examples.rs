@@ -0,0 +1,274 @@
+use crate::config::{BottConfig, ExamplesOptions};
+use crate::context::rank_by_similarity;
+use crate::context::system::SystemContext;
+use crate::errors::BottError;
+use crate::result::BottResult;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const EXAMPLES_FILE: &str = "examples.yaml";
+const CURATED_EXAMPLES: &str = include_str!("examples.yaml");
+
+// A question and the command that answers it, shown to the llm so it knows what a good answer looks like
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Example {
+    pub query: String,
+    pub command: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shells: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub distros: Vec<String>,
+}
+impl Example {
+    fn applies_to(&self, system: &SystemContext) -> bool {
+        let shell = system.shell_name();
+        let distro = system.distro.to_lowercase();
+        (self.shells.is_empty() || self.shells.contains(&shell))
+            && (self.distros.is_empty()
+                || self
+                    .distros
+                    .iter()
+                    .any(|d| distro.contains(d.to_lowercase().as_str())))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExamplesContext {
+    examples: Vec<Example>,
+}
+
+fn get_examples_path() -> PathBuf {
+    BottConfig::get_dir().join(EXAMPLES_FILE)
+}
+fn parse_examples(contents: &str) -> Option<Vec<Example>> {
+    if contents.trim().is_empty() {
+        return Some(vec![]);
+    }
+    serde_yaml::from_str::<Vec<Example>>(contents).ok()
+}
+fn load_user_examples(path: &Path) -> BottResult<Vec<Example>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|c| parse_examples(c.as_str()))
+        .ok_or(BottError::ExamplesStoreErr)
+}
+// The user's examples come first, so they win ties with the curated ones
+fn load_examples(path: &Path) -> BottResult<Vec<Example>> {
+    let mut examples = load_user_examples(path)?;
+    examples.extend(parse_examples(CURATED_EXAMPLES).unwrap_or_default());
+    Ok(examples)
+}
+fn select(
+    examples: Vec<Example>,
+    options: &ExamplesOptions,
+    system: &SystemContext,
+    query: &str,
+) -> Vec<Example> {
+    let examples = examples
+        .into_iter()
+        .filter(|e| e.applies_to(system))
+        .collect::<Vec<Example>>();
+    let mut order: Vec<usize> = vec![];
+    if options.similar {
+        let queries = examples
+            .iter()
+            .map(|e| e.query.clone())
+            .collect::<Vec<String>>();
+        order = rank_by_similarity(&queries, query);
+    }
+    // unrelated examples still show the llm what an answer looks like
+    let unranked = (0..examples.len())
+        .filter(|i| !order.contains(i))
+        .collect::<Vec<usize>>();
+    order
+        .into_iter()
+        .chain(unranked)
+        .take(options.count)
+        .map(|i| examples[i].clone())
+        .collect()
+}
+
+impl ExamplesContext {
+    pub fn detect(
+        options: &ExamplesOptions,
+        system: &SystemContext,
+        query: &str,
+    ) -> BottResult<Option<Self>> {
+        if !options.enabled || options.count == 0 {
+            return Ok(None);
+        }
+        let examples = select(load_examples(&get_examples_path())?, options, system, query);
+        if examples.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self { examples }))
+    }
+    pub fn get_examples(&self) -> &[Example] {
+        &self.examples
+    }
+    // Answers are shown the way the llm is asked to answer, as json when it is structured
+    pub fn render(&self, structured: bool) -> String {
+        let mut lines = vec![String::from(match structured {
+            true => "Examples of questions and the answers that suit this system, showing only the command field:",
+            false => "Examples of questions and the answers that suit this system:",
+        })];
+        for example in &self.examples {
+            lines.push(format!("Q: {}", example.query));
+            lines.push(match structured {
+                true => format!("A: {}", json!({ "command": example.command })),
+                false => format!("A: ```bash\n{}\n```", example.command),
+            });
+        }
+        lines.join("\n")
+    }
+}
+
+// Saves an example of the user's own next to the config
+pub fn add(example: Example) -> BottResult<()> {
+    let path = get_examples_path();
+    let mut examples = load_user_examples(&path)?;
+    examples.retain(|e| *e != example);
+    examples.push(example);
+    if let Some(dir) = path.parent() {
+        if fs::create_dir_all(dir).is_err() {
+            return Err(BottError::ExamplesStoreErr);
+        }
+    }
+    match fs::write(path, serde_yaml::to_string(&examples).unwrap()) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(BottError::ExamplesStoreErr),
+    }
+}
+// The examples that apply to this shell and distro, the user's own first
+pub fn list(system: &SystemContext) -> BottResult<String> {
+    let examples = load_examples(&get_examples_path())?
+        .into_iter()
+        .filter(|e| e.applies_to(system))
+        .map(|e| format!("{}\n  $ {}", e.query, e.command))
+        .collect::<Vec<String>>();
+    if examples.is_empty() {
+        return Ok(String::from("No examples for this system"));
+    }
+    Ok(examples.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn system(distro: &str, shell: &str) -> SystemContext {
+        SystemContext {
+            distro: distro.to_string(),
+            kernel: String::new(),
+            arch: String::from("x86_64"),
+            shell: shell.to_string(),
+            shell_version: None,
+            package_managers: vec![],
+            coreutils: String::from("GNU"),
+            container: None,
+            wsl: false,
+        }
+    }
+    fn options(count: usize, similar: bool) -> ExamplesOptions {
+        ExamplesOptions {
+            enabled: true,
+            count,
+            similar,
+        }
+    }
+    fn example(query: &str, command: &str) -> Example {
+        Example {
+            query: query.to_string(),
+            command: command.to_string(),
+            shells: vec![],
+            distros: vec![],
+        }
+    }
+    fn commands(examples: &[Example]) -> Vec<&str> {
+        examples.iter().map(|e| e.command.as_str()).collect()
+    }
+
+    #[test]
+    fn parses_the_curated_examples() {
+        let examples = parse_examples(CURATED_EXAMPLES).unwrap();
+        assert!(examples.len() > 10);
+        assert!(examples
+            .iter()
+            .all(|e| !e.query.is_empty() && !e.command.is_empty()));
+    }
+
+    #[test]
+    fn selects_examples_for_the_system() {
+        let examples = parse_examples(CURATED_EXAMPLES).unwrap();
+        let fedora = select(
+            examples.clone(),
+            &options(1, true),
+            &system("Fedora Linux 39", "/bin/bash"),
+            "install htop",
+        );
+        assert_eq!(commands(&fedora), vec!["sudo dnf install -y ripgrep"]);
+        let fish = select(
+            examples,
+            &options(1, true),
+            &system("macOS 14.1", "/opt/homebrew/bin/fish"),
+            "put ~/go/bin on the path",
+        );
+        assert_eq!(commands(&fish), vec!["set -gx PATH $HOME/bin $PATH"]);
+    }
+
+    #[test]
+    fn fills_up_with_unrelated_examples() {
+        let examples = vec![
+            example("list files", "ls"),
+            example("show my ip address", "ip addr"),
+            example("show running processes", "ps aux"),
+        ];
+        let debian = system("Debian GNU/Linux 12 (bookworm)", "/bin/zsh");
+        let query = "which processes are running";
+        assert_eq!(
+            commands(&select(examples.clone(), &options(2, true), &debian, query)),
+            vec!["ps aux", "ls"]
+        );
+        assert_eq!(
+            commands(&select(examples, &options(2, false), &debian, query)),
+            vec!["ls", "ip addr"]
+        );
+    }
+
+    #[test]
+    fn renders_answers_in_the_answer_format() {
+        let context = ExamplesContext {
+            examples: vec![example("show \"hidden\" files", "ls -a")],
+        };
+        assert!(context
+            .render(false)
+            .ends_with("Q: show \"hidden\" files\nA: ```bash\nls -a\n```"));
+        assert!(context
+            .render(true)
+            .ends_with("\nA: {\"command\":\"ls -a\"}"));
+    }
+
+    #[test]
+    fn reports_corrupt_user_examples() {
+        let dir = std::env::temp_dir().join(format!("bott-examples-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(EXAMPLES_FILE);
+        assert!(load_examples(&path).unwrap().len() > 10);
+        fs::write(&path, "- query: list files\n  command: [ls\n").unwrap();
+        assert!(matches!(
+            load_examples(&path),
+            Err(BottError::ExamplesStoreErr)
+        ));
+        fs::write(&path, "- query: list files\n  command: ls\n").unwrap();
+        assert_eq!(
+            load_examples(&path).unwrap()[0],
+            example("list files", "ls")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,116 @@
+pub mod examples;
+pub mod history;
+pub mod project;
+pub mod system;
+pub mod tools;
+
+use crate::config::BottConfig;
+use crate::context::examples::ExamplesContext;
+use crate::context::history::HistoryContext;
+use crate::context::project::ProjectContext;
+use crate::context::system::SystemContext;
+use crate::context::tools::ToolInventory;
+use crate::result::BottResult;
+use std::collections::{HashMap, HashSet};
+use std::process::Command;
+
+// Everything bott knows about the user's environment that can go into a prompt
+#[derive(Debug, Clone)]
+pub struct PromptContext {
+    pub system: SystemContext,
+    pub project: Option<ProjectContext>,
+    pub tools: Option<ToolInventory>,
+    pub history: Option<HistoryContext>,
+    pub examples: Option<ExamplesContext>,
+    // whether the llm answers in json, examples are shown the same way
+    pub structured: bool,
+}
+impl PromptContext {
+    pub fn collect(config: &BottConfig, query: &str) -> BottResult<Self> {
+        let system = SystemContext::detect();
+        let examples = ExamplesContext::detect(&config.get_examples_options(), &system, query)?;
+        Ok(Self {
+            system,
+            project: ProjectContext::detect(&config.get_context_options()),
+            tools: ToolInventory::load().ok(),
+            history: HistoryContext::detect(&config.get_history_options(), query),
+            examples,
+            structured: false,
+        })
+    }
+    pub fn with_structured(self, structured: bool) -> Self {
+        Self { structured, ..self }
+    }
+    pub fn render(&self) -> String {
+        let mut sections = vec![self.system.render()];
+        if let Some(project) = &self.project {
+            sections.push(project.render());
+        }
+        if let Some(tools) = &self.tools {
+            sections.push(tools.render());
+        }
+        if let Some(history) = &self.history {
+            sections.push(history.render());
+        }
+        if let Some(examples) = &self.examples {
+            sections.push(examples.render(self.structured));
+        }
+        sections.join("\n")
+    }
+}
+
+fn terms(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| t.len() > 1)
+        .map(|t| t.to_lowercase())
+        .collect()
+}
+// Positions of the documents that share words with the query, best matches first.
+// Rare words count more, earlier documents win ties.
+pub fn rank_by_similarity(documents: &[String], query: &str) -> Vec<usize> {
+    let indexed = documents
+        .iter()
+        .map(|d| terms(d))
+        .collect::<Vec<HashSet<String>>>();
+    let mut document_frequency: HashMap<&String, usize> = HashMap::new();
+    for document_terms in &indexed {
+        for term in document_terms {
+            *document_frequency.entry(term).or_insert(0) += 1;
+        }
+    }
+    let query_terms = terms(query);
+    let total = indexed.len() as f64;
+    let mut scored = indexed
+        .iter()
+        .enumerate()
+        .map(|(position, document_terms)| {
+            let score = document_terms
+                .intersection(&query_terms)
+                .map(|t| (total / *document_frequency.get(t).unwrap_or(&1) as f64).ln() + 1.0)
+                .sum::<f64>();
+            (position, score)
+        })
+        .filter(|(_, score)| *score > 0.0)
+        .collect::<Vec<(usize, f64)>>();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    scored.into_iter().map(|(position, _)| position).collect()
+}
+
+// Runs a command and returns the first line it printed, if it succeeded
+fn first_line_of(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    // some programs, like older shells, print their version on stderr
+    let text = if stdout.trim().is_empty() {
+        stderr
+    } else {
+        stdout
+    };
+    text.lines()
+        .map(|l| l.trim().to_string())
+        .find(|l| !l.is_empty())
+}
@@ -0,0 +1,324 @@
+use crate::config::ContextOptions;
+use regex::Regex;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const MANIFESTS: [&str; 16] = [
+    "Cargo.toml",
+    "package.json",
+    "Makefile",
+    "pyproject.toml",
+    "requirements.txt",
+    "setup.py",
+    "go.mod",
+    "pom.xml",
+    "build.gradle",
+    "CMakeLists.txt",
+    "Gemfile",
+    "composer.json",
+    "Dockerfile",
+    "docker-compose.yml",
+    "justfile",
+    "deno.json",
+];
+const MAX_LISTING_ENTRIES: usize = 30;
+const MAX_MANIFEST_DETAILS: usize = 10;
+
+#[derive(Debug, Clone)]
+pub struct GitSummary {
+    branch: String,
+    staged: usize,
+    modified: usize,
+    untracked: usize,
+}
+#[derive(Debug, Clone)]
+pub struct ProjectContext {
+    cwd: PathBuf,
+    git: Option<GitSummary>,
+    manifests: Vec<String>,
+    listing: Vec<String>,
+}
+
+fn git(cwd: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(cwd)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn detect_git(cwd: &Path) -> Option<GitSummary> {
+    let branch = git(cwd, &["rev-parse", "--abbrev-ref", "HEAD"])?;
+    let status = git(cwd, &["status", "--porcelain"]).unwrap_or_default();
+    Some(summarize_status(branch.as_str(), status.as_str()))
+}
+
+// Counts the entries of `git status --porcelain`, a file can be both staged and modified
+fn summarize_status(branch: &str, status: &str) -> GitSummary {
+    let mut summary = GitSummary {
+        branch: branch.trim().to_string(),
+        staged: 0,
+        modified: 0,
+        untracked: 0,
+    };
+    for line in status.lines() {
+        let mut flags = line.chars();
+        let index = flags.next().unwrap_or(' ');
+        let worktree = flags.next().unwrap_or(' ');
+        if index == '?' {
+            summary.untracked += 1;
+            continue;
+        }
+        if index != ' ' {
+            summary.staged += 1;
+        }
+        if worktree != ' ' {
+            summary.modified += 1;
+        }
+    }
+    summary
+}
+
+fn limited(mut items: Vec<String>) -> String {
+    let extra = items.len().saturating_sub(MAX_MANIFEST_DETAILS);
+    items.truncate(MAX_MANIFEST_DETAILS);
+    if extra > 0 {
+        items.push(format!("{} more", extra));
+    }
+    items.join(", ")
+}
+
+// Pulls out the parts of a manifest that tell the model how this project is built and run
+fn describe_manifest(path: &Path, name: &str) -> String {
+    let contents = fs::read_to_string(path).unwrap_or_default();
+    match name {
+        "package.json" => {
+            let json: serde_json::Value = serde_json::from_str(&contents).unwrap_or_default();
+            let scripts = json["scripts"]
+                .as_object()
+                .map(|s| s.keys().cloned().collect::<Vec<String>>())
+                .unwrap_or_default();
+            if scripts.is_empty() {
+                return String::from(name);
+            }
+            format!("{} (scripts: {})", name, limited(scripts))
+        }
+        "Makefile" | "justfile" => {
+            let re = Regex::new(r"(?m)^([A-Za-z0-9][A-Za-z0-9_.-]*)\s*:([^=]|$)").unwrap();
+            let targets = re
+                .captures_iter(&contents)
+                .map(|c| c[1].to_string())
+                .collect::<Vec<String>>();
+            if targets.is_empty() {
+                return String::from(name);
+            }
+            format!("{} (targets: {})", name, limited(targets))
+        }
+        "Cargo.toml" if contents.contains("[workspace]") => format!("{} (workspace)", name),
+        "pyproject.toml" => {
+            let re = Regex::new(r"(?m)^\[tool\.([A-Za-z0-9_-]+)").unwrap();
+            let mut tools = re
+                .captures_iter(&contents)
+                .map(|c| c[1].to_string())
+                .collect::<Vec<String>>();
+            tools.dedup();
+            if tools.is_empty() {
+                return String::from(name);
+            }
+            format!("{} (tools: {})", name, limited(tools))
+        }
+        _ => String::from(name),
+    }
+}
+
+fn detect_manifests(cwd: &Path) -> Vec<String> {
+    MANIFESTS
+        .iter()
+        .map(|name| (cwd.join(name), name))
+        .filter(|(path, _)| path.is_file())
+        .map(|(path, name)| describe_manifest(&path, name))
+        .collect()
+}
+
+fn detect_listing(cwd: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(cwd) else {
+        return vec![];
+    };
+    let mut listing = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') {
+                return None;
+            }
+            match e.file_type() {
+                Ok(t) if t.is_dir() => Some(format!("{}/", name)),
+                _ => Some(name),
+            }
+        })
+        .collect::<Vec<String>>();
+    listing.sort();
+    let extra = listing.len().saturating_sub(MAX_LISTING_ENTRIES);
+    listing.truncate(MAX_LISTING_ENTRIES);
+    if extra > 0 {
+        listing.push(format!("... and {} more", extra));
+    }
+    listing
+}
+
+impl ProjectContext {
+    pub fn detect(options: &ContextOptions) -> Option<Self> {
+        if !options.project {
+            return None;
+        }
+        Some(Self::detect_in(options, env::current_dir().ok()?))
+    }
+    fn detect_in(options: &ContextOptions, cwd: PathBuf) -> Self {
+        Self {
+            git: if options.git { detect_git(&cwd) } else { None },
+            manifests: if options.manifests {
+                detect_manifests(&cwd)
+            } else {
+                vec![]
+            },
+            listing: if options.listing {
+                detect_listing(&cwd)
+            } else {
+                vec![]
+            },
+            cwd,
+        }
+    }
+    pub fn render(&self) -> String {
+        let mut lines = vec![format!("Current directory: {}", self.cwd.display())];
+        if let Some(git) = &self.git {
+            lines.push(format!(
+                "Git: on branch {}, {} staged, {} modified, {} untracked",
+                git.branch, git.staged, git.modified, git.untracked
+            ));
+        }
+        if !self.manifests.is_empty() {
+            lines.push(format!("Project files: {}", self.manifests.join("; ")));
+        }
+        if !self.listing.is_empty() {
+            lines.push(format!("Directory contents: {}", self.listing.join(" ")));
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_project() -> PathBuf {
+        let dir = env::temp_dir().join(format!("bott-project-{}", rand::random::<u64>()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("Cargo.toml"), "[workspace]\nmembers = [\"cli\"]\n").unwrap();
+        fs::write(
+            dir.join("package.json"),
+            r#"{"scripts": {"build": "tsc", "test": "jest"}}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("Makefile"),
+            "CC := gcc\nbuild: src/main.c\n\t$(CC) -o app src/main.c\n.PHONY: build\nclean:\n\trm app\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("pyproject.toml"),
+            "[tool.ruff]\nline-length = 100\n[tool.pytest.ini_options]\n",
+        )
+        .unwrap();
+        fs::write(dir.join(".env"), "SECRET=1").unwrap();
+        dir
+    }
+    fn git_in(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=bott", "-c", "user.email=bott@localhost"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    #[test]
+    fn describes_manifests_and_listing() {
+        let dir = temp_project();
+        assert_eq!(
+            detect_manifests(&dir),
+            vec![
+                "Cargo.toml (workspace)",
+                "package.json (scripts: build, test)",
+                "Makefile (targets: build, clean)",
+                "pyproject.toml (tools: ruff, pytest)",
+            ]
+        );
+        // hidden files like .env stay out of the prompt
+        assert_eq!(
+            detect_listing(&dir),
+            vec![
+                "Cargo.toml",
+                "Makefile",
+                "package.json",
+                "pyproject.toml",
+                "src/"
+            ]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn summarizes_git_status() {
+        let summary = summarize_status(
+            "main\n",
+            "M  staged.rs\nMM both.rs\n M changed.rs\n?? new.rs\n",
+        );
+        assert_eq!(summary.branch, "main");
+        assert_eq!(summary.staged, 2);
+        assert_eq!(summary.modified, 2);
+        assert_eq!(summary.untracked, 1);
+
+        let dir = temp_project();
+        assert!(detect_git(&dir).is_none());
+        git_in(&dir, &["init", "-q", "-b", "feature"]);
+        git_in(&dir, &["add", "Cargo.toml", "Makefile"]);
+        git_in(&dir, &["commit", "-q", "-m", "init"]);
+        fs::write(dir.join("Makefile"), "all:\n").unwrap();
+        git_in(&dir, &["add", "package.json"]);
+        let git = detect_git(&dir).unwrap();
+        assert_eq!(
+            (git.branch.as_str(), git.staged, git.modified, git.untracked),
+            ("feature", 1, 1, 2)
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn renders_only_enabled_sections() {
+        let dir = temp_project();
+        let options = ContextOptions {
+            project: true,
+            git: false,
+            manifests: true,
+            listing: false,
+        };
+        let rendered = ProjectContext::detect_in(&options, dir.clone()).render();
+        assert_eq!(
+            rendered,
+            format!(
+                "Current directory: {}\nProject files: Cargo.toml (workspace); package.json (scripts: build, test); Makefile (targets: build, clean); pyproject.toml (tools: ruff, pytest)",
+                dir.display()
+            )
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
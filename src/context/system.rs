@@ -0,0 +1,334 @@
+use crate::context::first_line_of;
+use regex::Regex;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+const KNOWN_SHELLS: [&str; 8] = ["bash", "zsh", "fish", "sh", "dash", "ksh", "tcsh", "nu"];
+const PACKAGE_MANAGERS: [(&str, &str); 13] = [
+    ("apt-get", "apt"),
+    ("dnf", "dnf"),
+    ("yum", "yum"),
+    ("pacman", "pacman"),
+    ("zypper", "zypper"),
+    ("apk", "apk"),
+    ("emerge", "portage"),
+    ("xbps-install", "xbps"),
+    ("nix-env", "nix"),
+    ("brew", "homebrew"),
+    ("port", "macports"),
+    ("pkg", "pkg"),
+    ("winget.exe", "winget"),
+];
+
+#[derive(Debug, Clone)]
+pub struct SystemContext {
+    pub distro: String,
+    pub kernel: String,
+    pub arch: String,
+    pub shell: String,
+    pub shell_version: Option<String>,
+    pub package_managers: Vec<String>,
+    pub coreutils: String,
+    pub container: Option<String>,
+    pub wsl: bool,
+}
+
+fn detect_package_managers(path: &str) -> Vec<String> {
+    PACKAGE_MANAGERS
+        .iter()
+        .filter(|(program, _)| env::split_paths(path).any(|dir| dir.join(program).is_file()))
+        .map(|(_, name)| name.to_string())
+        .collect()
+}
+
+fn os_release_value(contents: &str, key: &str) -> Option<String> {
+    contents.lines().find_map(|line| {
+        let (k, v) = line.split_once('=')?;
+        if k.trim() != key {
+            return None;
+        }
+        Some(v.trim().trim_matches('"').trim_matches('\'').to_string())
+    })
+}
+
+fn parse_os_release(contents: &str) -> Option<String> {
+    if let Some(name) = os_release_value(contents, "PRETTY_NAME") {
+        return Some(name);
+    }
+    let name = os_release_value(contents, "NAME").unwrap_or_default();
+    let version = os_release_value(contents, "VERSION_ID").unwrap_or_default();
+    let distro = format!("{} {}", name, version).trim().to_string();
+    if distro.is_empty() {
+        return None;
+    }
+    Some(distro)
+}
+
+fn detect_distro() -> String {
+    if let Some(distro) = fs::read_to_string("/etc/os-release")
+        .ok()
+        .and_then(|c| parse_os_release(c.as_str()))
+    {
+        return distro;
+    }
+    if let Some(name) = first_line_of("sw_vers", &["-productName"]) {
+        let version = first_line_of("sw_vers", &["-productVersion"]).unwrap_or_default();
+        return format!("{} {}", name, version).trim().to_string();
+    }
+    first_line_of("uname", &["-s"]).unwrap_or(String::from(env::consts::OS))
+}
+
+fn detect_shell() -> String {
+    // the parent process is the shell that invoked us, which may differ from the login shell
+    let parent = format!("/proc/{}/exe", std::os::unix::process::parent_id());
+    if let Ok(exe) = fs::read_link(parent) {
+        let name = exe
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if KNOWN_SHELLS.contains(&name.as_str()) {
+            return exe.to_string_lossy().to_string();
+        }
+    }
+    env::var("SHELL").unwrap_or(String::from("sh"))
+}
+
+fn parse_shell_version(line: &str) -> Option<String> {
+    let re = Regex::new(r"\d+(\.\d+)+").unwrap();
+    re.find(line).map(|m| m.as_str().to_string())
+}
+
+fn detect_shell_version(shell: &str) -> Option<String> {
+    parse_shell_version(first_line_of(shell, &["--version"])?.as_str())
+}
+
+// What `ls --version` says about the coreutils, BSD ls has no --version at all
+fn parse_coreutils(version: &str) -> Option<String> {
+    if version.contains("GNU") {
+        return Some(String::from("GNU"));
+    }
+    if version.contains("uutils") {
+        return Some(String::from("uutils"));
+    }
+    None
+}
+
+fn detect_coreutils() -> String {
+    if let Some(coreutils) =
+        first_line_of("ls", &["--version"]).and_then(|v| parse_coreutils(v.as_str()))
+    {
+        return coreutils;
+    }
+    let busybox = env::split_paths(env::var("PATH").unwrap_or_default().as_str())
+        .map(|dir| dir.join("ls"))
+        .find(|p| p.is_file())
+        .and_then(|p| fs::canonicalize(p).ok())
+        .is_some_and(|p| p.ends_with("busybox"));
+    if busybox {
+        return String::from("BusyBox");
+    }
+    String::from("BSD")
+}
+
+fn detect_container() -> Option<String> {
+    if Path::new("/.dockerenv").exists() {
+        return Some(String::from("docker"));
+    }
+    if Path::new("/run/.containerenv").exists() {
+        return Some(String::from("podman"));
+    }
+    if let Some(container) = fs::read_to_string("/proc/1/cgroup")
+        .ok()
+        .and_then(|c| parse_cgroup(c.as_str()))
+    {
+        return Some(container);
+    }
+    env::var("container").ok().filter(|c| !c.is_empty())
+}
+
+fn parse_cgroup(cgroup: &str) -> Option<String> {
+    [
+        ("kubepods", "kubernetes"),
+        ("docker", "docker"),
+        ("lxc", "lxc"),
+    ]
+    .iter()
+    .find(|(marker, _)| cgroup.contains(marker))
+    .map(|(_, name)| name.to_string())
+}
+
+fn is_wsl_kernel(release: &str) -> bool {
+    release.to_lowercase().contains("microsoft")
+}
+
+fn detect_wsl() -> bool {
+    if env::var("WSL_DISTRO_NAME").is_ok() {
+        return true;
+    }
+    fs::read_to_string("/proc/sys/kernel/osrelease")
+        .map(|r| is_wsl_kernel(r.as_str()))
+        .unwrap_or(false)
+}
+
+impl SystemContext {
+    pub fn detect() -> Self {
+        let shell = detect_shell();
+        let shell_version = detect_shell_version(shell.as_str());
+        Self {
+            distro: detect_distro(),
+            kernel: first_line_of("uname", &["-sr"]).unwrap_or_default(),
+            arch: String::from(env::consts::ARCH),
+            shell,
+            shell_version,
+            package_managers: detect_package_managers(
+                env::var("PATH").unwrap_or_default().as_str(),
+            ),
+            coreutils: detect_coreutils(),
+            container: detect_container(),
+            wsl: detect_wsl(),
+        }
+    }
+    pub fn shell_name(&self) -> String {
+        Path::new(self.shell.as_str())
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or(self.shell.clone())
+    }
+    pub fn render(&self) -> String {
+        let mut lines = vec![format!("Operating system: {}", self.distro)];
+        if !self.kernel.is_empty() {
+            lines.push(format!("Kernel: {}", self.kernel));
+        }
+        lines.push(format!("Architecture: {}", self.arch));
+        match &self.shell_version {
+            Some(v) => lines.push(format!("Shell: {} {}", self.shell_name(), v)),
+            None => lines.push(format!("Shell: {}", self.shell_name())),
+        }
+        if !self.package_managers.is_empty() {
+            lines.push(format!(
+                "Package manager: {}",
+                self.package_managers.join(", ")
+            ));
+        }
+        lines.push(format!("Coreutils: {}", self.coreutils));
+        if let Some(container) = &self.container {
+            lines.push(format!("Running inside a {} container", container));
+        }
+        if self.wsl {
+            lines.push(String::from(
+                "Running under Windows Subsystem for Linux (WSL)",
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UBUNTU_OS_RELEASE: &str = r#"PRETTY_NAME="Ubuntu 22.04.3 LTS"
+NAME="Ubuntu"
+VERSION_ID="22.04"
+VERSION="22.04.3 LTS (Jammy Jellyfish)"
+ID=ubuntu
+ID_LIKE=debian
+"#;
+
+    #[test]
+    fn parses_os_release() {
+        assert_eq!(
+            parse_os_release(UBUNTU_OS_RELEASE),
+            Some(String::from("Ubuntu 22.04.3 LTS"))
+        );
+        // without a pretty name the name and version are joined
+        assert_eq!(
+            parse_os_release("NAME='Alpine Linux'\nVERSION_ID=3.19.0\n"),
+            Some(String::from("Alpine Linux 3.19.0"))
+        );
+        assert_eq!(
+            parse_os_release("NAME=NixOS\n"),
+            Some(String::from("NixOS"))
+        );
+        assert_eq!(parse_os_release("ID=unknown\n"), None);
+    }
+
+    #[test]
+    fn parses_shell_versions() {
+        for (line, version) in [
+            (
+                "GNU bash, version 5.2.15(1)-release (x86_64-pc-linux-gnu)",
+                "5.2.15",
+            ),
+            ("zsh 5.9 (x86_64-apple-darwin23.0)", "5.9"),
+            ("fish, version 3.6.1", "3.6.1"),
+            ("0.86.0", "0.86.0"),
+        ] {
+            assert_eq!(parse_shell_version(line), Some(String::from(version)));
+        }
+        assert_eq!(parse_shell_version("dash: illegal option --"), None);
+    }
+
+    #[test]
+    fn detects_coreutils_containers_and_wsl() {
+        assert_eq!(
+            parse_coreutils("ls (GNU coreutils) 9.1"),
+            Some(String::from("GNU"))
+        );
+        assert_eq!(
+            parse_coreutils("ls (uutils coreutils) 0.0.23"),
+            Some(String::from("uutils"))
+        );
+        assert_eq!(parse_coreutils("ls: unrecognized option"), None);
+
+        assert_eq!(
+            parse_cgroup("0::/kubepods/besteffort/pod1234/abcd"),
+            Some(String::from("kubernetes"))
+        );
+        assert_eq!(
+            parse_cgroup("12:memory:/docker/0123456789abcdef"),
+            Some(String::from("docker"))
+        );
+        assert_eq!(parse_cgroup("0::/init.scope"), None);
+
+        assert!(is_wsl_kernel("5.15.133.1-microsoft-standard-WSL2"));
+        assert!(is_wsl_kernel("4.4.0-19041-Microsoft"));
+        assert!(!is_wsl_kernel("6.5.0-14-generic"));
+    }
+
+    #[test]
+    fn detects_package_managers_on_path() {
+        let dir = env::temp_dir().join(format!("bott-path-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        for program in ["dnf", "brew", "unrelated"] {
+            fs::write(dir.join(program), "").unwrap();
+        }
+        let path = env::join_paths([dir.clone(), dir.join("missing")]).unwrap();
+        assert_eq!(
+            detect_package_managers(path.to_str().unwrap()),
+            vec!["dnf", "homebrew"]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn renders_the_system() {
+        let system = SystemContext {
+            distro: String::from("Debian GNU/Linux 12 (bookworm)"),
+            kernel: String::from("Linux 6.1.0"),
+            arch: String::from("aarch64"),
+            shell: String::from("/usr/bin/fish"),
+            shell_version: None,
+            package_managers: vec![String::from("apt"), String::from("nix")],
+            coreutils: String::from("GNU"),
+            container: Some(String::from("docker")),
+            wsl: true,
+        };
+        assert_eq!(system.shell_name(), "fish");
+        assert_eq!(
+            system.render(),
+            "Operating system: Debian GNU/Linux 12 (bookworm)\nKernel: Linux 6.1.0\nArchitecture: aarch64\nShell: fish\nPackage manager: apt, nix\nCoreutils: GNU\nRunning inside a docker container\nRunning under Windows Subsystem for Linux (WSL)"
+        );
+    }
+}
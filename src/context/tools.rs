@@ -0,0 +1,348 @@
+use crate::config::BottConfig;
+use crate::errors::BottError;
+use crate::result::BottResult;
+use regex::Regex;
+use serde_derive::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const CACHE_FILE: &str = "tools.json";
+const VERSION_TIMEOUT: Duration = Duration::from_secs(2);
+// all the tools are asked at once, this bounds the whole refresh
+const VERSIONS_TIMEOUT: Duration = Duration::from_secs(3);
+// (binary, flag that prints its version). Tools without a safe flag are only checked for presence
+const TOOLS: [(&str, Option<&str>); 56] = [
+    ("git", Some("--version")),
+    ("rg", Some("--version")),
+    ("grep", Some("--version")),
+    ("fd", Some("--version")),
+    ("fdfind", Some("--version")),
+    ("find", Some("--version")),
+    ("jq", Some("--version")),
+    ("yq", Some("--version")),
+    ("fzf", Some("--version")),
+    ("bat", Some("--version")),
+    ("batcat", Some("--version")),
+    ("eza", Some("--version")),
+    ("exa", Some("--version")),
+    ("tree", Some("--version")),
+    ("sd", Some("--version")),
+    ("awk", Some("--version")),
+    ("gawk", Some("--version")),
+    ("sed", Some("--version")),
+    ("gsed", Some("--version")),
+    ("perl", Some("--version")),
+    ("python3", Some("--version")),
+    ("node", Some("--version")),
+    ("npm", Some("--version")),
+    ("cargo", Some("--version")),
+    ("go", Some("version")),
+    ("java", Some("-version")),
+    ("make", Some("--version")),
+    ("curl", Some("--version")),
+    ("wget", Some("--version")),
+    ("http", Some("--version")),
+    ("xh", Some("--version")),
+    ("ssh", Some("-V")),
+    ("rsync", Some("--version")),
+    ("tar", Some("--version")),
+    ("zip", Some("-v")),
+    ("unzip", Some("-v")),
+    ("7z", None),
+    ("docker", Some("--version")),
+    ("podman", Some("--version")),
+    ("kubectl", None),
+    ("helm", Some("version")),
+    ("terraform", Some("--version")),
+    ("aws", Some("--version")),
+    ("gcloud", None),
+    ("az", None),
+    ("gh", Some("--version")),
+    ("tmux", Some("-V")),
+    ("htop", Some("--version")),
+    ("lsof", Some("-v")),
+    ("ss", Some("--version")),
+    ("ip", Some("-V")),
+    ("ffmpeg", Some("-version")),
+    ("sqlite3", Some("--version")),
+    ("xclip", None),
+    ("wl-copy", None),
+    ("pbcopy", None),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tool {
+    name: String,
+    version: Option<String>,
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolInventory {
+    refreshed_at: u64,
+    installed: Vec<Tool>,
+    missing: Vec<String>,
+}
+
+fn find_in_path(path: &str, program: &str) -> Option<PathBuf> {
+    env::split_paths(path)
+        .map(|dir| dir.join(program))
+        .find(|p| p.is_file())
+}
+
+// Asks a tool for its version, giving up on tools that hang instead of exiting
+fn probe_version(path: &PathBuf, flag: &str) -> Option<String> {
+    let mut child = Command::new(path)
+        .arg(flag)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+    let started = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            Ok(None) if started.elapsed() < VERSION_TIMEOUT => sleep(Duration::from_millis(10)),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+        }
+    }
+    let output = child.wait_with_output().ok()?;
+    parse_version(
+        format!(
+            "{}\n{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .as_str(),
+    )
+}
+
+// The first version number on the first line a tool printed
+fn parse_version(text: &str) -> Option<String> {
+    let line = text.lines().find(|l| !l.trim().is_empty())?;
+    let re = Regex::new(r"\d+(\.\d+)+").unwrap();
+    re.find(line).map(|m| m.as_str().to_string())
+}
+
+// Asks every tool at once, the ones that haven't answered by the deadline get no version
+fn probe_versions(tools: &[(usize, PathBuf, &'static str)]) -> Vec<(usize, String)> {
+    let (sender, receiver) = mpsc::channel();
+    for (position, path, flag) in tools.iter().cloned() {
+        let sender = sender.clone();
+        thread::spawn(move || {
+            if let Some(version) = probe_version(&path, flag) {
+                let _ = sender.send((position, version));
+            }
+        });
+    }
+    drop(sender);
+    let deadline = Instant::now() + VERSIONS_TIMEOUT;
+    let mut versions: Vec<(usize, String)> = vec![];
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        match receiver.recv_timeout(remaining) {
+            Ok(version) => versions.push(version),
+            Err(_) => break,
+        }
+    }
+    versions
+}
+
+fn get_cache_path() -> PathBuf {
+    BottConfig::get_dir().join(CACHE_FILE)
+}
+
+impl ToolInventory {
+    // Finds the tools on $PATH, running them for their version only when asked to
+    fn detect_in(path: &str, versions: bool) -> Self {
+        let mut installed: Vec<Tool> = vec![];
+        let mut missing: Vec<String> = vec![];
+        let mut probes: Vec<(usize, PathBuf, &'static str)> = vec![];
+        for (name, flag) in TOOLS {
+            match find_in_path(path, name) {
+                Some(found) => {
+                    if let Some(flag) = flag.filter(|_| versions) {
+                        probes.push((installed.len(), found, flag));
+                    }
+                    installed.push(Tool {
+                        name: name.to_string(),
+                        version: None,
+                    });
+                }
+                None => missing.push(name.to_string()),
+            }
+        }
+        for (position, version) in probe_versions(&probes) {
+            installed[position].version = Some(version);
+        }
+        let refreshed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Self {
+            refreshed_at,
+            installed,
+            missing,
+        }
+    }
+    pub fn detect() -> Self {
+        ToolInventory::detect_in(env::var("PATH").unwrap_or_default().as_str(), true)
+    }
+    pub fn refresh() -> BottResult<Self> {
+        let inventory = ToolInventory::detect();
+        inventory.save()?;
+        Ok(inventory)
+    }
+    // Reads the cached inventory. The first time bott runs it only looks at $PATH,
+    // running dozens of tools would hold up the first query; `context refresh` adds versions
+    pub fn load() -> BottResult<Self> {
+        let path = get_cache_path();
+        if let Some(inventory) = ToolInventory::load_from(&path) {
+            return Ok(inventory);
+        }
+        let inventory =
+            ToolInventory::detect_in(env::var("PATH").unwrap_or_default().as_str(), false);
+        inventory.save_to(&path)?;
+        Ok(inventory)
+    }
+    fn load_from(path: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(path).ok()?;
+        serde_json::from_str::<ToolInventory>(contents.as_str()).ok()
+    }
+    pub fn save(&self) -> BottResult<()> {
+        self.save_to(&get_cache_path())
+    }
+    fn save_to(&self, path: &Path) -> BottResult<()> {
+        if let Some(dir) = path.parent() {
+            if fs::create_dir_all(dir).is_err() {
+                return Err(BottError::ContextStoreErr);
+            }
+        }
+        let contents = serde_json::to_string(self).unwrap();
+        match fs::write(path, contents) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(BottError::ContextStoreErr),
+        }
+    }
+    // Installed tools with their version when they told it
+    pub fn get_installed(&self) -> Vec<String> {
+        self.installed
+            .iter()
+            .map(|t| match &t.version {
+                Some(v) => format!("{} {}", t.name, v),
+                None => t.name.clone(),
+            })
+            .collect()
+    }
+    pub fn render(&self) -> String {
+        let mut lines = vec![format!(
+            "Installed tools: {}",
+            self.get_installed().join(", ")
+        )];
+        if !self.missing.is_empty() {
+            lines.push(format!("Not installed: {}", self.missing.join(", ")));
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn temp_dir() -> PathBuf {
+        let dir = env::temp_dir().join(format!("bott-tools-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    fn script(dir: &Path, name: &str, body: &str) {
+        let path = dir.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn parses_versions() {
+        for (text, version) in [
+            ("git version 2.43.0", Some("2.43.0")),
+            (
+                "\nopenjdk version \"17.0.2\" 2022-01-18\nOpenJDK Runtime",
+                Some("17.0.2"),
+            ),
+            (
+                "OpenSSH_9.6p1 Ubuntu-3ubuntu13, OpenSSL 3.0.13",
+                Some("9.6"),
+            ),
+            ("jq-1.7.1", Some("1.7.1")),
+            ("tmux next-3.4", Some("3.4")),
+            ("usage: tree [-acdfghilnpqrstuvxACDFJQNSUX]", None),
+            ("", None),
+        ] {
+            assert_eq!(parse_version(text), version.map(String::from), "{}", text);
+        }
+    }
+
+    #[test]
+    fn probes_versions_only_when_asked() {
+        let dir = temp_dir();
+        script(&dir, "git", "echo git version 2.43.0");
+        script(&dir, "jq", "echo jq-1.7.1 >&2");
+        // a tool that hangs gets no version instead of holding up the refresh
+        script(&dir, "make", "sleep 10");
+        let path = dir.to_string_lossy().to_string();
+
+        let inventory = ToolInventory::detect_in(path.as_str(), false);
+        assert_eq!(inventory.get_installed(), vec!["git", "jq", "make"]);
+        assert_eq!(inventory.missing.len(), TOOLS.len() - 3);
+
+        let started = Instant::now();
+        let inventory = ToolInventory::detect_in(path.as_str(), true);
+        assert!(started.elapsed() < VERSIONS_TIMEOUT + Duration::from_secs(1));
+        assert_eq!(
+            inventory.get_installed(),
+            vec!["git 2.43.0", "jq 1.7.1", "make"]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn renders_and_caches_the_inventory() {
+        let inventory = ToolInventory {
+            refreshed_at: 1700000000,
+            installed: vec![
+                Tool {
+                    name: String::from("rg"),
+                    version: Some(String::from("14.1.0")),
+                },
+                Tool {
+                    name: String::from("xclip"),
+                    version: None,
+                },
+            ],
+            missing: vec![String::from("fd"), String::from("jq")],
+        };
+        assert_eq!(
+            inventory.render(),
+            "Installed tools: rg 14.1.0, xclip\nNot installed: fd, jq"
+        );
+
+        let dir = temp_dir();
+        let path = dir.join("cache").join(CACHE_FILE);
+        assert!(ToolInventory::load_from(&path).is_none());
+        inventory.save_to(&path).unwrap();
+        let cached = ToolInventory::load_from(&path).unwrap();
+        assert_eq!(cached.refreshed_at, inventory.refreshed_at);
+        assert_eq!(cached.render(), inventory.render());
+        fs::write(&path, "{not json").unwrap();
+        assert!(ToolInventory::load_from(&path).is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
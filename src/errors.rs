@@ -1,28 +1,112 @@
 use std::fmt;
+use std::time::Duration;
 
+#[derive(Debug)]
+pub enum BottHttpError {
+    Timeout,
+    Connect(String),
+    RateLimited(Option<u64>),
+    ServerError(u16),
+    Cancelled,
+    Request(String),
+    InvalidProxy(String),
+    InvalidCaBundle(String),
+}
 #[derive(Debug)]
 pub enum BottOllamaError {
     NotRunning,
     InvalidResponse,
     ModelUnavailable(String),
+    PullFailed(String),
     UnableToGetResponse,
     UnknownError(String),
 }
 #[derive(Debug)]
 pub enum BottOpenaiError {
+    MissingApiKey,
+    ApiError(String),
+    InvalidResponse,
+    ModelUnavailable(String),
+    UnableToGetResponse,
+}
+#[derive(Debug)]
+pub enum BottAnthropicError {
+    MissingApiKey,
+    ApiError(String),
+    InvalidResponse,
     UnableToGetResponse,
 }
 #[derive(Debug)]
+pub enum BottGeminiError {
+    MissingApiKey,
+    ApiError(String),
+    InvalidResponse,
+    UnableToGetResponse,
+}
+#[derive(Debug)]
+pub enum BottAzureError {
+    MissingConfig(String),
+}
+#[derive(Debug)]
+pub enum BottLlamacppError {
+    NotRunning(String),
+    ApiError(String),
+    InvalidResponse,
+    UnableToGetResponse,
+}
+// which variants are used depends on the local-inference feature
+#[derive(Debug)]
+#[allow(dead_code)]
+pub enum BottLocalError {
+    NotCompiled,
+    MissingModel,
+    LoadError(String),
+    InferenceError(String),
+    UnableToGetResponse,
+}
+#[derive(Debug)]
+pub enum BottMockError {
+    MissingScript,
+    InvalidScript(String),
+    NoMatch(String),
+    UnableToGetResponse,
+}
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum BottError {
     ConfigPathErr,
     ConfigLoadErr,
     ConfigStoreErr,
+    ConfigValueErr(String),
     KeychainLoadErr,
     KeychainGetErr,
     KeychainSetErr,
     KeychainDeleteErr,
+    ContextStoreErr,
+    UsageStoreErr,
+    CacheStoreErr,
+    ExamplesStoreErr,
+    PromptStoreErr,
+    // the prompt's name and what is wrong with its template
+    PromptTemplateErr(String, String),
+    EditorErr(String),
+    HttpErr(BottHttpError),
     OllamaErr(BottOllamaError),
     OpenaiErr(BottOpenaiError),
+    AnthropicErr(BottAnthropicError),
+    GeminiErr(BottGeminiError),
+    AzureErr(BottAzureError),
+    LlamacppErr(BottLlamacppError),
+    LocalErr(BottLocalError),
+    MockErr(BottMockError),
+    UnknownLlm(String),
+    LlmTimeout(Duration),
+    NoLlmAvailable(Vec<(String, String)>),
+    ModelsUnsupported(String),
+    ModelNotFound(String),
+    NoModels(String),
+    // spent and budget in dollars
+    BudgetExceeded(f64, f64),
 }
 impl fmt::Display for BottError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -30,10 +114,47 @@ impl fmt::Display for BottError {
             BottError::ConfigPathErr => write!(f, "Unable to get config path"),
             BottError::ConfigLoadErr => write!(f, "Unable to get config"),
             BottError::ConfigStoreErr => write!(f, "Unable to store config"),
+            BottError::ConfigValueErr(k) => write!(f, "Invalid value for key {}", k),
             BottError::KeychainLoadErr => write!(f, "Unable to load keychain"),
             BottError::KeychainGetErr => write!(f, "Unable to get key from keychain"),
             BottError::KeychainSetErr => write!(f, "Unable to set key in keychain"),
             BottError::KeychainDeleteErr => write!(f, "Unable delete key from keychain"),
+            BottError::ContextStoreErr => write!(f, "Unable to store context"),
+            BottError::UsageStoreErr => write!(f, "Unable to store usage"),
+            BottError::CacheStoreErr => write!(f, "Unable to store cached answers"),
+            BottError::ExamplesStoreErr => {
+                write!(f, "Unable to read or store examples in ~/.bott/examples.yaml")
+            }
+            BottError::PromptStoreErr => write!(f, "Unable to store prompt templates"),
+            BottError::PromptTemplateErr(name, s) => write!(
+                f,
+                "The {} prompt template is invalid: {}\nFix it with `bott! prompt edit -n {}` or do `bott! prompt reset -n {}`",
+                name, s, name, name
+            ),
+            BottError::EditorErr(s) => write!(f, "Unable to edit with {}, set $EDITOR", s),
+            // Http errors
+            BottError::HttpErr(BottHttpError::Timeout) => write!(
+                f,
+                "Request timed out. Allow more time with `bott! config set -k http:read_timeout -v SECONDS`"
+            ),
+            BottError::HttpErr(BottHttpError::Connect(s)) => write!(f, "Unable to connect: {}", s),
+            BottError::HttpErr(BottHttpError::RateLimited(Some(s))) => {
+                write!(f, "Rate limited, try again in {} seconds", s)
+            }
+            BottError::HttpErr(BottHttpError::RateLimited(None)) => {
+                write!(f, "Rate limited, try again later")
+            }
+            BottError::HttpErr(BottHttpError::ServerError(s)) => {
+                write!(f, "Server error {}, try again later", s)
+            }
+            BottError::HttpErr(BottHttpError::Cancelled) => write!(f, "Cancelled"),
+            BottError::HttpErr(BottHttpError::Request(s)) => write!(f, "Request failed: {}", s),
+            BottError::HttpErr(BottHttpError::InvalidProxy(s)) => {
+                write!(f, "Invalid proxy {}", s)
+            }
+            BottError::HttpErr(BottHttpError::InvalidCaBundle(s)) => {
+                write!(f, "Unable to read certificates from {}", s)
+            }
             // Ollama errors
             BottError::OllamaErr(BottOllamaError::NotRunning) => write!(f, "Ollama not running?"),
             BottError::OllamaErr(BottOllamaError::InvalidResponse) => {
@@ -42,16 +163,130 @@ impl fmt::Display for BottError {
             BottError::OllamaErr(BottOllamaError::ModelUnavailable(s)) => {
                 write!(f, "model not installed. Do `ollama pull {}`", s)
             }
+            BottError::OllamaErr(BottOllamaError::PullFailed(s)) => {
+                write!(f, "Unable to pull model: {}", s)
+            }
             BottError::OllamaErr(BottOllamaError::UnableToGetResponse) => {
                 write!(f, "Ollama sent invalid response")
             }
             BottError::OllamaErr(BottOllamaError::UnknownError(s)) => {
                 write!(f, "Unexpected error: {}", s)
             }
+            BottError::UnknownLlm(s) => write!(f, "Unknown llm {}", s),
+            BottError::LlmTimeout(d) => write!(f, "No answer after {} seconds", d.as_secs_f64()),
+            BottError::NoLlmAvailable(failures) => {
+                write!(f, "None of the configured llms could answer:")?;
+                for (llm, reason) in failures {
+                    write!(f, "\n  {}: {}", llm, reason)?;
+                }
+                Ok(())
+            }
+            BottError::ModelsUnsupported(s) => {
+                write!(f, "{} doesn't let you choose a model from bott", s)
+            }
+            BottError::ModelNotFound(s) => write!(f, "Model {} not found", s),
+            BottError::NoModels(s) => write!(f, "No {} models found", s),
+            BottError::BudgetExceeded(spent, budget) => write!(
+                f,
+                "The monthly budget of ${:.2} is spent (${:.2} so far), raise it with budget:monthly",
+                budget, spent
+            ),
             // Openai errors
+            BottError::OpenaiErr(BottOpenaiError::MissingApiKey) => write!(
+                f,
+                "Openai api key not set. Do `bott! config set -k openai:api_key -v YOUR_API_KEY`"
+            ),
+            BottError::OpenaiErr(BottOpenaiError::ApiError(s)) => {
+                write!(f, "Openai returned an error: {}", s)
+            }
+            BottError::OpenaiErr(BottOpenaiError::InvalidResponse) => {
+                write!(f, "Openai sent invalid response")
+            }
+            BottError::OpenaiErr(BottOpenaiError::ModelUnavailable(s)) => write!(
+                f,
+                "model {} not available. Do `bott! models list -l openai` to see yours",
+                s
+            ),
             BottError::OpenaiErr(BottOpenaiError::UnableToGetResponse) => {
                 write!(f, "Openai sent invalid response")
             }
+            // Anthropic errors
+            BottError::AnthropicErr(BottAnthropicError::MissingApiKey) => write!(
+                f,
+                "Anthropic api key not set. Do `bott! config set -k anthropic:api_key -v YOUR_API_KEY`"
+            ),
+            BottError::AnthropicErr(BottAnthropicError::ApiError(s)) => {
+                write!(f, "Anthropic returned an error: {}", s)
+            }
+            BottError::AnthropicErr(BottAnthropicError::InvalidResponse) => {
+                write!(f, "Anthropic sent invalid response")
+            }
+            BottError::AnthropicErr(BottAnthropicError::UnableToGetResponse) => {
+                write!(f, "Anthropic sent invalid response")
+            }
+            // Gemini errors
+            BottError::GeminiErr(BottGeminiError::MissingApiKey) => write!(
+                f,
+                "Gemini api key not set. Do `bott! config set -k gemini:api_key -v YOUR_API_KEY`"
+            ),
+            BottError::GeminiErr(BottGeminiError::ApiError(s)) => {
+                write!(f, "Gemini returned an error: {}", s)
+            }
+            BottError::GeminiErr(BottGeminiError::InvalidResponse) => {
+                write!(f, "Gemini sent invalid response")
+            }
+            BottError::GeminiErr(BottGeminiError::UnableToGetResponse) => {
+                write!(f, "Gemini sent invalid response")
+            }
+            // Azure errors
+            BottError::AzureErr(BottAzureError::MissingConfig(k)) => {
+                write!(f, "{} not set. Do `bott! config set -k {} -v VALUE`", k, k)
+            }
+            // llama.cpp errors
+            BottError::LlamacppErr(BottLlamacppError::NotRunning(url)) => {
+                write!(f, "llama.cpp server not running at {}?", url)
+            }
+            BottError::LlamacppErr(BottLlamacppError::ApiError(s)) => {
+                write!(f, "llama.cpp server returned an error: {}", s)
+            }
+            BottError::LlamacppErr(BottLlamacppError::InvalidResponse) => {
+                write!(f, "llama.cpp server sent invalid response")
+            }
+            BottError::LlamacppErr(BottLlamacppError::UnableToGetResponse) => {
+                write!(f, "llama.cpp server sent invalid response")
+            }
+            // Local inference errors
+            BottError::LocalErr(BottLocalError::NotCompiled) => write!(
+                f,
+                "bott was built without local inference. Rebuild with `--features local-inference`"
+            ),
+            BottError::LocalErr(BottLocalError::MissingModel) => write!(
+                f,
+                "Local model not set. Do `bott! config set -k local:model_path -v PATH_TO_GGUF`"
+            ),
+            BottError::LocalErr(BottLocalError::LoadError(s)) => {
+                write!(f, "Unable to load local model: {}", s)
+            }
+            BottError::LocalErr(BottLocalError::InferenceError(s)) => {
+                write!(f, "Local inference failed: {}", s)
+            }
+            BottError::LocalErr(BottLocalError::UnableToGetResponse) => {
+                write!(f, "Local model sent invalid response")
+            }
+            // Mock errors
+            BottError::MockErr(BottMockError::MissingScript) => write!(
+                f,
+                "Mock script not set. Do `bott! config set -k mock:script -v PATH_TO_SCRIPT`"
+            ),
+            BottError::MockErr(BottMockError::InvalidScript(s)) => {
+                write!(f, "Unable to read mock script: {}", s)
+            }
+            BottError::MockErr(BottMockError::NoMatch(s)) => {
+                write!(f, "No scripted response matches \"{}\"", s)
+            }
+            BottError::MockErr(BottMockError::UnableToGetResponse) => {
+                write!(f, "Scripted response has no command")
+            }
         }
     }
 }
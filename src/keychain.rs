@@ -18,10 +18,7 @@ impl Keychain {
         let current_user = get_user_by_uid(get_current_uid()).unwrap();
         let user = current_user.name().to_string_lossy().to_string();
         let namespace = String::from(namespace);
-        Self {
-            user: user,
-            namespace: namespace,
-        }
+        Self { user, namespace }
     }
     fn operate(
         &self,
@@ -36,7 +33,7 @@ impl Keychain {
             Ok(e) => e,
             Err(_) => return Err(BottError::KeychainLoadErr),
         };
-        return match operation {
+        match operation {
             KeychainOperation::Get => {
                 let password = match entry.get_password() {
                     Ok(s) => s,
@@ -47,15 +44,15 @@ impl Keychain {
             KeychainOperation::Set => {
                 let val = value.unwrap();
                 match entry.set_password(val) {
-                    Ok(_) => return Ok(None),
-                    Err(_) => return Err(BottError::KeychainSetErr),
+                    Ok(_) => Ok(None),
+                    Err(_) => Err(BottError::KeychainSetErr),
                 }
             }
             KeychainOperation::Delete => match entry.delete_password() {
-                Ok(_) => return Ok(None),
-                Err(_) => return Err(BottError::KeychainDeleteErr),
+                Ok(_) => Ok(None),
+                Err(_) => Err(BottError::KeychainDeleteErr),
             },
-        };
+        }
     }
     pub fn get(&self, key: &str) -> BottResult<Option<String>> {
         let password = self.operate(key, None, KeychainOperation::Get)?;
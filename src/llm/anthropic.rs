@@ -0,0 +1,450 @@
+use crate::config::BottConfig;
+use crate::context::PromptContext;
+use crate::errors::{BottAnthropicError, BottError};
+use crate::llm::conversation::{Conversation, Message};
+use crate::llm::http::HttpPolicy;
+use crate::llm::models::ModelInfo;
+use crate::llm::suggestion::Suggestion;
+use crate::llm::usage::Usage;
+use crate::llm::{
+    get_debug_prompt, get_debug_system_prompt, get_query_system_prompt, GenerateOutput,
+};
+use crate::result::BottResult;
+use serde_derive::{Deserialize, Serialize};
+use std::env;
+
+pub const ANTHROPIC_URL: &str = "https://api.anthropic.com";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const MAX_TOKENS: u32 = 1024;
+// the default page only holds 20 models
+const MODELS_LIMIT: u32 = 1000;
+
+#[derive(Serialize, Debug)]
+pub struct MessagesRequest {
+    model: String,
+    max_tokens: u32,
+    system: String,
+    messages: Vec<Message>,
+    stream: bool,
+}
+#[derive(Deserialize, Debug)]
+struct ErrorDetail {
+    message: String,
+}
+#[derive(Deserialize, Debug)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+#[derive(Deserialize, Debug)]
+struct ModelObject {
+    id: String,
+}
+#[derive(Deserialize, Debug)]
+struct ModelList {
+    data: Vec<ModelObject>,
+}
+#[derive(Deserialize, Debug)]
+struct TextDelta {
+    #[serde(default)]
+    text: String,
+}
+#[derive(Deserialize, Debug, Default)]
+struct TokenCounts {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+}
+#[derive(Deserialize, Debug)]
+struct MessageStart {
+    #[serde(default)]
+    usage: TokenCounts,
+}
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    // the prompt tokens come first, the answer's running total with each message delta
+    MessageStart {
+        message: MessageStart,
+    },
+    MessageDelta {
+        usage: TokenCounts,
+    },
+    ContentBlockDelta {
+        delta: TextDelta,
+    },
+    Error {
+        error: ErrorDetail,
+    },
+    #[serde(other)]
+    Other,
+}
+
+pub async fn get_model() -> BottResult<String> {
+    let config = BottConfig::load()?;
+    Ok(config.get_anthropic_options().model)
+}
+pub async fn check_health() -> BottResult<()> {
+    let mut config = BottConfig::load()?;
+    match config.get_key("anthropic:api_key") {
+        Ok(Some(_)) => Ok(()),
+        _ => Err(BottError::AnthropicErr(BottAnthropicError::MissingApiKey)),
+    }
+}
+pub async fn generate(
+    query: &str,
+    prompt_context: &PromptContext,
+    debug: bool,
+) -> BottResult<GenerateOutput> {
+    let context: Conversation;
+    let prompt: String;
+    let system_prompt: String;
+    if debug {
+        let input = env::var("bott_last_run_executed_code").unwrap_or(String::from(""));
+        let output = env::var("bott_last_run_output").unwrap_or(String::from(""));
+        prompt = get_debug_prompt(input.as_str(), output.as_str())?;
+        context = Conversation::default();
+        system_prompt = get_debug_system_prompt(prompt_context)?;
+    } else {
+        prompt = String::from(query);
+        context = Conversation::load();
+        system_prompt = get_query_system_prompt(prompt_context)?;
+    }
+    generate_with_context(prompt, system_prompt, context, debug).await
+}
+pub async fn generate_with_context(
+    prompt: String,
+    system_prompt: String,
+    context: Conversation,
+    debug: bool,
+) -> BottResult<GenerateOutput> {
+    let model: String = get_model().await?;
+    let mut config = BottConfig::load()?;
+    let api_key = match config.get_key("anthropic:api_key") {
+        Ok(Some(k)) => k,
+        _ => return Err(BottError::AnthropicErr(BottAnthropicError::MissingApiKey)),
+    };
+    let policy = HttpPolicy::load()?;
+    complete(
+        &policy,
+        ANTHROPIC_URL,
+        api_key.as_str(),
+        model,
+        prompt,
+        system_prompt,
+        context,
+        debug,
+    )
+    .await
+}
+
+// Takes the next complete event off the stream. Events end with a blank line, and are only
+// decoded once whole so characters and line breaks split across network chunks survive
+fn next_event(buffer: &mut Vec<u8>) -> Option<String> {
+    let (position, length) = buffer.windows(2).enumerate().find_map(|(i, w)| match w {
+        b"\n\n" => Some((i, 2)),
+        b"\n\r" if buffer.get(i + 2) == Some(&b'\n') => Some((i, 3)),
+        _ => None,
+    })?;
+    let event = String::from_utf8_lossy(&buffer[..position]).to_string();
+    buffer.drain(..position + length);
+    Some(event)
+}
+
+// Collects the text and token counts of a server-sent event stream from the Messages API
+fn read_stream_events(
+    buffer: &mut Vec<u8>,
+    answer: &mut String,
+    usage: &mut Usage,
+) -> BottResult<()> {
+    while let Some(event) = next_event(buffer) {
+        for line in event.lines() {
+            let Some(data) = line.strip_prefix("data:") else {
+                continue;
+            };
+            match serde_json::from_str::<StreamEvent>(data.trim()) {
+                Ok(StreamEvent::MessageStart { message }) => {
+                    usage.prompt_tokens = message.usage.input_tokens
+                }
+                Ok(StreamEvent::MessageDelta { usage: counts }) => {
+                    usage.completion_tokens = counts.output_tokens
+                }
+                Ok(StreamEvent::ContentBlockDelta { delta }) => answer.push_str(&delta.text),
+                Ok(StreamEvent::Error { error }) => {
+                    return Err(BottError::AnthropicErr(BottAnthropicError::ApiError(
+                        error.message,
+                    )));
+                }
+                Ok(StreamEvent::Other) => {}
+                Err(_) => return Err(BottError::AnthropicErr(BottAnthropicError::InvalidResponse)),
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn send_messages(
+    policy: &HttpPolicy,
+    base_url: &str,
+    api_key: &str,
+    request: &MessagesRequest,
+) -> BottResult<(String, Usage)> {
+    let client = policy.client()?;
+    let mut response = policy
+        .send(
+            client
+                .post(format!("{}/v1/messages", base_url))
+                .header("x-api-key", api_key)
+                .header("anthropic-version", ANTHROPIC_VERSION)
+                .json(request),
+        )
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let message = match response.json::<ErrorResponse>().await {
+            Ok(e) => e.error.message,
+            Err(_) => status.to_string(),
+        };
+        return Err(BottError::AnthropicErr(BottAnthropicError::ApiError(
+            message,
+        )));
+    }
+    let mut buffer: Vec<u8> = vec![];
+    let mut answer = String::new();
+    let mut usage = Usage {
+        model: request.model.clone(),
+        ..Usage::default()
+    };
+    while let Some(chunk) = policy.chunk(&mut response).await? {
+        buffer.extend_from_slice(&chunk);
+        read_stream_events(&mut buffer, &mut answer, &mut usage)?;
+    }
+    buffer.extend_from_slice(b"\n\n");
+    read_stream_events(&mut buffer, &mut answer, &mut usage)?;
+    Ok((answer, usage))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn complete(
+    policy: &HttpPolicy,
+    base_url: &str,
+    api_key: &str,
+    model: String,
+    prompt: String,
+    system_prompt: String,
+    mut context: Conversation,
+    debug: bool,
+) -> BottResult<GenerateOutput> {
+    context.push(Message::user(prompt));
+    let request = MessagesRequest {
+        model,
+        max_tokens: MAX_TOKENS,
+        system: system_prompt,
+        messages: context.messages.clone(),
+        stream: true,
+    };
+    let (content, usage) = send_messages(policy, base_url, api_key, &request).await?;
+    context.push(Message::assistant(content.clone()));
+    if debug {
+        return Ok(GenerateOutput::text(content, context).with_usage(usage));
+    }
+    match Suggestion::parse(content.as_str()) {
+        Some(s) => Ok(GenerateOutput::suggestion(s, context).with_usage(usage)),
+        None => Err(BottError::AnthropicErr(
+            BottAnthropicError::UnableToGetResponse,
+        )),
+    }
+}
+pub async fn list_models(
+    policy: &HttpPolicy,
+    base_url: &str,
+    api_key: &str,
+) -> BottResult<Vec<ModelInfo>> {
+    let client = policy.client()?;
+    let response = policy
+        .send(
+            client
+                .get(format!("{}/v1/models", base_url))
+                .query(&[("limit", MODELS_LIMIT)])
+                .header("x-api-key", api_key)
+                .header("anthropic-version", ANTHROPIC_VERSION),
+        )
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let message = match response.json::<ErrorResponse>().await {
+            Ok(e) => e.error.message,
+            Err(_) => status.to_string(),
+        };
+        return Err(BottError::AnthropicErr(BottAnthropicError::ApiError(
+            message,
+        )));
+    }
+    match response.json::<ModelList>().await {
+        Ok(b) => Ok(b
+            .data
+            .into_iter()
+            .map(|m| ModelInfo {
+                name: m.id,
+                ..ModelInfo::default()
+            })
+            .collect::<Vec<ModelInfo>>()),
+        Err(_) => Err(BottError::AnthropicErr(BottAnthropicError::InvalidResponse)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::test_server::TestServer;
+
+    fn stream_body(chunks: &[&str]) -> String {
+        let mut body = String::from(
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":25,\"output_tokens\":1}}}\n\n",
+        );
+        for chunk in chunks {
+            let delta = serde_json::json!({
+                "type": "content_block_delta",
+                "index": 0,
+                "delta": {"type": "text_delta", "text": chunk},
+            });
+            body.push_str(&format!("event: content_block_delta\ndata: {}\n\n", delta));
+        }
+        body.push_str("event: message_delta\ndata: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{\"output_tokens\":12}}\n\n");
+        body.push_str("event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n");
+        body
+    }
+
+    #[test]
+    fn decodes_events_split_across_chunks() {
+        let body = stream_body(&["```bash\nls ~/Téléchargements/日本\n```"]).replace('\n', "\r\n");
+        let bytes = body.as_bytes();
+        let mut buffer: Vec<u8> = vec![];
+        let mut answer = String::new();
+        let mut usage = Usage::default();
+        // one byte at a time splits every multi-byte character and every \r\n
+        for byte in bytes {
+            buffer.push(*byte);
+            read_stream_events(&mut buffer, &mut answer, &mut usage).unwrap();
+        }
+        assert!(buffer.is_empty());
+        assert_eq!(answer, "```bash\nls ~/Téléchargements/日本\n```");
+        assert_eq!(usage.prompt_tokens, 25);
+        assert_eq!(usage.completion_tokens, 12);
+    }
+
+    #[tokio::test]
+    async fn extracts_command_from_stream() {
+        let body = stream_body(&["Here you go:\n```bash\n", "ls -la", "\n```"]);
+        let server = TestServer::start(200, "text/event-stream", &body).await;
+        let output = complete(
+            &HttpPolicy::default(),
+            &server.url,
+            "test-key",
+            String::from("claude-test"),
+            String::from("list files"),
+            String::from("be helpful"),
+            Conversation::default(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.answer, "ls -la");
+        assert_eq!(output.context.messages.len(), 2);
+        assert_eq!(output.context.messages[1].role, "assistant");
+        assert_eq!(
+            output.usage,
+            Usage {
+                model: String::from("claude-test"),
+                prompt_tokens: 25,
+                completion_tokens: 12,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_system_prompt_and_history() {
+        let body = stream_body(&["```bash\ngit status\n```"]);
+        let server = TestServer::start(200, "text/event-stream", &body).await;
+        let history = Conversation::new(vec![
+            Message::user(String::from("show branches")),
+            Message::assistant(String::from("```bash\ngit branch\n```")),
+        ]);
+        complete(
+            &HttpPolicy::default(),
+            &server.url,
+            "test-key",
+            String::from("claude-test"),
+            String::from("and the status?"),
+            String::from("be helpful"),
+            history,
+            false,
+        )
+        .await
+        .unwrap();
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].request_line.starts_with("POST /v1/messages"));
+        assert_eq!(requests[0].header("x-api-key").unwrap(), "test-key");
+        assert_eq!(
+            requests[0].header("anthropic-version").unwrap(),
+            ANTHROPIC_VERSION
+        );
+        let json = requests[0].json();
+        assert_eq!(json["system"], "be helpful");
+        assert_eq!(json["stream"], true);
+        let roles = json["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["role"].as_str().unwrap().to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(roles, vec!["user", "assistant", "user"]);
+    }
+
+    #[tokio::test]
+    async fn surfaces_api_errors() {
+        let body = r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#;
+        let server = TestServer::start(401, "application/json", body).await;
+        let result = complete(
+            &HttpPolicy::default(),
+            &server.url,
+            "bad-key",
+            String::from("claude-test"),
+            String::from("list files"),
+            String::from("be helpful"),
+            Conversation::default(),
+            false,
+        )
+        .await;
+        match result {
+            Err(BottError::AnthropicErr(BottAnthropicError::ApiError(m))) => {
+                assert_eq!(m, "invalid x-api-key")
+            }
+            _ => panic!("expected an api error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn fails_without_code_block() {
+        let body = stream_body(&["I can only help with shell commands."]);
+        let server = TestServer::start(200, "text/event-stream", &body).await;
+        let result = complete(
+            &HttpPolicy::default(),
+            &server.url,
+            "test-key",
+            String::from("claude-test"),
+            String::from("tell me a joke"),
+            String::from("be helpful"),
+            Conversation::default(),
+            false,
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(BottError::AnthropicErr(
+                BottAnthropicError::UnableToGetResponse
+            ))
+        ));
+    }
+}
@@ -0,0 +1,155 @@
+use crate::config::BottConfig;
+use crate::context::PromptContext;
+use crate::errors::{BottAzureError, BottError};
+use crate::llm::conversation::Conversation;
+use crate::llm::http::HttpPolicy;
+use crate::llm::openai::chat;
+use crate::llm::{
+    get_debug_prompt, get_debug_system_prompt, get_query_system_prompt, GenerateOutput,
+};
+use crate::result::BottResult;
+use async_openai::config::AzureConfig;
+use std::env;
+
+fn get_azure_config(
+    endpoint: String,
+    deployment: &str,
+    api_version: String,
+    api_key: String,
+) -> AzureConfig {
+    AzureConfig::new()
+        .with_api_base(endpoint)
+        .with_deployment_id(deployment)
+        .with_api_version(api_version)
+        .with_api_key(api_key)
+}
+fn get_required_key(config: &mut BottConfig, key: &str) -> BottResult<String> {
+    match config.get_key(key) {
+        Ok(Some(v)) if !v.is_empty() => Ok(v),
+        _ => Err(BottError::AzureErr(BottAzureError::MissingConfig(
+            key.to_string(),
+        ))),
+    }
+}
+pub async fn check_health() -> BottResult<()> {
+    let mut config = BottConfig::load()?;
+    for key in [
+        "azure:endpoint",
+        "azure:deployment",
+        "azure:api_version",
+        "azure:api_key",
+    ] {
+        get_required_key(&mut config, key)?;
+    }
+    Ok(())
+}
+pub async fn generate(
+    query: &str,
+    prompt_context: &PromptContext,
+    debug: bool,
+) -> BottResult<GenerateOutput> {
+    let context: Conversation;
+    let prompt: String;
+    let system_prompt: String;
+    if debug {
+        let input = env::var("bott_last_run_executed_code").unwrap_or(String::from(""));
+        let output = env::var("bott_last_run_output").unwrap_or(String::from(""));
+        prompt = get_debug_prompt(input.as_str(), output.as_str())?;
+        context = Conversation::default();
+        system_prompt = get_debug_system_prompt(prompt_context)?;
+    } else {
+        prompt = String::from(query);
+        context = Conversation::load();
+        system_prompt = get_query_system_prompt(prompt_context)?;
+    }
+    generate_with_context(prompt, system_prompt, context, debug).await
+}
+pub async fn generate_with_context(
+    prompt: String,
+    system_prompt: String,
+    context: Conversation,
+    debug: bool,
+) -> BottResult<GenerateOutput> {
+    let mut config = BottConfig::load()?;
+    let endpoint = get_required_key(&mut config, "azure:endpoint")?;
+    let deployment = get_required_key(&mut config, "azure:deployment")?;
+    let api_version = get_required_key(&mut config, "azure:api_version")?;
+    let api_key = get_required_key(&mut config, "azure:api_key")?;
+    let azure_config = get_azure_config(endpoint, &deployment, api_version, api_key);
+    let generation = config.get_azure_options().generation;
+    let response_format = config.get_key("azure:response_format")?.unwrap_or_default();
+    let policy = HttpPolicy::load()?;
+    // the deployment decides the model, azure ignores the model in the request
+    chat(
+        &policy,
+        &azure_config,
+        deployment,
+        &generation,
+        response_format.as_str(),
+        prompt,
+        system_prompt,
+        context,
+        debug,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GenerationOptions;
+    use crate::llm::test_server::TestServer;
+
+    #[tokio::test]
+    async fn calls_the_deployment() {
+        let body = serde_json::json!({
+            "id": "chatcmpl-test",
+            "object": "chat.completion",
+            "created": 1700000000,
+            "model": "gpt-4o",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "```bash\nls -la\n```"},
+                "finish_reason": "stop",
+            }],
+        });
+        let server = TestServer::start(200, "application/json", &body.to_string()).await;
+        let config = get_azure_config(
+            server.url.clone(),
+            "my-deployment",
+            String::from("2024-02-01"),
+            String::from("test-key"),
+        );
+        let generation = GenerationOptions {
+            structured: Some(true),
+            temperature: Some(0.2),
+            max_tokens: Some(200),
+            ..GenerationOptions::default()
+        };
+        let output = chat(
+            &HttpPolicy::default(),
+            &config,
+            String::from("my-deployment"),
+            &generation,
+            "json_object",
+            String::from("list files"),
+            String::from("be helpful"),
+            Conversation::default(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.answer, "ls -la");
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].request_line.starts_with(
+            "POST /openai/deployments/my-deployment/chat/completions?api-version=2024-02-01 "
+        ));
+        assert_eq!(requests[0].header("api-key").unwrap(), "test-key");
+        assert!(requests[0].header("authorization").is_none());
+        let json = requests[0].json();
+        assert_eq!(json["temperature"].as_f64().unwrap() as f32, 0.2);
+        assert_eq!(json["max_tokens"], 200);
+        assert_eq!(json["response_format"]["type"], "json_object");
+    }
+}
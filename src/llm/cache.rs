@@ -0,0 +1,316 @@
+use crate::config::BottConfig;
+use crate::errors::BottError;
+use crate::llm::conversation::Conversation;
+use crate::llm::ollama::format_size;
+use crate::llm::suggestion::Suggestion;
+use crate::llm::{
+    GenerateOutput, LLM_ANTHROPIC, LLM_AZURE, LLM_GEMINI, LLM_LLAMACPP, LLM_LOCAL, LLM_MOCK,
+    LLM_OLLAMA, LLM_OPENAI,
+};
+use crate::result::BottResult;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const CACHE_DIR: &str = "cache";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CacheEntry {
+    created_at: u64,
+    hits: u64,
+    llm: String,
+    answer: String,
+    suggestion: Option<Suggestion>,
+    context: Conversation,
+}
+// An answer read from the cache, with how old it is in seconds
+pub struct CacheHit {
+    pub llm: String,
+    pub output: GenerateOutput,
+    pub age: u64,
+}
+pub struct CacheStats {
+    pub entries: usize,
+    pub expired: usize,
+    pub hits: u64,
+    pub size: u64,
+    pub ttl: u64,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+// Everything about an llm that changes its answers: the model and its parameters
+fn get_model_settings(config: &mut BottConfig, llm: &str) -> String {
+    match llm {
+        LLM_OLLAMA => serde_json::to_string(&config.get_ollama_options()).unwrap(),
+        LLM_OPENAI => serde_json::to_string(&config.get_openai_options()).unwrap(),
+        LLM_ANTHROPIC => config.get_anthropic_options().model,
+        LLM_GEMINI => config.get_gemini_options().model,
+        LLM_AZURE => serde_json::to_string(&config.get_azure_options()).unwrap(),
+        LLM_LLAMACPP => serde_json::to_string(&config.get_llamacpp_options()).unwrap(),
+        LLM_LOCAL => serde_json::to_string(&config.get_local_options()).unwrap(),
+        // the script is the model, editing it changes the answers
+        LLM_MOCK => config
+            .get_mock_options()
+            .script
+            .and_then(|p| fs::read_to_string(p).ok())
+            .unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+pub struct ResponseCache {
+    dir: PathBuf,
+    ttl: u64,
+}
+impl ResponseCache {
+    pub fn from_config(config: &BottConfig) -> Self {
+        Self {
+            dir: BottConfig::get_dir().join(CACHE_DIR),
+            ttl: config.get_cache_options().ttl as u64,
+        }
+    }
+    pub fn is_enabled(&self) -> bool {
+        self.ttl > 0
+    }
+    // A hash of everything the answer depends on, the system prompt carries the directory context
+    pub fn get_key(
+        config: &mut BottConfig,
+        llm: &str,
+        system_prompt: &str,
+        history: &Conversation,
+        query: &str,
+    ) -> String {
+        let mut hasher = Sha256::new();
+        for part in [
+            llm,
+            get_model_settings(config, llm).as_str(),
+            system_prompt,
+            serde_json::to_string(history).unwrap().as_str(),
+            query,
+        ] {
+            hasher.update(part.as_bytes());
+            // keeps parts from running into each other
+            hasher.update([0]);
+        }
+        format!("{:x}", hasher.finalize())
+    }
+    fn get_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+    fn read_entry(path: &PathBuf) -> Option<CacheEntry> {
+        let contents = fs::read_to_string(path).ok()?;
+        serde_json::from_str::<CacheEntry>(contents.as_str()).ok()
+    }
+    fn write_entry(&self, key: &str, entry: &CacheEntry) -> BottResult<()> {
+        if fs::create_dir_all(&self.dir).is_err() {
+            return Err(BottError::CacheStoreErr);
+        }
+        match fs::write(self.get_path(key), serde_json::to_string(entry).unwrap()) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(BottError::CacheStoreErr),
+        }
+    }
+    pub fn get(&self, key: &str) -> Option<CacheHit> {
+        let path = self.get_path(key);
+        let mut entry = ResponseCache::read_entry(&path)?;
+        let age = now().saturating_sub(entry.created_at);
+        if age >= self.ttl {
+            let _ = fs::remove_file(path);
+            return None;
+        }
+        entry.hits += 1;
+        // the hit count is only for stats, an answer is still an answer without it
+        let _ = self.write_entry(key, &entry);
+        Some(CacheHit {
+            llm: entry.llm,
+            output: GenerateOutput {
+                answer: entry.answer,
+                suggestion: entry.suggestion,
+                context: entry.context,
+                usage: Default::default(),
+            },
+            age,
+        })
+    }
+    pub fn put(&self, key: &str, llm: &str, output: &GenerateOutput) -> BottResult<()> {
+        let entry = CacheEntry {
+            created_at: now(),
+            hits: 0,
+            llm: llm.to_string(),
+            answer: output.answer.clone(),
+            suggestion: output.suggestion.clone(),
+            context: output.context.clone(),
+        };
+        self.prune();
+        self.write_entry(key, &entry)
+    }
+    // Removes the expired answers, most are never asked for again once the history moved on
+    fn prune(&self) {
+        let Ok(files) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        let now = now();
+        for path in files.filter_map(|f| f.ok()).map(|f| f.path()) {
+            if let Some(entry) = ResponseCache::read_entry(&path) {
+                if now.saturating_sub(entry.created_at) >= self.ttl {
+                    let _ = fs::remove_file(path);
+                }
+            }
+        }
+    }
+    pub fn stats(&self) -> CacheStats {
+        let mut stats = CacheStats {
+            entries: 0,
+            expired: 0,
+            hits: 0,
+            size: 0,
+            ttl: self.ttl,
+        };
+        let Ok(files) = fs::read_dir(&self.dir) else {
+            return stats;
+        };
+        let now = now();
+        for path in files.filter_map(|f| f.ok()).map(|f| f.path()) {
+            let Some(entry) = ResponseCache::read_entry(&path) else {
+                continue;
+            };
+            stats.entries += 1;
+            stats.hits += entry.hits;
+            stats.size += fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            if now.saturating_sub(entry.created_at) >= self.ttl {
+                stats.expired += 1;
+            }
+        }
+        stats
+    }
+    // Removes every cached answer and returns how many there were
+    pub fn clear(&self) -> BottResult<usize> {
+        let entries = self.stats().entries;
+        if self.dir.exists() && fs::remove_dir_all(&self.dir).is_err() {
+            return Err(BottError::CacheStoreErr);
+        }
+        Ok(entries)
+    }
+}
+impl CacheStats {
+    pub fn render(&self) -> String {
+        [
+            format!("Entries: {} ({} expired)", self.entries, self.expired),
+            format!("Hits: {}", self.hits),
+            format!("Size: {}", format_size(self.size)),
+            match self.ttl {
+                0 => String::from("TTL: off"),
+                t => format!("TTL: {}s", t),
+            },
+        ]
+        .join("\n")
+    }
+}
+
+// How long ago an answer was cached, in the largest unit that fits
+pub fn format_age(age: u64) -> String {
+    match age {
+        0..=59 => format!("{}s", age),
+        60..=3599 => format!("{}m", age / 60),
+        3600..=86399 => format!("{}h", age / 3600),
+        _ => format!("{}d", age / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::conversation::Message;
+
+    fn temp_cache(ttl: u64) -> ResponseCache {
+        let dir = std::env::temp_dir().join(format!("bott-cache-{}", rand::random::<u64>()));
+        ResponseCache { dir, ttl }
+    }
+
+    #[test]
+    fn reuses_answers_within_ttl() {
+        let cache = temp_cache(60);
+        let output = GenerateOutput::suggestion(
+            Suggestion::new(String::from("ls -la")),
+            Conversation::new(vec![
+                Message::user(String::from("list files")),
+                Message::assistant(String::from("```bash\nls -la\n```")),
+            ]),
+        );
+        cache.put("key", "ollama", &output).unwrap();
+        let hit = cache.get("key").unwrap();
+        assert_eq!(hit.llm, "ollama");
+        assert_eq!(hit.output.answer, "ls -la");
+        assert_eq!(hit.output.context, output.context);
+        assert!(cache.get("other").is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.hits, stats.expired), (1, 1, 0));
+        assert_eq!(cache.clear().unwrap(), 1);
+        assert!(cache.get("key").is_none());
+    }
+
+    fn expired_entry() -> CacheEntry {
+        CacheEntry {
+            created_at: now() - 120,
+            hits: 0,
+            llm: String::from("openai"),
+            answer: String::from("ls"),
+            suggestion: None,
+            context: Conversation::default(),
+        }
+    }
+
+    #[test]
+    fn removes_expired_answers_when_looked_up() {
+        let cache = temp_cache(60);
+        cache.write_entry("key", &expired_entry()).unwrap();
+        assert_eq!(cache.stats().expired, 1);
+        assert!(cache.get("key").is_none());
+        assert!(!cache.get_path("key").exists());
+        assert_eq!(cache.stats().entries, 0);
+        cache.clear().unwrap();
+    }
+
+    #[test]
+    fn prunes_expired_answers_when_storing() {
+        let cache = temp_cache(60);
+        cache.write_entry("old", &expired_entry()).unwrap();
+        let output = GenerateOutput::text(String::from("ls"), Conversation::default());
+        cache.put("new", "ollama", &output).unwrap();
+        assert!(!cache.get_path("old").exists());
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.expired), (1, 0));
+        cache.clear().unwrap();
+    }
+
+    #[test]
+    fn keys_depend_on_the_whole_request() {
+        let mut config = BottConfig::default();
+        let history = Conversation::new(vec![Message::user(String::from("list files"))]);
+        let key = |config: &mut BottConfig, history: &Conversation, query: &str| {
+            ResponseCache::get_key(config, "ollama", "be helpful", history, query)
+        };
+        let first = key(&mut config, &history, "and hidden ones?");
+        assert_eq!(first, key(&mut config, &history, "and hidden ones?"));
+        assert_ne!(first, key(&mut config, &history, "and large ones?"));
+        assert_ne!(
+            first,
+            key(&mut config, &Conversation::default(), "and hidden ones?")
+        );
+    }
+
+    #[test]
+    fn formats_ages() {
+        assert_eq!(format_age(42), "42s");
+        assert_eq!(format_age(7200), "2h");
+        assert_eq!(format_age(3 * 86400), "3d");
+    }
+}
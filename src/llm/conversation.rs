@@ -0,0 +1,118 @@
+use base64::{engine::general_purpose, Engine as _};
+use serde_derive::{Deserialize, Serialize};
+use std::env;
+
+pub const ROLE_USER: &str = "user";
+pub const ROLE_ASSISTANT: &str = "assistant";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Message {
+    pub role: String,
+    pub content: String,
+}
+impl Message {
+    pub fn user(content: String) -> Self {
+        Self {
+            role: String::from(ROLE_USER),
+            content,
+        }
+    }
+    pub fn assistant(content: String) -> Self {
+        Self {
+            role: String::from(ROLE_ASSISTANT),
+            content,
+        }
+    }
+}
+
+// The questions and answers of a session, independent of the llm that answered them.
+// System prompts aren't part of it, every backend adds its own for the current directory.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(transparent)]
+pub struct Conversation {
+    pub messages: Vec<Message>,
+}
+impl Conversation {
+    pub fn new(messages: Vec<Message>) -> Self {
+        Self { messages }
+    }
+    // The session the shell keeps between queries
+    pub fn load() -> Self {
+        Conversation::decode(env::var("bott_context").unwrap_or_default().as_str())
+    }
+    pub fn push(&mut self, message: Message) {
+        self.messages.push(message);
+    }
+    // Contents are base64 encoded so the shell can keep the session in a variable
+    pub fn encode(&self) -> String {
+        let messages = self
+            .messages
+            .iter()
+            .map(|m| Message {
+                role: m.role.clone(),
+                content: general_purpose::STANDARD.encode(&m.content),
+            })
+            .collect::<Vec<Message>>();
+        serde_json::to_string(&messages).unwrap()
+    }
+    // Sessions stored by older versions are read too: OpenAI messages have the same shape,
+    // their system and tool messages are dropped. Anything else starts a new conversation.
+    pub fn decode(context: &str) -> Self {
+        let messages: Vec<Message> = serde_json::from_str(context).unwrap_or_default();
+        Conversation::new(
+            messages
+                .into_iter()
+                .filter(|m| m.role == ROLE_USER || m.role == ROLE_ASSISTANT)
+                .filter_map(|m| {
+                    let content = general_purpose::STANDARD.decode(&m.content).ok()?;
+                    Some(Message {
+                        role: m.role,
+                        content: String::from_utf8(content).ok()?,
+                    })
+                })
+                .collect::<Vec<Message>>(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn survives_encoding() {
+        let conversation = Conversation::new(vec![
+            Message::user(String::from("list \"files\"\nplease")),
+            Message::assistant(String::from("ls -la")),
+        ]);
+        assert_eq!(
+            Conversation::decode(conversation.encode().as_str()),
+            conversation
+        );
+    }
+
+    #[test]
+    fn reads_openai_sessions() {
+        let encode = |s: &str| general_purpose::STANDARD.encode(s);
+        let context = serde_json::json!([
+            {"role": "system", "content": encode("be helpful")},
+            {"role": "user", "content": encode("list files")},
+            {"role": "assistant", "content": encode("```bash\nls\n```"), "tool_calls": []},
+        ])
+        .to_string();
+        let conversation = Conversation::decode(context.as_str());
+        assert_eq!(
+            conversation.messages,
+            vec![
+                Message::user(String::from("list files")),
+                Message::assistant(String::from("```bash\nls\n```")),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_unknown_sessions() {
+        assert!(Conversation::decode("1 2 3").messages.is_empty());
+        assert!(Conversation::decode("").messages.is_empty());
+    }
+}
@@ -0,0 +1,243 @@
+use crate::config::BottConfig;
+use crate::llm::anthropic::ANTHROPIC_URL;
+use crate::llm::gemini::GEMINI_URL;
+use crate::llm::http::{load_certificates, HttpPolicy};
+use crate::llm::ollama::OLLAMA_URL;
+use crate::llm::{
+    get_llms, GenerateOutput, LLM_ANTHROPIC, LLM_AZURE, LLM_GEMINI, LLM_LLAMACPP, LLM_OLLAMA,
+    LLM_OPENAI,
+};
+use crate::result::BottResult;
+use async_openai::config::{Config, OpenAIConfig};
+use reqwest::{StatusCode, Url};
+use std::time::Instant;
+
+pub struct NetReport {
+    pub lines: Vec<String>,
+    pub ok: bool,
+}
+
+// Proxy urls often carry credentials, keep them out of the report
+fn redact_proxy(proxy: &str) -> String {
+    match Url::parse(proxy) {
+        Ok(mut url) if url.password().is_some() => {
+            let _ = url.set_password(Some("redacted"));
+            url.to_string()
+        }
+        _ => proxy.to_string(),
+    }
+}
+
+// A cheap url that every provider answers, even without credentials
+fn get_probe_url(llm: &str, config: &mut BottConfig) -> BottResult<Option<String>> {
+    let url = match llm {
+        LLM_OLLAMA => Some(format!("{}/api/version", OLLAMA_URL)),
+        LLM_OPENAI => Some(format!("{}/models", OpenAIConfig::default().api_base())),
+        LLM_ANTHROPIC => Some(format!("{}/v1/models", ANTHROPIC_URL)),
+        LLM_GEMINI => Some(format!("{}/v1beta/models", GEMINI_URL)),
+        LLM_AZURE => config.get_key("azure:endpoint")?.map(|endpoint| {
+            format!(
+                "{}/openai/models?api-version={}",
+                endpoint.trim_end_matches('/'),
+                config
+                    .get_key("azure:api_version")
+                    .ok()
+                    .flatten()
+                    .unwrap_or_default()
+            )
+        }),
+        LLM_LLAMACPP => Some(format!("{}/health", config.get_llamacpp_options().url)),
+        _ => None,
+    };
+    Ok(url)
+}
+
+impl NetReport {
+    fn fail(&mut self, line: String) {
+        self.ok = false;
+        self.lines.push(line);
+    }
+}
+
+// Describes the proxy and certificates in use and builds the client they make up
+fn check_policy(policy: &HttpPolicy, report: &mut NetReport) -> Option<reqwest::Client> {
+    report.lines.push(match &policy.proxy {
+        Some(p) => format!(
+            "Proxy: {} (not used for {})",
+            redact_proxy(p),
+            policy.get_no_proxy()
+        ),
+        None => String::from("Proxy: none"),
+    });
+    match &policy.ca_bundle {
+        Some(path) => match load_certificates(path) {
+            Ok(c) => report
+                .lines
+                .push(format!("CA bundle: {} ({} certificates)", path, c.len())),
+            Err(e) => report.fail(format!("CA bundle: {}", e)),
+        },
+        None => report
+            .lines
+            .push(String::from("CA bundle: system certificates only")),
+    }
+    match policy.client() {
+        Ok(c) => Some(c),
+        Err(e) => {
+            report.fail(e.to_string());
+            None
+        }
+    }
+}
+
+async fn probe(
+    policy: &HttpPolicy,
+    client: &reqwest::Client,
+    llm: &str,
+    url: &str,
+    report: &mut NetReport,
+) {
+    let started = Instant::now();
+    match policy.send(client.get(url)).await {
+        // the proxy answered instead of the llm
+        Ok(r) if r.status() == StatusCode::PROXY_AUTHENTICATION_REQUIRED => report.fail(format!(
+            "{}: unable to reach {}: the proxy asks for credentials ({})",
+            llm,
+            url,
+            r.status()
+        )),
+        Ok(r) => report.lines.push(format!(
+            "{}: reached {} ({} in {}ms)",
+            llm,
+            url,
+            r.status(),
+            started.elapsed().as_millis()
+        )),
+        Err(e) => report.fail(format!("{}: unable to reach {}: {}", llm, url, e)),
+    }
+}
+
+// Checks that every configured llm can be reached with the current proxy and tls settings
+pub async fn check_network() -> BottResult<NetReport> {
+    let mut config = BottConfig::load()?;
+    let mut report = NetReport {
+        lines: vec![],
+        ok: true,
+    };
+    // one attempt is enough to tell whether something is reachable
+    let policy = HttpPolicy {
+        retries: 0,
+        ..HttpPolicy::from_config(&config)
+    };
+    let Some(client) = check_policy(&policy, &mut report) else {
+        return Ok(report);
+    };
+    let llm_list = config.get_key("llm")?.unwrap_or_default();
+    for llm in get_llms(&llm_list) {
+        match get_probe_url(llm, &mut config)? {
+            Some(url) => probe(&policy, &client, llm, &url, &mut report).await,
+            None => report.lines.push(format!("{}: no network needed", llm)),
+        }
+        match GenerateOutput::check_health(llm).await {
+            Ok(_) => report.lines.push(String::from("  ready")),
+            Err(e) => report.fail(format!("  not ready: {}", e)),
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::test_server::{TestResponse, TestServer};
+    use std::env;
+    use std::fs;
+    use std::time::Duration;
+
+    fn policy() -> HttpPolicy {
+        HttpPolicy {
+            connect_timeout: Duration::from_secs(1),
+            read_timeout: Duration::from_secs(5),
+            retries: 0,
+            ..HttpPolicy::default()
+        }
+    }
+
+    fn report() -> NetReport {
+        NetReport {
+            lines: vec![],
+            ok: true,
+        }
+    }
+
+    async fn check(policy: &HttpPolicy, url: &str) -> NetReport {
+        let mut report = report();
+        if let Some(client) = check_policy(policy, &mut report) {
+            probe(policy, &client, LLM_OLLAMA, url, &mut report).await;
+        }
+        report
+    }
+
+    #[tokio::test]
+    async fn reports_reachable_llms() {
+        let server = TestServer::start(200, "application/json", r#"{"version":"0.1.0"}"#).await;
+        let url = format!("{}/api/version", server.url);
+        let report = check(&policy(), &url).await;
+        assert!(report.ok);
+        assert_eq!(report.lines[0], "Proxy: none");
+        assert_eq!(report.lines[1], "CA bundle: system certificates only");
+        assert!(report.lines[2].starts_with(&format!("ollama: reached {} (200 OK in ", url)));
+    }
+
+    #[tokio::test]
+    async fn reports_proxy_errors() {
+        let proxy = TestServer::start_with(vec![TestResponse::new(
+            407,
+            "text/plain",
+            "proxy authentication required",
+        )])
+        .await;
+        let policy = HttpPolicy {
+            proxy: Some(proxy.url.replace("http://", "http://user:secret@")),
+            ..policy()
+        };
+        let report = check(&policy, "http://llm.example.com/api/version").await;
+        assert!(!report.ok);
+        assert!(report.lines[0].contains("user:redacted@"));
+        assert!(!report.lines[0].contains("secret"));
+        assert!(report.lines[2].contains("the proxy asks for credentials"));
+
+        // nothing listening on the proxy port
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let policy = HttpPolicy {
+            proxy: Some(format!("http://{}", listener.local_addr().unwrap())),
+            ..policy
+        };
+        drop(listener);
+        let report = check(&policy, "http://llm.example.com/api/version").await;
+        assert!(!report.ok);
+        assert!(report.lines[2].starts_with("ollama: unable to reach http://llm.example.com"));
+    }
+
+    #[tokio::test]
+    async fn reports_tls_failures() {
+        let path = env::temp_dir().join(format!("bott-ca-{}.pem", rand::random::<u64>()));
+        fs::write(&path, "not a certificate").unwrap();
+        let invalid_bundle = HttpPolicy {
+            ca_bundle: Some(path.to_string_lossy().to_string()),
+            ..policy()
+        };
+        let report = check(&invalid_bundle, "https://llm.example.com").await;
+        fs::remove_file(&path).unwrap();
+        assert!(!report.ok);
+        assert!(report.lines[1].starts_with("CA bundle: "));
+        // no client can be built, so nothing is probed
+        assert_eq!(report.lines.len(), 3);
+
+        // the server doesn't speak tls
+        let server = TestServer::start(200, "text/plain", "ok").await;
+        let url = server.url.replace("http://", "https://");
+        let report = check(&policy(), &url).await;
+        assert!(!report.ok);
+        assert!(report.lines[2].starts_with(&format!("ollama: unable to reach {}", url)));
+    }
+}
@@ -0,0 +1,416 @@
+use crate::config::BottConfig;
+use crate::context::PromptContext;
+use crate::errors::{BottError, BottGeminiError};
+use crate::llm::conversation::{Conversation, Message, ROLE_ASSISTANT};
+use crate::llm::http::HttpPolicy;
+use crate::llm::models::ModelInfo;
+use crate::llm::suggestion::Suggestion;
+use crate::llm::usage::Usage;
+use crate::llm::{
+    get_debug_prompt, get_debug_system_prompt, get_query_system_prompt, GenerateOutput,
+};
+use crate::result::BottResult;
+use serde_derive::{Deserialize, Serialize};
+use std::env;
+
+pub const GEMINI_URL: &str = "https://generativelanguage.googleapis.com";
+const MODELS_PAGE_SIZE: u32 = 1000;
+
+// Answers can mix in parts without text, like function calls or inline data
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Part {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Content {
+    pub role: String,
+    pub parts: Vec<Part>,
+}
+#[derive(Serialize, Debug)]
+pub struct SystemInstruction {
+    parts: Vec<Part>,
+}
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerateContentRequest {
+    system_instruction: SystemInstruction,
+    contents: Vec<Content>,
+}
+#[derive(Deserialize, Debug)]
+struct Candidate {
+    content: Option<Content>,
+}
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: u64,
+    #[serde(default)]
+    candidates_token_count: u64,
+}
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GenerateContentResponse {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    #[serde(default)]
+    usage_metadata: UsageMetadata,
+}
+#[derive(Deserialize, Debug)]
+struct ErrorDetail {
+    message: String,
+}
+#[derive(Deserialize, Debug)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ModelObject {
+    name: String,
+    input_token_limit: Option<u64>,
+    #[serde(default)]
+    supported_generation_methods: Vec<String>,
+}
+#[derive(Deserialize, Debug)]
+struct ModelList {
+    #[serde(default)]
+    models: Vec<ModelObject>,
+}
+
+// Gemini calls the assistant "model"
+impl From<&Message> for Content {
+    fn from(message: &Message) -> Self {
+        let role = if message.role == ROLE_ASSISTANT {
+            "model"
+        } else {
+            message.role.as_str()
+        };
+        Self {
+            role: String::from(role),
+            parts: vec![Part {
+                text: Some(message.content.clone()),
+            }],
+        }
+    }
+}
+
+pub async fn get_model() -> BottResult<String> {
+    let config = BottConfig::load()?;
+    Ok(config.get_gemini_options().model)
+}
+pub async fn check_health() -> BottResult<()> {
+    let mut config = BottConfig::load()?;
+    match config.get_key("gemini:api_key") {
+        Ok(Some(_)) => Ok(()),
+        _ => Err(BottError::GeminiErr(BottGeminiError::MissingApiKey)),
+    }
+}
+pub async fn generate(
+    query: &str,
+    prompt_context: &PromptContext,
+    debug: bool,
+) -> BottResult<GenerateOutput> {
+    let context: Conversation;
+    let prompt: String;
+    let system_prompt: String;
+    if debug {
+        let input = env::var("bott_last_run_executed_code").unwrap_or(String::from(""));
+        let output = env::var("bott_last_run_output").unwrap_or(String::from(""));
+        prompt = get_debug_prompt(input.as_str(), output.as_str())?;
+        context = Conversation::default();
+        system_prompt = get_debug_system_prompt(prompt_context)?;
+    } else {
+        prompt = String::from(query);
+        context = Conversation::load();
+        system_prompt = get_query_system_prompt(prompt_context)?;
+    }
+    generate_with_context(prompt, system_prompt, context, debug).await
+}
+pub async fn generate_with_context(
+    prompt: String,
+    system_prompt: String,
+    context: Conversation,
+    debug: bool,
+) -> BottResult<GenerateOutput> {
+    let model: String = get_model().await?;
+    let mut config = BottConfig::load()?;
+    let api_key = match config.get_key("gemini:api_key") {
+        Ok(Some(k)) => k,
+        _ => return Err(BottError::GeminiErr(BottGeminiError::MissingApiKey)),
+    };
+    let policy = HttpPolicy::load()?;
+    complete(
+        &policy,
+        GEMINI_URL,
+        api_key.as_str(),
+        model,
+        prompt,
+        system_prompt,
+        context,
+        debug,
+    )
+    .await
+}
+
+pub async fn send_generate_content(
+    policy: &HttpPolicy,
+    base_url: &str,
+    api_key: &str,
+    model: &str,
+    request: &GenerateContentRequest,
+) -> BottResult<(String, Usage)> {
+    let client = policy.client()?;
+    let response = policy
+        .send_generation(
+            client
+                .post(format!(
+                    "{}/v1beta/models/{}:generateContent",
+                    base_url, model
+                ))
+                .header("x-goog-api-key", api_key)
+                .json(request),
+        )
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let message = match response.json::<ErrorResponse>().await {
+            Ok(e) => e.error.message,
+            Err(_) => status.to_string(),
+        };
+        return Err(BottError::GeminiErr(BottGeminiError::ApiError(message)));
+    }
+    let body = match response.json::<GenerateContentResponse>().await {
+        Ok(b) => b,
+        Err(_) => return Err(BottError::GeminiErr(BottGeminiError::InvalidResponse)),
+    };
+    let usage = Usage {
+        model: model.to_string(),
+        prompt_tokens: body.usage_metadata.prompt_token_count,
+        completion_tokens: body.usage_metadata.candidates_token_count,
+    };
+    // a blocked prompt comes back without any candidates
+    let content = body
+        .candidates
+        .into_iter()
+        .next()
+        .and_then(|c| c.content)
+        .ok_or(BottError::GeminiErr(BottGeminiError::UnableToGetResponse))?;
+    let answer = content
+        .parts
+        .into_iter()
+        .filter_map(|p| p.text)
+        .collect::<Vec<String>>()
+        .join("");
+    Ok((answer, usage))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn complete(
+    policy: &HttpPolicy,
+    base_url: &str,
+    api_key: &str,
+    model: String,
+    prompt: String,
+    system_prompt: String,
+    mut context: Conversation,
+    debug: bool,
+) -> BottResult<GenerateOutput> {
+    context.push(Message::user(prompt));
+    let request = GenerateContentRequest {
+        system_instruction: SystemInstruction {
+            parts: vec![Part {
+                text: Some(system_prompt),
+            }],
+        },
+        contents: context
+            .messages
+            .iter()
+            .map(Content::from)
+            .collect::<Vec<Content>>(),
+    };
+    let (content, usage) =
+        send_generate_content(policy, base_url, api_key, model.as_str(), &request).await?;
+    context.push(Message::assistant(content.clone()));
+    if debug {
+        return Ok(GenerateOutput::text(content, context).with_usage(usage));
+    }
+    match Suggestion::parse(content.as_str()) {
+        Some(s) => Ok(GenerateOutput::suggestion(s, context).with_usage(usage)),
+        None => Err(BottError::GeminiErr(BottGeminiError::UnableToGetResponse)),
+    }
+}
+// Only models that can generate content are listed, embedding models can't answer
+pub async fn list_models(
+    policy: &HttpPolicy,
+    base_url: &str,
+    api_key: &str,
+) -> BottResult<Vec<ModelInfo>> {
+    let client = policy.client()?;
+    let response = policy
+        .send(
+            client
+                .get(format!("{}/v1beta/models", base_url))
+                .query(&[("pageSize", MODELS_PAGE_SIZE)])
+                .header("x-goog-api-key", api_key),
+        )
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let message = match response.json::<ErrorResponse>().await {
+            Ok(e) => e.error.message,
+            Err(_) => status.to_string(),
+        };
+        return Err(BottError::GeminiErr(BottGeminiError::ApiError(message)));
+    }
+    match response.json::<ModelList>().await {
+        Ok(b) => Ok(b
+            .models
+            .into_iter()
+            .filter(|m| {
+                m.supported_generation_methods
+                    .iter()
+                    .any(|g| g == "generateContent")
+            })
+            .map(|m| ModelInfo {
+                name: m.name.trim_start_matches("models/").to_string(),
+                context_length: m.input_token_limit,
+                ..ModelInfo::default()
+            })
+            .collect::<Vec<ModelInfo>>()),
+        Err(_) => Err(BottError::GeminiErr(BottGeminiError::InvalidResponse)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::test_server::TestServer;
+
+    fn response_body(text: &str) -> String {
+        serde_json::json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [{"text": text}]},
+                "finishReason": "STOP",
+            }],
+            "usageMetadata": {"promptTokenCount": 40, "candidatesTokenCount": 8, "totalTokenCount": 48},
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn maps_system_prompt_and_history() {
+        let body = response_body("```bash\ngit status\n```");
+        let server = TestServer::start(200, "application/json", &body).await;
+        let history = Conversation::new(vec![
+            Message::user(String::from("show branches")),
+            Message::assistant(String::from("```bash\ngit branch\n```")),
+        ]);
+        let output = complete(
+            &HttpPolicy::default(),
+            &server.url,
+            "test-key",
+            String::from("gemini-test"),
+            String::from("and the status?"),
+            String::from("be helpful"),
+            history,
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.answer, "git status");
+        assert_eq!(output.context.messages.len(), 4);
+        assert_eq!(output.context.messages[3].role, "assistant");
+        assert_eq!(output.usage.prompt_tokens, 40);
+        assert_eq!(output.usage.completion_tokens, 8);
+
+        let requests = server.requests();
+        assert!(requests[0]
+            .request_line
+            .starts_with("POST /v1beta/models/gemini-test:generateContent"));
+        assert_eq!(requests[0].header("x-goog-api-key").unwrap(), "test-key");
+        let json = requests[0].json();
+        assert_eq!(json["systemInstruction"]["parts"][0]["text"], "be helpful");
+        let roles = json["contents"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["role"].as_str().unwrap().to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(roles, vec!["user", "model", "user"]);
+    }
+
+    #[tokio::test]
+    async fn skips_parts_without_text() {
+        let body = serde_json::json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [
+                    {"text": "```bash\nls "},
+                    {"executableCode": {"language": "PYTHON", "code": "print(1)"}},
+                    {"text": "-la\n```"},
+                ]},
+                "finishReason": "STOP",
+            }],
+        });
+        let server = TestServer::start(200, "application/json", &body.to_string()).await;
+        let output = complete(
+            &HttpPolicy::default(),
+            &server.url,
+            "test-key",
+            String::from("gemini-test"),
+            String::from("list files"),
+            String::from("be helpful"),
+            Conversation::default(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.answer, "ls -la");
+    }
+
+    #[tokio::test]
+    async fn surfaces_api_errors() {
+        let body =
+            r#"{"error":{"code":400,"message":"API key not valid","status":"INVALID_ARGUMENT"}}"#;
+        let server = TestServer::start(400, "application/json", body).await;
+        let result = complete(
+            &HttpPolicy::default(),
+            &server.url,
+            "bad-key",
+            String::from("gemini-test"),
+            String::from("list files"),
+            String::from("be helpful"),
+            Conversation::default(),
+            false,
+        )
+        .await;
+        match result {
+            Err(BottError::GeminiErr(BottGeminiError::ApiError(m))) => {
+                assert_eq!(m, "API key not valid")
+            }
+            _ => panic!("expected an api error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn fails_when_prompt_is_blocked() {
+        let body = r#"{"promptFeedback":{"blockReason":"SAFETY"}}"#;
+        let server = TestServer::start(200, "application/json", body).await;
+        let result = complete(
+            &HttpPolicy::default(),
+            &server.url,
+            "test-key",
+            String::from("gemini-test"),
+            String::from("list files"),
+            String::from("be helpful"),
+            Conversation::default(),
+            false,
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(BottError::GeminiErr(BottGeminiError::UnableToGetResponse))
+        ));
+    }
+}
@@ -0,0 +1,448 @@
+use crate::config::{BottConfig, HttpOptions};
+use crate::errors::{BottError, BottHttpError};
+use crate::result::BottResult;
+use rand::Rng;
+use reqwest::{Certificate, NoProxy, Proxy, RequestBuilder, Response, StatusCode};
+use std::env;
+use std::fs;
+use std::future::Future;
+use std::time::Duration;
+
+const BASE_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+// local llm servers are never reached through a proxy
+const LOCAL_HOSTS: &str = "localhost,127.0.0.1,::1";
+const PROXY_ENV: [&str; 6] = [
+    "HTTPS_PROXY",
+    "https_proxy",
+    "HTTP_PROXY",
+    "http_proxy",
+    "ALL_PROXY",
+    "all_proxy",
+];
+const NO_PROXY_ENV: [&str; 2] = ["NO_PROXY", "no_proxy"];
+
+// How every backend talks to its llm: timeouts, retries of transient failures and Ctrl-C
+#[derive(Debug, Clone)]
+pub struct HttpPolicy {
+    pub connect_timeout: Duration,
+    pub read_timeout: Duration,
+    pub retries: usize,
+    pub base_delay: Duration,
+    pub proxy: Option<String>,
+    pub no_proxy: Option<String>,
+    pub ca_bundle: Option<String>,
+}
+
+impl From<HttpOptions> for HttpPolicy {
+    fn from(options: HttpOptions) -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(options.connect_timeout as u64),
+            read_timeout: Duration::from_secs(options.read_timeout as u64),
+            retries: options.retries,
+            base_delay: BASE_DELAY,
+            proxy: None,
+            no_proxy: None,
+            ca_bundle: None,
+        }
+    }
+}
+
+impl Default for HttpPolicy {
+    fn default() -> Self {
+        HttpPolicy::from(HttpOptions::default())
+    }
+}
+
+fn first_env(names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .filter_map(|n| env::var(n).ok())
+        .find(|v| !v.trim().is_empty())
+}
+
+// Reads every certificate in a PEM bundle, reqwest only parses one at a time
+pub fn load_certificates(path: &str) -> BottResult<Vec<Certificate>> {
+    const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+    const END: &str = "-----END CERTIFICATE-----";
+    let invalid = || BottError::HttpErr(BottHttpError::InvalidCaBundle(path.to_string()));
+    let contents = fs::read_to_string(path).map_err(|_| invalid())?;
+    let mut certificates: Vec<Certificate> = vec![];
+    let mut rest = contents.as_str();
+    while let Some(start) = rest.find(BEGIN) {
+        let Some(end) = rest[start..].find(END) else {
+            break;
+        };
+        let pem = &rest[start..start + end + END.len()];
+        certificates.push(Certificate::from_pem(pem.as_bytes()).map_err(|_| invalid())?);
+        rest = &rest[start + end + END.len()..];
+    }
+    if certificates.is_empty() {
+        return Err(invalid());
+    }
+    Ok(certificates)
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::BAD_GATEWAY
+        || status == StatusCode::SERVICE_UNAVAILABLE
+        || status == StatusCode::GATEWAY_TIMEOUT
+        || status == StatusCode::INTERNAL_SERVER_ERROR
+}
+
+// Only the delay-seconds form is supported, llm apis don't send http dates
+fn get_retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+pub fn transport_error(error: &reqwest::Error) -> BottError {
+    if error.is_timeout() {
+        BottError::HttpErr(BottHttpError::Timeout)
+    } else if error.is_connect() {
+        BottError::HttpErr(BottHttpError::Connect(error.to_string()))
+    } else {
+        BottError::HttpErr(BottHttpError::Request(error.to_string()))
+    }
+}
+
+fn status_error(response: &Response) -> BottError {
+    match response.status() {
+        StatusCode::TOO_MANY_REQUESTS => BottError::HttpErr(BottHttpError::RateLimited(
+            get_retry_after(response).map(|d| d.as_secs()),
+        )),
+        status => BottError::HttpErr(BottHttpError::ServerError(status.as_u16())),
+    }
+}
+
+// Runs the future unless the user presses Ctrl-C first
+pub async fn cancellable<F: Future>(future: F) -> BottResult<F::Output> {
+    tokio::select! {
+        output = future => Ok(output),
+        _ = tokio::signal::ctrl_c() => Err(BottError::HttpErr(BottHttpError::Cancelled)),
+    }
+}
+
+impl HttpPolicy {
+    pub fn load() -> BottResult<Self> {
+        let config = BottConfig::load()?;
+        Ok(HttpPolicy::from_config(&config))
+    }
+    // Configured proxies win over the usual proxy environment variables
+    pub fn from_config(config: &BottConfig) -> Self {
+        let proxy_options = config.get_proxy_options();
+        Self {
+            proxy: proxy_options.url.or_else(|| first_env(&PROXY_ENV)),
+            no_proxy: proxy_options.no_proxy.or_else(|| first_env(&NO_PROXY_ENV)),
+            ca_bundle: config.get_tls_options().ca_bundle,
+            ..HttpPolicy::from(config.get_http_options())
+        }
+    }
+    pub fn get_no_proxy(&self) -> String {
+        match &self.no_proxy {
+            Some(n) if !n.trim().is_empty() => format!("{},{}", LOCAL_HOSTS, n.trim()),
+            _ => String::from(LOCAL_HOSTS),
+        }
+    }
+    pub fn client(&self) -> BottResult<reqwest::Client> {
+        // no total timeout, answers are streamed for as long as they keep coming, see chunk()
+        let mut builder = reqwest::Client::builder().connect_timeout(self.connect_timeout);
+        builder = match &self.proxy {
+            Some(url) => {
+                let proxy = Proxy::all(url)
+                    .map_err(|_| BottError::HttpErr(BottHttpError::InvalidProxy(url.clone())))?
+                    .no_proxy(NoProxy::from_string(&self.get_no_proxy()));
+                builder.proxy(proxy)
+            }
+            // the environment was already looked at when building the policy
+            None => builder.no_proxy(),
+        };
+        if let Some(path) = &self.ca_bundle {
+            for certificate in load_certificates(path)? {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        builder
+            .build()
+            .map_err(|e| BottError::HttpErr(BottHttpError::Request(e.to_string())))
+    }
+    // Exponential backoff with jitter, so parallel clients don't retry in lockstep
+    fn backoff(&self, attempt: usize) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt as u32))
+            .min(MAX_DELAY);
+        let jitter = rand::thread_rng().gen_range(0.5..=1.0);
+        delay.mul_f64(jitter)
+    }
+    // Sends the request, retrying rate limits, server errors and network blips.
+    // Any other response is returned as is so backends can read their error messages.
+    pub async fn send(&self, request: RequestBuilder) -> BottResult<Response> {
+        self.send_with(request, self.read_timeout).await
+    }
+    // Sends a request that generates a whole answer before the server responds. There is no
+    // waiting limit and no retry once it is sent, the server would only start over.
+    // The fallback chain decides how long an answer may take.
+    pub async fn send_generation(&self, request: RequestBuilder) -> BottResult<Response> {
+        self.send_with(request, Duration::MAX).await
+    }
+    async fn send_with(&self, request: RequestBuilder, wait: Duration) -> BottResult<Response> {
+        let mut attempt = 0;
+        loop {
+            let Some(current) = request.try_clone() else {
+                return Err(BottError::HttpErr(BottHttpError::Request(String::from(
+                    "request can't be retried",
+                ))));
+            };
+            let retry_after = match cancellable(tokio::time::timeout(wait, current.send())).await? {
+                // the server accepted the request but never answered
+                Err(_) if attempt >= self.retries => {
+                    return Err(BottError::HttpErr(BottHttpError::Timeout))
+                }
+                Err(_) => None,
+                Ok(Ok(response)) if is_retryable(response.status()) => {
+                    if attempt >= self.retries {
+                        return Err(status_error(&response));
+                    }
+                    get_retry_after(&response)
+                }
+                Ok(Ok(response)) => return Ok(response),
+                Ok(Err(e)) => {
+                    if attempt >= self.retries || !(e.is_connect() || e.is_timeout()) {
+                        return Err(transport_error(&e));
+                    }
+                    None
+                }
+            };
+            let delay = retry_after
+                .unwrap_or_else(|| self.backoff(attempt))
+                .min(MAX_DELAY);
+            cancellable(tokio::time::sleep(delay)).await?;
+            attempt += 1;
+        }
+    }
+    // Reads the next chunk of a streamed response, giving up once nothing arrived for read_timeout
+    pub async fn chunk(&self, response: &mut Response) -> BottResult<Option<Vec<u8>>> {
+        match cancellable(tokio::time::timeout(self.read_timeout, response.chunk())).await? {
+            Ok(Ok(chunk)) => Ok(chunk.map(|c| c.to_vec())),
+            Ok(Err(e)) => Err(transport_error(&e)),
+            Err(_) => Err(BottError::HttpErr(BottHttpError::Timeout)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::test_server::{TestResponse, TestServer};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn policy(retries: usize) -> HttpPolicy {
+        HttpPolicy {
+            connect_timeout: Duration::from_secs(1),
+            read_timeout: Duration::from_secs(5),
+            retries,
+            base_delay: Duration::from_millis(1),
+            ..HttpPolicy::default()
+        }
+    }
+
+    // Answers with a chunked body, waiting between the chunks
+    async fn start_slow_server(chunks: Vec<&'static str>, pause: Duration) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 4096];
+            let _ = stream.read(&mut request).await;
+            let head =
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nTransfer-Encoding: chunked\r\n\r\n";
+            let _ = stream.write_all(head.as_bytes()).await;
+            for chunk in chunks {
+                tokio::time::sleep(pause).await;
+                let data = format!("{:x}\r\n{}\r\n", chunk.len(), chunk);
+                let _ = stream.write_all(data.as_bytes()).await;
+            }
+            let _ = stream.write_all(b"0\r\n\r\n").await;
+        });
+        url
+    }
+
+    async fn read_all(policy: &HttpPolicy, response: &mut Response) -> BottResult<String> {
+        let mut body: Vec<u8> = vec![];
+        while let Some(chunk) = policy.chunk(response).await? {
+            body.extend_from_slice(&chunk);
+        }
+        Ok(String::from_utf8_lossy(&body).to_string())
+    }
+
+    async fn get(policy: &HttpPolicy, url: &str) -> BottResult<Response> {
+        let client = policy.client().unwrap();
+        policy.send(client.get(url)).await
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let server = TestServer::start_with(vec![
+            TestResponse::new(503, "text/plain", "busy"),
+            TestResponse::new(502, "text/plain", "bad gateway"),
+            TestResponse::new(200, "text/plain", "ok"),
+        ])
+        .await;
+        let response = get(&policy(3), &server.url).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn honours_retry_after() {
+        let server = TestServer::start_with(vec![
+            TestResponse::new(429, "text/plain", "slow down").header("Retry-After", "1"),
+            TestResponse::new(200, "text/plain", "ok"),
+        ])
+        .await;
+        let started = std::time::Instant::now();
+        let response = get(&policy(1), &server.url).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_retries() {
+        let server =
+            TestServer::start_with(vec![
+                TestResponse::new(429, "text/plain", "slow down").header("Retry-After", "0")
+            ])
+            .await;
+        let result = get(&policy(2), &server.url).await;
+        assert!(matches!(
+            result,
+            Err(BottError::HttpErr(BottHttpError::RateLimited(Some(0))))
+        ));
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn returns_client_errors_without_retrying() {
+        let server = TestServer::start(401, "application/json", r#"{"error":"bad key"}"#).await;
+        let response = get(&policy(3), &server.url).await.unwrap();
+        assert_eq!(response.status(), 401);
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn reports_connection_failures() {
+        // bind and release a port so nothing is listening on it
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let result = get(&policy(1), &url).await;
+        assert!(matches!(
+            result,
+            Err(BottError::HttpErr(BottHttpError::Connect(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn sends_requests_through_proxy() {
+        let proxy = TestServer::start(200, "text/plain", "proxied").await;
+        let policy = HttpPolicy {
+            proxy: Some(proxy.url.clone()),
+            ..policy(0)
+        };
+        let response = get(&policy, "http://llm.example.com/v1/models")
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "proxied");
+        assert!(proxy.requests()[0]
+            .request_line
+            .starts_with("GET http://llm.example.com/v1/models"));
+    }
+
+    #[tokio::test]
+    async fn bypasses_proxy_for_local_servers() {
+        let proxy = TestServer::start(200, "text/plain", "proxied").await;
+        let server = TestServer::start(200, "text/plain", "direct").await;
+        let policy = HttpPolicy {
+            proxy: Some(proxy.url.clone()),
+            no_proxy: Some(String::from("internal.example.com")),
+            ..policy(0)
+        };
+        let response = get(&policy, &server.url).await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "direct");
+        assert!(proxy.requests().is_empty());
+        assert_eq!(
+            policy.get_no_proxy(),
+            "localhost,127.0.0.1,::1,internal.example.com"
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_reading_streams_that_take_longer_than_the_read_timeout() {
+        let url = start_slow_server(vec!["a", "b", "c", "d"], Duration::from_millis(150)).await;
+        let policy = HttpPolicy {
+            read_timeout: Duration::from_millis(400),
+            ..policy(0)
+        };
+        let mut response = get(&policy, &url).await.unwrap();
+        assert_eq!(read_all(&policy, &mut response).await.unwrap(), "abcd");
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_stalled_streams() {
+        let url = start_slow_server(vec!["a"], Duration::from_secs(2)).await;
+        let policy = HttpPolicy {
+            read_timeout: Duration::from_millis(200),
+            ..policy(0)
+        };
+        let mut response = get(&policy, &url).await.unwrap();
+        assert!(matches!(
+            read_all(&policy, &mut response).await,
+            Err(BottError::HttpErr(BottHttpError::Timeout))
+        ));
+    }
+
+    #[tokio::test]
+    async fn times_out_waiting_for_an_answer() {
+        // accepts the connection but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let policy = HttpPolicy {
+            read_timeout: Duration::from_millis(200),
+            ..policy(0)
+        };
+        let result = get(&policy, &url).await;
+        assert!(matches!(
+            result,
+            Err(BottError::HttpErr(BottHttpError::Timeout))
+        ));
+        drop(listener);
+    }
+
+    #[tokio::test]
+    async fn waits_for_generations_without_retrying() {
+        let server = TestServer::start_with(vec![
+            TestResponse::new(200, "text/plain", "done").delay(Duration::from_millis(600))
+        ])
+        .await;
+        let policy = HttpPolicy {
+            read_timeout: Duration::from_millis(200),
+            ..policy(3)
+        };
+        let client = policy.client().unwrap();
+        let response = policy
+            .send_generation(client.post(&server.url))
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "done");
+        assert_eq!(server.requests().len(), 1);
+    }
+}
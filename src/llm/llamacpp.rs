@@ -0,0 +1,404 @@
+use crate::config::BottConfig;
+use crate::context::PromptContext;
+use crate::errors::{BottError, BottHttpError, BottLlamacppError};
+use crate::llm::conversation::{Conversation, Message, ROLE_ASSISTANT};
+use crate::llm::http::HttpPolicy;
+use crate::llm::suggestion::Suggestion;
+use crate::llm::usage::Usage;
+use crate::llm::{
+    get_debug_prompt, get_debug_system_prompt, get_query_system_prompt, GenerateOutput,
+};
+use crate::result::BottResult;
+use serde_derive::{Deserialize, Serialize};
+use std::env;
+use std::time::Duration;
+
+const API_COMPLETION: &str = "completion";
+const MAX_TOKENS: u32 = 512;
+// Forces the model to answer with a single fenced bash block and nothing else.
+// Backticks are left out of the command so the closing fence can't be faked.
+const COMMAND_GRAMMAR: &str = r#"root ::= "```bash\n" command "\n```"
+command ::= line ("\n" line)*
+line ::= [^`\n]+
+"#;
+const FENCE_START: &str = "```bash\n";
+const FENCE_END: &str = "\n```";
+
+#[derive(Serialize, Debug)]
+struct ChatRequest {
+    messages: Vec<Message>,
+    max_tokens: u32,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    grammar: Option<String>,
+}
+#[derive(Serialize, Debug)]
+struct CompletionRequest {
+    prompt: String,
+    n_predict: u32,
+    stream: bool,
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    grammar: Option<String>,
+}
+#[derive(Deserialize, Debug)]
+struct ChatChoiceMessage {
+    content: Option<String>,
+}
+#[derive(Deserialize, Debug)]
+struct ChatChoice {
+    message: ChatChoiceMessage,
+}
+#[derive(Deserialize, Debug, Default)]
+struct ChatUsage {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
+}
+#[derive(Deserialize, Debug)]
+struct ChatResponse {
+    #[serde(default)]
+    choices: Vec<ChatChoice>,
+    #[serde(default)]
+    model: String,
+    #[serde(default)]
+    usage: ChatUsage,
+}
+#[derive(Deserialize, Debug)]
+struct CompletionResponse {
+    content: String,
+    #[serde(default)]
+    model: String,
+    #[serde(default)]
+    tokens_evaluated: u64,
+    #[serde(default)]
+    tokens_predicted: u64,
+}
+#[derive(Deserialize, Debug)]
+struct ErrorDetail {
+    message: String,
+}
+#[derive(Deserialize, Debug)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+
+pub async fn check_health(timeout: Duration) -> BottResult<()> {
+    let config = BottConfig::load()?;
+    let url = config.get_llamacpp_options().url;
+    let client = HttpPolicy::from_config(&config).client()?;
+    match client
+        .get(format!("{}/health", url))
+        .timeout(timeout)
+        .send()
+        .await
+    {
+        Ok(r) if r.status().is_success() => Ok(()),
+        // the server answers 503 while the model is still loading
+        Ok(r) => Err(BottError::LlamacppErr(BottLlamacppError::ApiError(
+            r.status().to_string(),
+        ))),
+        Err(_) => Err(BottError::LlamacppErr(BottLlamacppError::NotRunning(url))),
+    }
+}
+pub async fn generate(
+    query: &str,
+    prompt_context: &PromptContext,
+    debug: bool,
+) -> BottResult<GenerateOutput> {
+    let context: Conversation;
+    let prompt: String;
+    let system_prompt: String;
+    if debug {
+        let input = env::var("bott_last_run_executed_code").unwrap_or(String::from(""));
+        let output = env::var("bott_last_run_output").unwrap_or(String::from(""));
+        prompt = get_debug_prompt(input.as_str(), output.as_str())?;
+        context = Conversation::default();
+        system_prompt = get_debug_system_prompt(prompt_context)?;
+    } else {
+        prompt = String::from(query);
+        context = Conversation::load();
+        system_prompt = get_query_system_prompt(prompt_context)?;
+    }
+    generate_with_context(prompt, system_prompt, context, debug).await
+}
+pub async fn generate_with_context(
+    prompt: String,
+    system_prompt: String,
+    context: Conversation,
+    debug: bool,
+) -> BottResult<GenerateOutput> {
+    let config = BottConfig::load()?;
+    let options = config.get_llamacpp_options();
+    let policy = HttpPolicy::from_config(&config);
+    complete(
+        &policy,
+        options.url.as_str(),
+        options.api.as_str(),
+        prompt,
+        system_prompt,
+        context,
+        debug,
+    )
+    .await
+}
+
+// Renders the conversation as plain text for the raw /completion endpoint,
+// which doesn't apply the model's chat template
+fn render_transcript(system_prompt: &str, messages: &[Message]) -> String {
+    let mut transcript = format!("System: {}\n", system_prompt.trim());
+    for message in messages {
+        let speaker = match message.role.as_str() {
+            ROLE_ASSISTANT => "Assistant",
+            _ => "User",
+        };
+        transcript.push_str(&format!("{}: {}\n", speaker, message.content.trim()));
+    }
+    transcript.push_str("Assistant: ");
+    transcript
+}
+
+async fn post<T: serde::Serialize>(
+    policy: &HttpPolicy,
+    base_url: &str,
+    path: &str,
+    request: &T,
+) -> BottResult<String> {
+    let client = policy.client()?;
+    let response = match policy
+        .send_generation(client.post(format!("{}{}", base_url, path)).json(request))
+        .await
+    {
+        Ok(r) => r,
+        Err(BottError::HttpErr(BottHttpError::Connect(_))) => {
+            return Err(BottError::LlamacppErr(BottLlamacppError::NotRunning(
+                base_url.to_string(),
+            )))
+        }
+        Err(e) => return Err(e),
+    };
+    let status = response.status();
+    let body = match response.text().await {
+        Ok(b) => b,
+        Err(_) => return Err(BottError::LlamacppErr(BottLlamacppError::InvalidResponse)),
+    };
+    if !status.is_success() {
+        let message = match serde_json::from_str::<ErrorResponse>(body.as_str()) {
+            Ok(e) => e.error.message,
+            Err(_) => status.to_string(),
+        };
+        return Err(BottError::LlamacppErr(BottLlamacppError::ApiError(message)));
+    }
+    Ok(body)
+}
+
+pub async fn complete(
+    policy: &HttpPolicy,
+    base_url: &str,
+    api: &str,
+    prompt: String,
+    system_prompt: String,
+    mut context: Conversation,
+    debug: bool,
+) -> BottResult<GenerateOutput> {
+    context.push(Message::user(prompt));
+    // debug answers are free form explanations, only commands are constrained
+    let grammar = if debug {
+        None
+    } else {
+        Some(String::from(COMMAND_GRAMMAR))
+    };
+    let (content, usage) = if api == API_COMPLETION {
+        let request = CompletionRequest {
+            prompt: render_transcript(system_prompt.as_str(), &context.messages),
+            n_predict: MAX_TOKENS,
+            stream: false,
+            stop: vec![String::from("\nUser:")],
+            grammar,
+        };
+        let body = post(policy, base_url, "/completion", &request).await?;
+        match serde_json::from_str::<CompletionResponse>(body.as_str()) {
+            Ok(r) => (
+                r.content,
+                Usage {
+                    model: r.model,
+                    prompt_tokens: r.tokens_evaluated,
+                    completion_tokens: r.tokens_predicted,
+                },
+            ),
+            Err(_) => return Err(BottError::LlamacppErr(BottLlamacppError::InvalidResponse)),
+        }
+    } else {
+        let mut messages = vec![Message {
+            role: String::from("system"),
+            content: system_prompt,
+        }];
+        messages.extend(context.messages.iter().cloned());
+        let request = ChatRequest {
+            messages,
+            max_tokens: MAX_TOKENS,
+            stream: false,
+            grammar,
+        };
+        let body = post(policy, base_url, "/v1/chat/completions", &request).await?;
+        match serde_json::from_str::<ChatResponse>(body.as_str()) {
+            Ok(r) => (
+                r.choices
+                    .into_iter()
+                    .next()
+                    .and_then(|c| c.message.content)
+                    .ok_or(BottError::LlamacppErr(
+                        BottLlamacppError::UnableToGetResponse,
+                    ))?,
+                Usage {
+                    model: r.model,
+                    prompt_tokens: r.usage.prompt_tokens,
+                    completion_tokens: r.usage.completion_tokens,
+                },
+            ),
+            Err(_) => return Err(BottError::LlamacppErr(BottLlamacppError::InvalidResponse)),
+        }
+    };
+    context.push(Message::assistant(content.clone()));
+    if debug {
+        return Ok(GenerateOutput::text(content, context).with_usage(usage));
+    }
+    // the grammar guarantees the shape, so the command is just what sits between the fences
+    match content
+        .trim()
+        .strip_prefix(FENCE_START)
+        .and_then(|c| c.strip_suffix(FENCE_END))
+    {
+        Some(command) => Ok(GenerateOutput::suggestion(
+            Suggestion::new(command.trim().to_string()),
+            context,
+        )
+        .with_usage(usage)),
+        None => Err(BottError::LlamacppErr(
+            BottLlamacppError::UnableToGetResponse,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::test_server::TestServer;
+
+    fn chat_body(content: &str) -> String {
+        serde_json::json!({
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": content},
+                "finish_reason": "stop",
+            }],
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn chat_sends_grammar_and_history() {
+        let body = chat_body("```bash\ngit status\n```");
+        let server = TestServer::start(200, "application/json", &body).await;
+        let history = Conversation::new(vec![
+            Message::user(String::from("show branches")),
+            Message::assistant(String::from("```bash\ngit branch\n```")),
+        ]);
+        let output = complete(
+            &HttpPolicy::default(),
+            &server.url,
+            "chat",
+            String::from("and the status?"),
+            String::from("be helpful"),
+            history,
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.answer, "git status");
+        assert_eq!(output.context.messages.len(), 4);
+
+        let requests = server.requests();
+        assert!(requests[0]
+            .request_line
+            .starts_with("POST /v1/chat/completions"));
+        let json = requests[0].json();
+        assert_eq!(json["grammar"], COMMAND_GRAMMAR);
+        let roles = json["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["role"].as_str().unwrap().to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(roles, vec!["system", "user", "assistant", "user"]);
+    }
+
+    #[tokio::test]
+    async fn completion_renders_transcript() {
+        let body = r#"{"content":"```bash\nls -la\n```","stop":true}"#;
+        let server = TestServer::start(200, "application/json", body).await;
+        let output = complete(
+            &HttpPolicy::default(),
+            &server.url,
+            "completion",
+            String::from("list files"),
+            String::from("be helpful"),
+            Conversation::default(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.answer, "ls -la");
+
+        let requests = server.requests();
+        assert!(requests[0].request_line.starts_with("POST /completion"));
+        let json = requests[0].json();
+        assert_eq!(
+            json["prompt"],
+            "System: be helpful\nUser: list files\nAssistant: "
+        );
+        assert_eq!(json["grammar"], COMMAND_GRAMMAR);
+    }
+
+    #[tokio::test]
+    async fn debug_is_not_constrained() {
+        let body = chat_body("The file does not exist.");
+        let server = TestServer::start(200, "application/json", &body).await;
+        let output = complete(
+            &HttpPolicy::default(),
+            &server.url,
+            "chat",
+            String::from("why?"),
+            String::from("be helpful"),
+            Conversation::default(),
+            true,
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.answer, "The file does not exist.");
+        assert!(server.requests()[0].json().get("grammar").is_none());
+    }
+
+    #[tokio::test]
+    async fn surfaces_api_errors() {
+        let body = r#"{"error":{"code":400,"message":"failed to parse grammar","type":"invalid_request_error"}}"#;
+        let server = TestServer::start(400, "application/json", body).await;
+        let result = complete(
+            &HttpPolicy::default(),
+            &server.url,
+            "chat",
+            String::from("list files"),
+            String::from("be helpful"),
+            Conversation::default(),
+            false,
+        )
+        .await;
+        match result {
+            Err(BottError::LlamacppErr(BottLlamacppError::ApiError(m))) => {
+                assert_eq!(m, "failed to parse grammar")
+            }
+            _ => panic!("expected an api error"),
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use crate::config::{BottConfig, LocalOptions};
+use crate::context::PromptContext;
+use crate::errors::{BottError, BottLocalError};
+use crate::llm::conversation::{Conversation, Message, ROLE_ASSISTANT};
+use crate::llm::suggestion::Suggestion;
+use crate::llm::usage::Usage;
+use crate::llm::{
+    get_debug_prompt, get_debug_system_prompt, get_query_system_prompt, GenerateOutput,
+};
+use crate::result::BottResult;
+use std::env;
+use std::path::{Path, PathBuf};
+
+const TEMPLATE_CHATML: &str = "chatml";
+
+pub async fn check_health() -> BottResult<()> {
+    if !cfg!(feature = "local-inference") {
+        return Err(BottError::LocalErr(BottLocalError::NotCompiled));
+    }
+    let config = BottConfig::load()?;
+    match config.get_local_options().model_path {
+        Some(p) if Path::new(&p).is_file() => Ok(()),
+        Some(p) => Err(BottError::LocalErr(BottLocalError::LoadError(format!(
+            "{} not found",
+            p
+        )))),
+        None => Err(BottError::LocalErr(BottLocalError::MissingModel)),
+    }
+}
+pub async fn generate(
+    query: &str,
+    prompt_context: &PromptContext,
+    debug: bool,
+) -> BottResult<GenerateOutput> {
+    let context: Conversation;
+    let prompt: String;
+    let system_prompt: String;
+    if debug {
+        let input = env::var("bott_last_run_executed_code").unwrap_or(String::from(""));
+        let output = env::var("bott_last_run_output").unwrap_or(String::from(""));
+        prompt = get_debug_prompt(input.as_str(), output.as_str())?;
+        context = Conversation::default();
+        system_prompt = get_debug_system_prompt(prompt_context)?;
+    } else {
+        prompt = String::from(query);
+        context = Conversation::load();
+        system_prompt = get_query_system_prompt(prompt_context)?;
+    }
+    generate_with_context(prompt, system_prompt, context, debug).await
+}
+pub async fn generate_with_context(
+    prompt: String,
+    system_prompt: String,
+    mut context: Conversation,
+    debug: bool,
+) -> BottResult<GenerateOutput> {
+    let config = BottConfig::load()?;
+    let options = config.get_local_options();
+    context.push(Message::user(prompt));
+    let rendered = render_prompt(
+        options.template.as_str(),
+        system_prompt.as_str(),
+        &context.messages,
+    );
+    // inference keeps the cpu busy for a while, keep it off the async runtime
+    let (content, usage) =
+        match tokio::task::spawn_blocking(move || infer(&options, rendered)).await {
+            Ok(r) => r?,
+            Err(e) => {
+                return Err(BottError::LocalErr(BottLocalError::InferenceError(
+                    e.to_string(),
+                )))
+            }
+        };
+    context.push(Message::assistant(content.clone()));
+    if debug {
+        return Ok(GenerateOutput::text(content, context).with_usage(usage));
+    }
+    match Suggestion::parse(content.as_str()) {
+        Some(s) => Ok(GenerateOutput::suggestion(s, context).with_usage(usage)),
+        None => Err(BottError::LocalErr(BottLocalError::UnableToGetResponse)),
+    }
+}
+
+// Formats the conversation with the chat template the model was trained on
+fn render_prompt(template: &str, system_prompt: &str, messages: &[Message]) -> String {
+    let mut prompt = String::new();
+    if template == TEMPLATE_CHATML {
+        prompt.push_str(&format!(
+            "<|im_start|>system\n{}<|im_end|>\n",
+            system_prompt.trim()
+        ));
+        for message in messages {
+            prompt.push_str(&format!(
+                "<|im_start|>{}\n{}<|im_end|>\n",
+                message.role,
+                message.content.trim()
+            ));
+        }
+        prompt.push_str("<|im_start|>assistant\n");
+        return prompt;
+    }
+    // llama2 / codellama instruct: the system prompt goes inside the first instruction
+    let mut system = Some(system_prompt.trim());
+    for message in messages {
+        if message.role == ROLE_ASSISTANT {
+            prompt.push_str(&format!(" {} </s>", message.content.trim()));
+            continue;
+        }
+        let content = match system.take() {
+            Some(s) => format!("<<SYS>>\n{}\n<</SYS>>\n\n{}", s, message.content.trim()),
+            None => message.content.trim().to_string(),
+        };
+        if !prompt.is_empty() {
+            prompt.push_str("<s>");
+        }
+        prompt.push_str(&format!("[INST] {} [/INST]", content));
+    }
+    prompt
+}
+
+// tokenizer.json is looked up next to the model unless configured explicitly
+#[cfg_attr(not(feature = "local-inference"), allow(dead_code))]
+fn get_tokenizer_path(options: &LocalOptions, model_path: &Path) -> PathBuf {
+    match &options.tokenizer_path {
+        Some(p) => PathBuf::from(p),
+        None => model_path.with_file_name("tokenizer.json"),
+    }
+}
+
+#[cfg(not(feature = "local-inference"))]
+fn infer(_options: &LocalOptions, _prompt: String) -> BottResult<(String, Usage)> {
+    Err(BottError::LocalErr(BottLocalError::NotCompiled))
+}
+
+#[cfg(feature = "local-inference")]
+fn infer(options: &LocalOptions, prompt: String) -> BottResult<(String, Usage)> {
+    use candle_core::quantized::gguf_file;
+    use candle_core::{Device, Tensor};
+    use candle_transformers::generation::LogitsProcessor;
+    use candle_transformers::models::quantized_llama::{ModelWeights, MAX_SEQ_LEN};
+    use tokenizers::Tokenizer;
+
+    const MAX_TOKENS: usize = 512;
+    const STOP_TOKENS: [&str; 3] = ["</s>", "<|im_end|>", "<|endoftext|>"];
+
+    let load_err = |e: String| BottError::LocalErr(BottLocalError::LoadError(e));
+    let inference_err =
+        |e: candle_core::Error| BottError::LocalErr(BottLocalError::InferenceError(e.to_string()));
+
+    let model_path = match &options.model_path {
+        Some(p) => PathBuf::from(p),
+        None => return Err(BottError::LocalErr(BottLocalError::MissingModel)),
+    };
+    let device = Device::Cpu;
+    let mut file = std::fs::File::open(&model_path)
+        .map_err(|e| load_err(format!("{}: {}", model_path.display(), e)))?;
+    let content = gguf_file::Content::read(&mut file).map_err(|e| load_err(e.to_string()))?;
+    let mut model = ModelWeights::from_gguf(content, &mut file, &device)
+        .map_err(|e| load_err(e.to_string()))?;
+    let tokenizer_path = get_tokenizer_path(options, &model_path);
+    let tokenizer = Tokenizer::from_file(&tokenizer_path)
+        .map_err(|e| load_err(format!("{}: {}", tokenizer_path.display(), e)))?;
+
+    let encoding = tokenizer
+        .encode(prompt, true)
+        .map_err(|e| load_err(e.to_string()))?;
+    let mut tokens = encoding.get_ids().to_vec();
+    // keep the end of long conversations so there's room left for the answer
+    if tokens.len() + MAX_TOKENS > MAX_SEQ_LEN {
+        tokens.drain(..tokens.len() + MAX_TOKENS - MAX_SEQ_LEN);
+    }
+    let stop_tokens = STOP_TOKENS
+        .iter()
+        .filter_map(|t| tokenizer.token_to_id(t))
+        .collect::<Vec<u32>>();
+
+    // greedy sampling, the same question should get the same command
+    let mut sampler = LogitsProcessor::new(0, None, None);
+    let mut generated: Vec<u32> = vec![];
+    let mut input = Tensor::new(tokens.as_slice(), &device)
+        .and_then(|t| t.unsqueeze(0))
+        .map_err(inference_err)?;
+    let mut position = 0;
+    while generated.len() < MAX_TOKENS {
+        let logits = model
+            .forward(&input, position)
+            .and_then(|l| l.squeeze(0))
+            .map_err(inference_err)?;
+        position += input.dim(1).map_err(inference_err)?;
+        let next = sampler.sample(&logits).map_err(inference_err)?;
+        if stop_tokens.contains(&next) {
+            break;
+        }
+        generated.push(next);
+        input = Tensor::new(&[next], &device)
+            .and_then(|t| t.unsqueeze(0))
+            .map_err(inference_err)?;
+    }
+    let answer = tokenizer
+        .decode(&generated, true)
+        .map_err(|e| BottError::LocalErr(BottLocalError::InferenceError(e.to_string())))?;
+    let usage = Usage {
+        model: model_path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        prompt_tokens: tokens.len() as u64,
+        completion_tokens: generated.len() as u64,
+    };
+    Ok((answer, usage))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conversation() -> Vec<Message> {
+        vec![
+            Message::user(String::from("show branches")),
+            Message::assistant(String::from("```bash\ngit branch\n```")),
+            Message::user(String::from("and the status?")),
+        ]
+    }
+
+    #[test]
+    fn renders_llama2_template() {
+        let prompt = render_prompt("llama2", "be helpful", &conversation());
+        assert_eq!(
+            prompt,
+            "[INST] <<SYS>>\nbe helpful\n<</SYS>>\n\nshow branches [/INST] ```bash\ngit branch\n``` </s><s>[INST] and the status? [/INST]"
+        );
+    }
+
+    #[test]
+    fn renders_chatml_template() {
+        let prompt = render_prompt("chatml", "be helpful", &conversation()[..1]);
+        assert_eq!(
+            prompt,
+            "<|im_start|>system\nbe helpful<|im_end|>\n<|im_start|>user\nshow branches<|im_end|>\n<|im_start|>assistant\n"
+        );
+    }
+
+    #[test]
+    fn finds_tokenizer_next_to_model() {
+        let options = LocalOptions::default();
+        let path = get_tokenizer_path(&options, Path::new("/models/codellama.Q4_K_M.gguf"));
+        assert_eq!(path, PathBuf::from("/models/tokenizer.json"));
+    }
+}
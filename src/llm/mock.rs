@@ -0,0 +1,227 @@
+use crate::config::BottConfig;
+use crate::context::PromptContext;
+use crate::errors::{BottError, BottMockError};
+use crate::llm::conversation::{Conversation, Message};
+use crate::llm::suggestion::Suggestion;
+use crate::llm::usage::Usage;
+use crate::llm::{
+    get_debug_prompt, get_debug_system_prompt, get_query_system_prompt, GenerateOutput,
+};
+use crate::result::BottResult;
+use regex::Regex;
+use serde_derive::Deserialize;
+use std::env;
+use std::fs;
+
+const MOCK_MODEL: &str = "mock";
+
+// A scripted answer, given when its pattern matches the prompt.
+// Entries without a pattern match anything.
+#[derive(Deserialize, Debug, Clone)]
+struct MockResponse {
+    #[serde(default)]
+    pattern: Option<String>,
+    response: String,
+}
+// Answers for demos and shell tests, in yaml or json (which yaml reads too)
+#[derive(Deserialize, Debug, Clone)]
+struct MockScript {
+    responses: Vec<MockResponse>,
+}
+impl MockScript {
+    fn parse(contents: &str) -> BottResult<Self> {
+        let script: MockScript = match serde_yaml::from_str(contents) {
+            Ok(s) => s,
+            Err(e) => {
+                return Err(BottError::MockErr(BottMockError::InvalidScript(
+                    e.to_string(),
+                )))
+            }
+        };
+        // a broken pattern is a mistake in the script, not a prompt that doesn't match
+        for pattern in script.responses.iter().filter_map(|r| r.pattern.as_ref()) {
+            if let Err(e) = Regex::new(pattern) {
+                return Err(BottError::MockErr(BottMockError::InvalidScript(
+                    e.to_string(),
+                )));
+            }
+        }
+        Ok(script)
+    }
+    fn load(config: &BottConfig) -> BottResult<Self> {
+        let Some(path) = config.get_mock_options().script else {
+            return Err(BottError::MockErr(BottMockError::MissingScript));
+        };
+        match fs::read_to_string(&path) {
+            Ok(c) => MockScript::parse(&c),
+            Err(e) => Err(BottError::MockErr(BottMockError::InvalidScript(format!(
+                "{}: {}",
+                path, e
+            )))),
+        }
+    }
+    // The first response whose pattern matches, in the order of the script
+    fn find(&self, prompt: &str) -> Option<&MockResponse> {
+        self.responses.iter().find(|r| match &r.pattern {
+            Some(p) => Regex::new(p).is_ok_and(|re| re.is_match(prompt)),
+            None => true,
+        })
+    }
+}
+
+pub async fn check_health() -> BottResult<()> {
+    MockScript::load(&BottConfig::load()?).map(|_| ())
+}
+pub async fn generate(
+    query: &str,
+    prompt_context: &PromptContext,
+    debug: bool,
+) -> BottResult<GenerateOutput> {
+    let context: Conversation;
+    let prompt: String;
+    let system_prompt: String;
+    if debug {
+        let input = env::var("bott_last_run_executed_code").unwrap_or(String::from(""));
+        let output = env::var("bott_last_run_output").unwrap_or(String::from(""));
+        prompt = get_debug_prompt(input.as_str(), output.as_str())?;
+        context = Conversation::default();
+        system_prompt = get_debug_system_prompt(prompt_context)?;
+    } else {
+        prompt = String::from(query);
+        context = Conversation::load();
+        system_prompt = get_query_system_prompt(prompt_context)?;
+    }
+    generate_with_context(prompt, system_prompt, context, debug).await
+}
+pub async fn generate_with_context(
+    prompt: String,
+    system_prompt: String,
+    context: Conversation,
+    debug: bool,
+) -> BottResult<GenerateOutput> {
+    let script = MockScript::load(&BottConfig::load()?)?;
+    complete(&script, prompt, system_prompt, context, debug)
+}
+fn complete(
+    script: &MockScript,
+    prompt: String,
+    system_prompt: String,
+    mut context: Conversation,
+    debug: bool,
+) -> BottResult<GenerateOutput> {
+    let Some(scripted) = script.find(prompt.as_str()) else {
+        return Err(BottError::MockErr(BottMockError::NoMatch(
+            prompt.trim().to_string(),
+        )));
+    };
+    let content = scripted.response.clone();
+    // words stand in for tokens, enough to see requests show up in the usage report
+    let usage = Usage {
+        model: String::from(MOCK_MODEL),
+        prompt_tokens: (system_prompt.split_whitespace().count()
+            + context
+                .messages
+                .iter()
+                .map(|m| m.content.split_whitespace().count())
+                .sum::<usize>()
+            + prompt.split_whitespace().count()) as u64,
+        completion_tokens: content.split_whitespace().count() as u64,
+    };
+    context.push(Message::user(prompt));
+    context.push(Message::assistant(content.clone()));
+    if debug {
+        return Ok(GenerateOutput::text(content, context).with_usage(usage));
+    }
+    match Suggestion::parse(content.as_str()) {
+        Some(s) => Ok(GenerateOutput::suggestion(s, context).with_usage(usage)),
+        None => Err(BottError::MockErr(BottMockError::UnableToGetResponse)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = r#"
+responses:
+  - pattern: "(?i)list.*files"
+    response: |
+      Sure:
+      ```bash
+      ls -la
+      ```
+  - pattern: "(?i)clean"
+    response: '{"command": "rm -rf build", "explanation": "Deletes the build directory", "destructive": true}'
+  - pattern: "sandwich"
+    response: "I can only help with commands"
+"#;
+
+    fn ask(script: &MockScript, prompt: &str, context: Conversation) -> BottResult<GenerateOutput> {
+        complete(
+            script,
+            prompt.to_string(),
+            String::from("be helpful"),
+            context,
+            false,
+        )
+    }
+
+    #[test]
+    fn answers_the_first_matching_response() {
+        let script = MockScript::parse(SCRIPT).unwrap();
+        let history = Conversation::new(vec![
+            Message::user(String::from("show branches")),
+            Message::assistant(String::from("```bash\ngit branch\n```")),
+        ]);
+        let output = ask(&script, "List all files", history).unwrap();
+        assert_eq!(output.answer, "ls -la");
+        assert_eq!(output.context.messages.len(), 4);
+        assert_eq!(output.context.messages[2].content, "List all files");
+        assert_eq!(output.usage.model, "mock");
+        assert_eq!(output.usage.completion_tokens, 5);
+
+        let output = ask(&script, "clean the build", Conversation::default()).unwrap();
+        assert_eq!(output.answer, "rm -rf build");
+        assert!(output.suggestion.unwrap().destructive);
+    }
+
+    #[test]
+    fn reports_unanswerable_prompts() {
+        let script = MockScript::parse(SCRIPT).unwrap();
+        assert!(matches!(
+            ask(&script, "make me a sandwich", Conversation::default()),
+            Err(BottError::MockErr(BottMockError::UnableToGetResponse))
+        ));
+        match ask(&script, "what time is it?", Conversation::default()) {
+            Err(BottError::MockErr(BottMockError::NoMatch(p))) => {
+                assert_eq!(p, "what time is it?")
+            }
+            _ => panic!("expected no match"),
+        }
+        // a response without a pattern answers everything
+        let catch_all =
+            MockScript::parse(r#"{"responses": [{"response": "```bash\ndate\n```"}]}"#).unwrap();
+        let output = ask(&catch_all, "what time is it?", Conversation::default()).unwrap();
+        assert_eq!(output.answer, "date");
+    }
+
+    #[test]
+    fn rejects_invalid_scripts() {
+        assert!(matches!(
+            MockScript::parse("responses: [{pattern: \"(\", response: ls}]"),
+            Err(BottError::MockErr(BottMockError::InvalidScript(_)))
+        ));
+        assert!(matches!(
+            MockScript::parse("answers: []"),
+            Err(BottError::MockErr(BottMockError::InvalidScript(_)))
+        ));
+    }
+
+    #[test]
+    fn parses_the_demo_script() {
+        let contents =
+            fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/mock.yaml")).unwrap();
+        let script = MockScript::parse(&contents).unwrap();
+        assert!(ask(&script, "list files by size", Conversation::default()).is_ok());
+    }
+}
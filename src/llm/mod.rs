@@ -1,238 +1,555 @@
+pub mod anthropic;
+pub mod azure;
+pub mod cache;
+pub mod conversation;
+pub mod doctor;
+pub mod gemini;
+pub mod http;
+pub mod llamacpp;
+pub mod local;
+pub mod mock;
+pub mod models;
 pub mod ollama;
 pub mod openai;
+pub mod prompt;
+pub mod suggestion;
+#[cfg(test)]
+mod test_server;
+pub mod usage;
 
 use crate::config::BottConfig;
+use crate::context::PromptContext;
+use crate::errors::BottError;
+use crate::llm::anthropic::{
+    check_health as anthropic_check_health, generate as anthropic_generate,
+    generate_with_context as anthropic_generate_with_context,
+};
+use crate::llm::azure::{
+    check_health as azure_check_health, generate as azure_generate,
+    generate_with_context as azure_generate_with_context,
+};
+use crate::llm::cache::{format_age, ResponseCache};
+use crate::llm::conversation::Conversation;
+use crate::llm::gemini::{
+    check_health as gemini_check_health, generate as gemini_generate,
+    generate_with_context as gemini_generate_with_context,
+};
+use crate::llm::llamacpp::{
+    check_health as llamacpp_check_health, generate as llamacpp_generate,
+    generate_with_context as llamacpp_generate_with_context,
+};
+use crate::llm::local::{
+    check_health as local_check_health, generate as local_generate,
+    generate_with_context as local_generate_with_context,
+};
+use crate::llm::mock::{
+    check_health as mock_check_health, generate as mock_generate,
+    generate_with_context as mock_generate_with_context,
+};
 use crate::llm::ollama::{
-    generate as ollama_generate, print_answer_and_context as ollama_print_answer_and_context,
+    check_health as ollama_check_health, check_health_or_pull as ollama_check_health_or_pull,
+    generate as ollama_generate, generate_with_context as ollama_generate_with_context,
 };
 use crate::llm::openai::{
-    generate as openai_generate, print_answer_and_context as openai_print_answer_and_context,
+    check_health as openai_check_health, generate as openai_generate,
+    generate_with_context as openai_generate_with_context,
 };
-use crate::result::BottResult;
-use async_openai::types::{ChatCompletionRequestMessage, ChatCompletionRequestUserMessageContent};
-use base64::{
-    alphabet,
-    engine::{self, general_purpose},
-    Engine as _,
+use crate::llm::prompt::{
+    DebugVariables, PromptTemplates, PromptVariables, PROMPT_DEBUG, PROMPT_DEBUG_SYSTEM,
+    PROMPT_QUERY_SYSTEM,
 };
+use crate::llm::suggestion::Suggestion;
+use crate::llm::usage::{check_budget, record as record_usage, Usage};
+use crate::result::BottResult;
+use crate::validate::{validate_command, ValidationIssue};
+use std::future::Future;
 use std::string::ToString;
+use std::time::Duration;
 
 const LLM_OLLAMA: &str = "ollama";
 const LLM_OPENAI: &str = "openai";
+const LLM_ANTHROPIC: &str = "anthropic";
+const LLM_GEMINI: &str = "gemini";
+const LLM_AZURE: &str = "azure";
+const LLM_LLAMACPP: &str = "llamacpp";
+const LLM_LOCAL: &str = "local";
+const LLM_MOCK: &str = "mock";
+pub const LLMS: [&str; 8] = [
+    LLM_OLLAMA,
+    LLM_OPENAI,
+    LLM_ANTHROPIC,
+    LLM_GEMINI,
+    LLM_AZURE,
+    LLM_LLAMACPP,
+    LLM_LOCAL,
+    LLM_MOCK,
+];
+// how long a local server gets to show it is up before moving on to the next llm
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
 
+// The answer of any llm together with the conversation it continues
 #[derive(Debug, Clone)]
-pub struct GenerateOutputOllama {
-    answer: String,
-    context: Vec<usize>,
-}
-#[derive(Debug, Clone)]
-pub struct GenerateOutputOpenai {
+pub struct GenerateOutput {
     answer: String,
-    context: Vec<ChatCompletionRequestMessage>,
-}
-impl GenerateOutputOpenai {
-    pub fn encode_context(
-        context: &Vec<ChatCompletionRequestMessage>,
-    ) -> Vec<ChatCompletionRequestMessage> {
-        return context
-            .iter()
-            .map(|m| match m {
-                ChatCompletionRequestMessage::User(_m) => {
-                    let mut c = _m.clone();
-                    if let Some(ChatCompletionRequestUserMessageContent::Text(_c)) = c.content {
-                        c.content = Some(ChatCompletionRequestUserMessageContent::Text(
-                            general_purpose::STANDARD.encode(_c),
-                        ));
-                    }
-                    ChatCompletionRequestMessage::User(c)
-                }
-                ChatCompletionRequestMessage::System(_m) => {
-                    let mut c = _m.clone();
-                    if c.content.is_some() {
-                        c.content = Some(general_purpose::STANDARD.encode(c.content.unwrap()));
-                    }
-                    ChatCompletionRequestMessage::System(c)
-                }
-                ChatCompletionRequestMessage::Assistant(_m) => {
-                    let mut c = _m.clone();
-                    if c.content.is_some() {
-                        c.content = Some(general_purpose::STANDARD.encode(c.content.unwrap()));
-                    }
-                    ChatCompletionRequestMessage::Assistant(c)
-                }
-                ChatCompletionRequestMessage::Tool(_m) => {
-                    let mut c = _m.clone();
-                    if c.content.is_some() {
-                        c.content = Some(general_purpose::STANDARD.encode(c.content.unwrap()));
-                    }
-                    ChatCompletionRequestMessage::Tool(c)
-                }
-                ChatCompletionRequestMessage::Function(_m) => {
-                    let mut c = _m.clone();
-                    if c.content.is_some() {
-                        c.content = Some(general_purpose::STANDARD.encode(c.content.unwrap()));
-                    }
-                    ChatCompletionRequestMessage::Function(c)
-                }
-            })
-            .collect::<Vec<ChatCompletionRequestMessage>>();
-    }
-    pub fn decode_context(
-        context: &Vec<ChatCompletionRequestMessage>,
-    ) -> Vec<ChatCompletionRequestMessage> {
-        return context
-            .iter()
-            .map(|m| match m {
-                ChatCompletionRequestMessage::User(_m) => {
-                    let mut c = _m.clone();
-                    if let Some(ChatCompletionRequestUserMessageContent::Text(_c)) = c.content {
-                        c.content = Some(ChatCompletionRequestUserMessageContent::Text(
-                            String::from_utf8(general_purpose::STANDARD.decode(_c).unwrap())
-                                .unwrap(),
-                        ));
-                    }
-                    ChatCompletionRequestMessage::User(c)
-                }
-                ChatCompletionRequestMessage::System(_m) => {
-                    let mut c = _m.clone();
-                    if c.content.is_some() {
-                        c.content = Some(
-                            String::from_utf8(
-                                general_purpose::STANDARD
-                                    .decode(c.content.unwrap())
-                                    .unwrap(),
-                            )
-                            .unwrap(),
-                        );
-                    }
-                    ChatCompletionRequestMessage::System(c)
-                }
-                ChatCompletionRequestMessage::Assistant(_m) => {
-                    let mut c = _m.clone();
-                    if c.content.is_some() {
-                        c.content = Some(
-                            String::from_utf8(
-                                general_purpose::STANDARD
-                                    .decode(c.content.unwrap())
-                                    .unwrap(),
-                            )
-                            .unwrap(),
-                        );
-                    }
-                    ChatCompletionRequestMessage::Assistant(c)
-                }
-                ChatCompletionRequestMessage::Tool(_m) => {
-                    let mut c = _m.clone();
-                    if c.content.is_some() {
-                        c.content = Some(
-                            String::from_utf8(
-                                general_purpose::STANDARD
-                                    .decode(c.content.unwrap())
-                                    .unwrap(),
-                            )
-                            .unwrap(),
-                        );
-                    }
-                    ChatCompletionRequestMessage::Tool(c)
-                }
-                ChatCompletionRequestMessage::Function(_m) => {
-                    let mut c = _m.clone();
-                    if c.content.is_some() {
-                        c.content = Some(
-                            String::from_utf8(
-                                general_purpose::STANDARD
-                                    .decode(c.content.unwrap())
-                                    .unwrap(),
-                            )
-                            .unwrap(),
-                        );
-                    }
-                    ChatCompletionRequestMessage::Function(c)
-                }
-            })
-            .collect::<Vec<ChatCompletionRequestMessage>>();
-    }
-}
-
-pub enum GenerateOutput {
-    Ollama(GenerateOutputOllama),
-    Openai(GenerateOutputOpenai),
+    // what the llm told about the command, debug answers don't have one
+    suggestion: Option<Suggestion>,
+    context: Conversation,
+    usage: Usage,
 }
 impl GenerateOutput {
+    pub fn text(answer: String, context: Conversation) -> Self {
+        Self {
+            answer,
+            suggestion: None,
+            context,
+            usage: Usage::default(),
+        }
+    }
+    pub fn suggestion(suggestion: Suggestion, context: Conversation) -> Self {
+        Self {
+            answer: suggestion.command.clone(),
+            suggestion: Some(suggestion),
+            context,
+            usage: Usage::default(),
+        }
+    }
+    pub fn with_usage(self, usage: Usage) -> Self {
+        Self { usage, ..self }
+    }
     pub async fn get_output(
         llm: &str,
         query: &str,
-        distro: &str,
-        shell: &str,
+        prompt_context: &PromptContext,
         debug: bool,
     ) -> BottResult<GenerateOutput> {
-        let output = match llm {
-            LLM_OLLAMA => {
-                let _output = ollama_generate(query, distro, shell, debug).await?;
-                GenerateOutput::Ollama(_output)
+        match llm {
+            LLM_OLLAMA => ollama_generate(query, prompt_context, debug).await,
+            LLM_OPENAI => openai_generate(query, prompt_context, debug).await,
+            LLM_ANTHROPIC => anthropic_generate(query, prompt_context, debug).await,
+            LLM_GEMINI => gemini_generate(query, prompt_context, debug).await,
+            LLM_AZURE => azure_generate(query, prompt_context, debug).await,
+            LLM_LLAMACPP => llamacpp_generate(query, prompt_context, debug).await,
+            LLM_LOCAL => local_generate(query, prompt_context, debug).await,
+            LLM_MOCK => mock_generate(query, prompt_context, debug).await,
+            _ => Err(BottError::UnknownLlm(llm.to_string())),
+        }
+    }
+    // Cheap checks that tell whether an llm can answer at all, before spending time on a query
+    pub async fn check_health(llm: &str) -> BottResult<()> {
+        match llm {
+            LLM_OLLAMA => ollama_check_health(HEALTH_CHECK_TIMEOUT).await,
+            LLM_OPENAI => openai_check_health().await,
+            LLM_ANTHROPIC => anthropic_check_health().await,
+            LLM_GEMINI => gemini_check_health().await,
+            LLM_AZURE => azure_check_health().await,
+            LLM_LLAMACPP => llamacpp_check_health(HEALTH_CHECK_TIMEOUT).await,
+            LLM_LOCAL => local_check_health().await,
+            LLM_MOCK => mock_check_health().await,
+            _ => Err(BottError::UnknownLlm(llm.to_string())),
+        }
+    }
+    // Health check before asking, the one place where fixing the llm interactively is ok
+    pub async fn prepare(llm: &str) -> BottResult<()> {
+        match llm {
+            LLM_OLLAMA => ollama_check_health_or_pull(HEALTH_CHECK_TIMEOUT).await,
+            _ => GenerateOutput::check_health(llm).await,
+        }
+    }
+    pub fn answer(&self) -> &str {
+        self.answer.as_str()
+    }
+    // Continues the same conversation with a follow up prompt, used to fix invalid answers
+    pub async fn follow_up(
+        self,
+        llm: &str,
+        prompt: &str,
+        prompt_context: &PromptContext,
+    ) -> BottResult<GenerateOutput> {
+        let prompt = prompt.to_string();
+        let system_prompt = get_query_system_prompt(prompt_context)?;
+        let context = self.context;
+        match llm {
+            LLM_OLLAMA => ollama_generate_with_context(prompt, system_prompt, context, false).await,
+            LLM_OPENAI => openai_generate_with_context(prompt, system_prompt, context, false).await,
+            LLM_ANTHROPIC => {
+                anthropic_generate_with_context(prompt, system_prompt, context, false).await
             }
-            LLM_OPENAI => {
-                print!("i am here 1");
-                let _output = openai_generate(query, distro, shell, debug).await?;
-                GenerateOutput::Openai(_output)
+            LLM_GEMINI => gemini_generate_with_context(prompt, system_prompt, context, false).await,
+            LLM_AZURE => azure_generate_with_context(prompt, system_prompt, context, false).await,
+            LLM_LLAMACPP => {
+                llamacpp_generate_with_context(prompt, system_prompt, context, false).await
             }
-            _ => unimplemented!(),
-        };
-        Ok(output)
+            LLM_LOCAL => local_generate_with_context(prompt, system_prompt, context, false).await,
+            LLM_MOCK => mock_generate_with_context(prompt, system_prompt, context, false).await,
+            _ => Err(BottError::UnknownLlm(llm.to_string())),
+        }
     }
 }
-pub fn get_query_system_prompt(distro: &str, shell: &str) -> String {
-    return format!(
-        r#"
-    You are a helpful code assistant who helps people write single line bash scripts for terminal usage.Bash code must always be enclosed between ```bash and ``` tags. 
-    The bash code needs to be compatible with the users operating system and shell.
-    For your information, 
-    Operating system: {distro}
-    Shell: {shell}
-    "#,
-        distro = distro,
-        shell = shell,
-    );
+pub struct GenerateResult {
+    llm: String,
+    output: GenerateOutput,
+    issues: Vec<ValidationIssue>,
+    // llms that were skipped before one could answer
+    skipped: Vec<(String, String)>,
+    budget_warning: Option<String>,
+    // how old the answer is when it came from the cache
+    cached: Option<u64>,
 }
-
-pub fn get_debug_system_prompt(distro: &str, shell: &str) -> String {
-    return format!(
-        r#"
-    You are a helpful code assistant who helps people write single line bash scripts for terminal usage. Given an input command and the corresponding output, tell the user why the command is failing. Write your answer in a single line with newlines using `\n` and double quoutes escaped
-    For your information, 
-    Operating system: {distro}
-    Shell: {shell}
-    "#,
-        distro = distro,
-        shell = shell,
-    );
+// The prompts come from templates that users can customize with `bott! prompt edit`
+pub fn get_query_system_prompt(prompt_context: &PromptContext) -> BottResult<String> {
+    PromptTemplates::default().render(
+        PROMPT_QUERY_SYSTEM,
+        PromptVariables::from_context(prompt_context),
+    )
+}
+pub fn get_debug_system_prompt(prompt_context: &PromptContext) -> BottResult<String> {
+    PromptTemplates::default().render(
+        PROMPT_DEBUG_SYSTEM,
+        PromptVariables::from_context(prompt_context),
+    )
 }
-pub fn get_debug_prompt(input: &str, output: &str) -> String {
-    return format!(
+pub fn get_debug_prompt(input: &str, output: &str) -> BottResult<String> {
+    PromptTemplates::default().render(
+        PROMPT_DEBUG,
+        DebugVariables {
+            input: input.to_string(),
+            output: output.to_string(),
+        },
+    )
+}
+pub fn get_repair_prompt(command: &str, issues: &[ValidationIssue], structured: bool) -> String {
+    let problems = issues
+        .iter()
+        .map(|i| format!("- {}", i))
+        .collect::<Vec<String>>()
+        .join("\n");
+    // the fixed answer has to come back in the format the llm was asked for
+    let format = if structured {
+        "Reply only with the JSON object of the fixed command, with the same fields as before."
+    } else {
+        "Bash code must always be enclosed between ```bash and ``` tags."
+    };
+    format!(
         r#"
-    input: {input}
-    output: {output}
+    The command you suggested, `{command}`, failed these checks on my system:
+    {problems}
+    Fix the command so that it works on my system. {format}
     "#,
-        input = input,
-        output = output,
-    );
+        command = command,
+        problems = problems,
+        format = format,
+    )
+}
+// Whether the llm was asked for json answers, see `bott! config set -k openai:structured`
+pub fn is_structured(config: &BottConfig, llm: &str) -> bool {
+    match llm {
+        LLM_OLLAMA => config.get_ollama_options().generation.structured,
+        LLM_OPENAI => config.get_openai_options().generation.structured,
+        LLM_AZURE => config.get_azure_options().generation.structured,
+        _ => None,
+    }
+    .unwrap_or_default()
 }
-pub async fn generate(
-    query: &str,
-    distro: &str,
-    shell: &str,
-    debug: bool,
-) -> BottResult<GenerateOutput> {
+pub fn get_llms(llm_list: &str) -> Vec<&str> {
+    llm_list
+        .split(',')
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<&str>>()
+}
+// Asks the configured llms in order and returns the first answer. `prepare` checks an llm
+// can answer at all and `ask` gets its answer, which has `timeout` to arrive
+async fn get_first_output<'a, P, A>(
+    llms: &[&'a str],
+    timeout: Duration,
+    prepare: impl Fn(&'a str) -> P,
+    ask: impl Fn(&'a str) -> A,
+) -> BottResult<(String, GenerateOutput, Vec<(String, String)>)>
+where
+    P: Future<Output = BottResult<()>>,
+    A: Future<Output = BottResult<GenerateOutput>>,
+{
+    let mut failures: Vec<(String, BottError)> = vec![];
+    for llm in llms {
+        let attempt = async {
+            prepare(llm).await?;
+            match tokio::time::timeout(timeout, ask(llm)).await {
+                Ok(output) => output,
+                Err(_) => Err(BottError::LlmTimeout(timeout)),
+            }
+        };
+        match attempt.await {
+            Ok(output) => {
+                let skipped = failures
+                    .into_iter()
+                    .map(|(l, e)| (l, e.to_string()))
+                    .collect::<Vec<(String, String)>>();
+                return Ok((llm.to_string(), output, skipped));
+            }
+            Err(e) => failures.push((llm.to_string(), e)),
+        }
+    }
+    // a single llm keeps its own error, it is more helpful than a summary
+    if failures.len() == 1 {
+        return Err(failures.pop().unwrap().1);
+    }
+    Err(BottError::NoLlmAvailable(
+        failures
+            .into_iter()
+            .map(|(l, e)| (l, e.to_string()))
+            .collect::<Vec<(String, String)>>(),
+    ))
+}
+pub async fn generate(query: &str, debug: bool, no_cache: bool) -> BottResult<GenerateResult> {
     let mut config: BottConfig = BottConfig::load()?;
-    let llm = config.get_key("llm")?.unwrap_or("".to_string());
-    let output = GenerateOutput::get_output(llm.as_str(), query, distro, shell, debug).await?;
-    return Ok(output);
+    let llm_list = config.get_key("llm")?.unwrap_or("".to_string());
+    let llms = get_llms(&llm_list);
+    if llms.is_empty() {
+        return Err(BottError::UnknownLlm(llm_list));
+    }
+    let timeout = Duration::from_secs(config.get_llm_timeout() as u64);
+    let prompt_context = PromptContext::collect(&config, query)?;
+    // llms that answer in json see the examples as json, debug answers are always text
+    let structured_context = prompt_context.clone().with_structured(true);
+    let structured_llms = llms
+        .iter()
+        .filter(|l| !debug && is_structured(&config, l))
+        .map(|l| l.to_string())
+        .collect::<Vec<String>>();
+    let context_for = |llm: &str| match structured_llms.iter().any(|l| l == llm) {
+        true => &structured_context,
+        false => &prompt_context,
+    };
+    // debug answers depend on the last run, they aren't worth keeping
+    let cache = ResponseCache::from_config(&config);
+    let mut cache_keys: Vec<(String, String)> = vec![];
+    if !debug && cache.is_enabled() {
+        let history = Conversation::load();
+        for llm in &llms {
+            let system_prompt = get_query_system_prompt(context_for(llm))?;
+            let key = ResponseCache::get_key(&mut config, llm, &system_prompt, &history, query);
+            cache_keys.push((llm.to_string(), key));
+        }
+    }
+    if !no_cache {
+        if let Some(hit) = cache_keys.iter().find_map(|(_, key)| cache.get(key)) {
+            let issues =
+                validate_command(hit.output.answer(), prompt_context.system.shell.as_str());
+            return Ok(GenerateResult {
+                llm: hit.llm,
+                output: hit.output,
+                issues,
+                skipped: vec![],
+                budget_warning: None,
+                cached: Some(hit.age),
+            });
+        }
+    }
+    let budget_warning = check_budget(&config)?;
+    let (llm, mut output, skipped) =
+        get_first_output(&llms, timeout, GenerateOutput::prepare, |llm| {
+            GenerateOutput::get_output(llm, query, context_for(llm), debug)
+        })
+        .await?;
+    // usage is bookkeeping, failing to store it shouldn't cost the user the answer
+    let _ = record_usage(&config, llm.as_str(), &output.usage);
+    if debug {
+        return Ok(GenerateResult {
+            llm,
+            output,
+            issues: vec![],
+            skipped,
+            budget_warning,
+            cached: None,
+        });
+    }
+    let mut issues = validate_command(output.answer(), prompt_context.system.shell.as_str());
+    if !issues.is_empty() {
+        // give the model a single chance to fix its answer before the user sees it,
+        // falling back to the original answer and its warnings if that fails
+        let prompt = get_repair_prompt(output.answer(), &issues, structured_llms.contains(&llm));
+        let repair =
+            output
+                .clone()
+                .follow_up(llm.as_str(), prompt.as_str(), context_for(llm.as_str()));
+        if let Ok(Ok(repaired)) = tokio::time::timeout(timeout, repair).await {
+            let _ = record_usage(&config, llm.as_str(), &repaired.usage);
+            output = repaired;
+            issues = validate_command(output.answer(), prompt_context.system.shell.as_str());
+        }
+    }
+    // answers that still fail the checks are asked again next time
+    if issues.is_empty() {
+        if let Some((_, key)) = cache_keys.iter().find(|(l, _)| *l == llm) {
+            let _ = cache.put(key, llm.as_str(), &output);
+        }
+    }
+    Ok(GenerateResult {
+        llm,
+        output,
+        issues,
+        skipped,
+        budget_warning,
+        cached: None,
+    })
+}
+pub fn print_answer_and_context(result: GenerateResult) {
+    print!(
+        "<ANSWER>{answer}</ANSWER><CONTEXT>{context}</CONTEXT>",
+        answer = result.output.answer.trim(),
+        context = result.output.context.encode()
+    );
+    print!(
+        "<CACHED>{cached}</CACHED>",
+        cached = result.cached.map(format_age).unwrap_or_default()
+    );
+    let suggestion = result.output.suggestion.clone().unwrap_or_default();
+    print!(
+        "<EXPLANATION>{explanation}</EXPLANATION><ASSUMPTIONS>{assumptions}</ASSUMPTIONS>",
+        explanation = suggestion.explanation.trim(),
+        assumptions = suggestion.assumptions.join("\n")
+    );
+    let mut notes: Vec<String> = vec![];
+    if suggestion.requires_sudo {
+        notes.push(String::from("the command needs root privileges"));
+    }
+    if suggestion.destructive {
+        notes.push(String::from(
+            "the command deletes or overwrites data, check it before running it",
+        ));
+    }
+    print!(
+        "<LLM>{llm}</LLM><WARNINGS>{warnings}</WARNINGS>",
+        llm = result.llm,
+        warnings = get_warnings(&result, notes)
+    );
 }
-pub fn print_answer_and_context(output: GenerateOutput) -> BottResult<()> {
-    match output {
-        GenerateOutput::Ollama(o) => ollama_print_answer_and_context(o),
-        GenerateOutput::Openai(o) => openai_print_answer_and_context(o),
-        _ => unimplemented!(),
+fn get_warnings(result: &GenerateResult, notes: Vec<String>) -> String {
+    result
+        .skipped
+        .iter()
+        .map(|(l, e)| format!("{} could not answer ({}), answered by {}", l, e, result.llm))
+        .chain(result.budget_warning.clone())
+        .chain(notes)
+        .chain(result.issues.iter().map(|i| i.to_string()))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::{BottOllamaError, BottOpenaiError};
+    use std::sync::Mutex;
+
+    const TIMEOUT: Duration = Duration::from_millis(200);
+
+    fn answer(command: &str) -> GenerateOutput {
+        GenerateOutput::text(command.to_string(), Conversation::default())
+    }
+    // ollama isn't running, openai fails, llamacpp hangs and the mock answers
+    async fn prepare(llm: &str) -> BottResult<()> {
+        match llm {
+            LLM_OLLAMA => Err(BottError::OllamaErr(BottOllamaError::NotRunning)),
+            _ => Ok(()),
+        }
+    }
+    async fn ask(llm: &str) -> BottResult<GenerateOutput> {
+        match llm {
+            LLM_OPENAI => Err(BottError::OpenaiErr(BottOpenaiError::InvalidResponse)),
+            LLM_LLAMACPP => {
+                tokio::time::sleep(TIMEOUT * 10).await;
+                Ok(answer("too late"))
+            }
+            _ => Ok(answer(format!("echo {}", llm).as_str())),
+        }
+    }
+
+    #[test]
+    fn asks_for_repairs_in_the_answer_format() {
+        let issues = vec![ValidationIssue::MissingExecutable(String::from("exa"))];
+        let prompt = get_repair_prompt("exa -l", &issues, false);
+        assert!(prompt.contains("`exa -l`"));
+        assert!(prompt.contains("- `exa` is not found on $PATH"));
+        assert!(prompt.contains("```bash"));
+        let prompt = get_repair_prompt("exa -l", &issues, true);
+        assert!(prompt.contains("JSON object"));
+        assert!(!prompt.contains("```bash"));
+    }
+
+    #[tokio::test]
+    async fn answers_with_the_first_healthy_llm() {
+        let asked: Mutex<Vec<String>> = Mutex::new(vec![]);
+        let (llm, output, skipped) = get_first_output(
+            &[LLM_OLLAMA, LLM_MOCK, LLM_OPENAI],
+            TIMEOUT,
+            prepare,
+            |llm| {
+                asked.lock().unwrap().push(llm.to_string());
+                ask(llm)
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(llm, LLM_MOCK);
+        assert_eq!(output.answer(), "echo mock");
+        // an unhealthy llm is never asked, and the ones after the answer aren't either
+        assert_eq!(*asked.lock().unwrap(), vec![LLM_MOCK]);
+        assert_eq!(
+            skipped,
+            vec![(
+                String::from(LLM_OLLAMA),
+                String::from("Ollama not running?")
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn moves_on_after_errors_and_timeouts() {
+        let (llm, output, skipped) =
+            get_first_output(&[LLM_OPENAI, LLM_LLAMACPP, LLM_MOCK], TIMEOUT, prepare, ask)
+                .await
+                .unwrap();
+        assert_eq!(llm, LLM_MOCK);
+        assert_eq!(output.answer(), "echo mock");
+        let reasons = skipped
+            .iter()
+            .map(|(l, _)| l.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(reasons, vec![LLM_OPENAI, LLM_LLAMACPP]);
+        assert_eq!(skipped[1].1, "No answer after 0.2 seconds");
+
+        let result = GenerateResult {
+            llm,
+            output,
+            issues: vec![],
+            skipped,
+            budget_warning: None,
+            cached: None,
+        };
+        let warnings = get_warnings(&result, vec![]);
+        assert!(warnings.starts_with("openai could not answer ("));
+        assert!(warnings.ends_with(
+            "llamacpp could not answer (No answer after 0.2 seconds), answered by mock"
+        ));
+    }
+
+    #[tokio::test]
+    async fn reports_every_failure() {
+        match get_first_output(&[LLM_OLLAMA, LLM_OPENAI], TIMEOUT, prepare, ask).await {
+            Err(BottError::NoLlmAvailable(failures)) => {
+                let llms = failures
+                    .iter()
+                    .map(|(l, _)| l.as_str())
+                    .collect::<Vec<&str>>();
+                assert_eq!(llms, vec![LLM_OLLAMA, LLM_OPENAI]);
+            }
+            _ => panic!("expected every llm to fail"),
+        }
+        // a single llm keeps its own error
+        assert!(matches!(
+            get_first_output(&[LLM_OLLAMA], TIMEOUT, prepare, ask).await,
+            Err(BottError::OllamaErr(BottOllamaError::NotRunning))
+        ));
     }
-    Ok(())
 }
@@ -0,0 +1,234 @@
+use crate::config::BottConfig;
+use crate::errors::{BottAnthropicError, BottError, BottGeminiError, BottOpenaiError};
+use crate::llm::anthropic::{list_models as anthropic_list_models, ANTHROPIC_URL};
+use crate::llm::gemini::{list_models as gemini_list_models, GEMINI_URL};
+use crate::llm::http::HttpPolicy;
+use crate::llm::ollama::{
+    format_size, list_models as ollama_list_models, show_model as ollama_show_model, OLLAMA_URL,
+};
+use crate::llm::openai::list_models as openai_list_models;
+use crate::llm::{get_llms, LLM_ANTHROPIC, LLM_GEMINI, LLM_OLLAMA, LLM_OPENAI};
+use crate::result::BottResult;
+use async_openai::config::OpenAIConfig;
+use dialoguer::{theme::ColorfulTheme, Select};
+
+// What a provider tells about one of its models, most of it only Ollama knows
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelInfo {
+    pub name: String,
+    pub size: Option<u64>,
+    pub family: Option<String>,
+    pub parameters: Option<String>,
+    pub quantization: Option<String>,
+    pub context_length: Option<u64>,
+    pub owned_by: Option<String>,
+}
+
+// Without --llm the models of the first configured llm are used
+fn get_llm(config: &mut BottConfig, llm: Option<&str>) -> BottResult<String> {
+    if let Some(l) = llm {
+        return Ok(l.to_string());
+    }
+    let llm_list = config.get_key("llm")?.unwrap_or_default();
+    match get_llms(&llm_list).first() {
+        Some(l) => Ok(l.to_string()),
+        None => Err(BottError::UnknownLlm(llm_list)),
+    }
+}
+// Only these llms have a model to choose, the others are set up with a deployment or a file
+fn get_model_key(llm: &str) -> BottResult<String> {
+    match llm {
+        LLM_OLLAMA | LLM_OPENAI | LLM_ANTHROPIC | LLM_GEMINI => Ok(format!("{}:model", llm)),
+        _ => Err(BottError::ModelsUnsupported(llm.to_string())),
+    }
+}
+fn get_api_key(config: &mut BottConfig, llm: &str) -> BottResult<String> {
+    let missing = match llm {
+        LLM_OPENAI => BottError::OpenaiErr(BottOpenaiError::MissingApiKey),
+        LLM_ANTHROPIC => BottError::AnthropicErr(BottAnthropicError::MissingApiKey),
+        _ => BottError::GeminiErr(BottGeminiError::MissingApiKey),
+    };
+    match config.get_key(format!("{}:api_key", llm).as_str()) {
+        Ok(Some(k)) => Ok(k),
+        _ => Err(missing),
+    }
+}
+async fn get_models(config: &mut BottConfig, llm: &str) -> BottResult<Vec<ModelInfo>> {
+    get_model_key(llm)?;
+    let policy = HttpPolicy::from_config(config);
+    match llm {
+        LLM_OLLAMA => ollama_list_models(&policy, OLLAMA_URL).await,
+        LLM_OPENAI => {
+            let api_key = get_api_key(config, llm)?;
+            openai_list_models(&policy, &OpenAIConfig::new().with_api_key(api_key)).await
+        }
+        LLM_ANTHROPIC => {
+            let api_key = get_api_key(config, llm)?;
+            anthropic_list_models(&policy, ANTHROPIC_URL, api_key.as_str()).await
+        }
+        _ => {
+            let api_key = get_api_key(config, llm)?;
+            gemini_list_models(&policy, GEMINI_URL, api_key.as_str()).await
+        }
+    }
+}
+
+fn render_table(models: &[ModelInfo], current: &str) -> String {
+    let optional = |v: &Option<String>| v.clone().unwrap_or(String::from("-"));
+    let mut rows = vec![[
+        String::from("NAME"),
+        String::from("SIZE"),
+        String::from("FAMILY"),
+        String::from("PARAMETERS"),
+        String::from("QUANTIZATION"),
+        String::from("CONTEXT"),
+    ]];
+    for model in models {
+        let marker = if model.name == current { "* " } else { "  " };
+        rows.push([
+            format!("{}{}", marker, model.name),
+            optional(&model.size.map(format_size)),
+            optional(&model.family),
+            optional(&model.parameters),
+            optional(&model.quantization),
+            optional(&model.context_length.map(|c| c.to_string())),
+        ]);
+    }
+    rows[0][0] = format!("  {}", rows[0][0]);
+    // columns nobody has a value for are left out, hosted apis mostly only give names
+    let columns = (0..6)
+        .filter(|&c| c == 0 || rows[1..].iter().any(|r| r[c] != "-"))
+        .collect::<Vec<usize>>();
+    let widths = columns
+        .iter()
+        .map(|&c| rows.iter().map(|r| r[c].chars().count()).max().unwrap_or(0))
+        .collect::<Vec<usize>>();
+    rows.iter()
+        .map(|r| {
+            columns
+                .iter()
+                .zip(widths.iter())
+                .map(|(&c, &w)| format!("{:<w$}", r[c], w = w))
+                .collect::<Vec<String>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+fn render_details(model: &ModelInfo) -> String {
+    let details = [
+        ("Size", model.size.map(format_size)),
+        ("Family", model.family.clone()),
+        ("Parameters", model.parameters.clone()),
+        ("Quantization", model.quantization.clone()),
+        (
+            "Context length",
+            model.context_length.map(|c| c.to_string()),
+        ),
+        ("Owned by", model.owned_by.clone()),
+    ];
+    let mut lines = vec![format!("Name: {}", model.name)];
+    for (label, value) in details {
+        if let Some(v) = value {
+            lines.push(format!("{}: {}", label, v));
+        }
+    }
+    lines.join("\n")
+}
+
+// The installed or available models, the one bott uses is marked with a *
+pub async fn list(llm: Option<&str>) -> BottResult<String> {
+    let mut config = BottConfig::load()?;
+    let llm = get_llm(&mut config, llm)?;
+    let models = get_models(&mut config, llm.as_str()).await?;
+    let current = config
+        .get_key(get_model_key(llm.as_str())?.as_str())?
+        .unwrap_or_default();
+    Ok(render_table(&models, current.as_str()))
+}
+pub async fn show(llm: Option<&str>, name: &str) -> BottResult<String> {
+    let mut config = BottConfig::load()?;
+    let llm = get_llm(&mut config, llm)?;
+    if llm == LLM_OLLAMA {
+        let policy = HttpPolicy::from_config(&config);
+        return Ok(render_details(
+            &ollama_show_model(&policy, OLLAMA_URL, name).await?,
+        ));
+    }
+    match get_models(&mut config, llm.as_str())
+        .await?
+        .into_iter()
+        .find(|m| m.name == name)
+    {
+        Some(m) => Ok(render_details(&m)),
+        None => Err(BottError::ModelNotFound(name.to_string())),
+    }
+}
+// Sets the model to use, asking the user to pick one when it isn't given.
+// None means the user backed out of the selection.
+pub async fn select(llm: Option<&str>, name: Option<&str>) -> BottResult<Option<String>> {
+    let mut config = BottConfig::load()?;
+    let llm = get_llm(&mut config, llm)?;
+    let key = get_model_key(llm.as_str())?;
+    let model = match name {
+        Some(n) => n.to_string(),
+        None => {
+            let models = get_models(&mut config, llm.as_str()).await?;
+            if models.is_empty() {
+                return Err(BottError::NoModels(llm));
+            }
+            let current = config.get_key(key.as_str())?.unwrap_or_default();
+            let names = models
+                .iter()
+                .map(|m| m.name.clone())
+                .collect::<Vec<String>>();
+            let selection = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!("Which {} model should bott use?", llm))
+                .items(&names)
+                .default(names.iter().position(|n| *n == current).unwrap_or(0))
+                .interact_opt()
+                .unwrap_or(None);
+            match selection {
+                Some(i) => names[i].clone(),
+                None => return Ok(None),
+            }
+        }
+    };
+    config.set_key(key.as_str(), model.as_str())?;
+    Ok(Some(format!("{} set to {}", key, model)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_only_known_columns() {
+        let models = vec![
+            ModelInfo {
+                name: String::from("gpt-4"),
+                ..ModelInfo::default()
+            },
+            ModelInfo {
+                name: String::from("gpt-4o"),
+                context_length: Some(128000),
+                ..ModelInfo::default()
+            },
+        ];
+        assert_eq!(
+            render_table(&models, "gpt-4o"),
+            "  NAME    CONTEXT\n  gpt-4   -\n* gpt-4o  128000"
+        );
+    }
+
+    #[test]
+    fn only_some_llms_have_models() {
+        assert_eq!(get_model_key("ollama").unwrap(), "ollama:model");
+        assert!(matches!(
+            get_model_key("azure"),
+            Err(BottError::ModelsUnsupported(_))
+        ));
+    }
+}
@@ -1,48 +1,209 @@
-use crate::config::BottConfig;
-use crate::errors::{BottError, BottOllamaError};
+use crate::config::{BottConfig, GenerationOptions};
+use crate::context::PromptContext;
+use crate::errors::{BottError, BottHttpError, BottOllamaError};
+use crate::llm::conversation::{Conversation, Message};
+use crate::llm::http::HttpPolicy;
+use crate::llm::models::ModelInfo;
+use crate::llm::suggestion::{get_structured_system_prompt, Suggestion};
+use crate::llm::usage::Usage;
 use crate::llm::{
-    get_debug_prompt, get_debug_system_prompt, get_query_system_prompt, GenerateOutputOllama,
+    get_debug_prompt, get_debug_system_prompt, get_query_system_prompt, GenerateOutput,
 };
 use crate::result::BottResult;
-use regex::Regex;
+use dialoguer::{theme::ColorfulTheme, Confirm};
 use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::time::Duration;
 
+pub const OLLAMA_URL: &str = "http://localhost:11434";
+// models are several gigabytes, the usual read timeout is far too short to download one
+const PULL_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+const PROGRESS_WIDTH: usize = 30;
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ModelDetails {
+    #[serde(default)]
+    family: String,
+    #[serde(default)]
+    parameter_size: String,
+    #[serde(default)]
+    quantization_level: String,
+}
 #[derive(Deserialize, Debug)]
 pub struct ModelMetadata {
     name: String,
-    size: usize,
+    #[serde(default)]
+    size: u64,
+    #[serde(default)]
+    details: ModelDetails,
+}
+#[derive(Serialize, Debug)]
+pub struct ShowRequest {
+    name: String,
+}
+#[derive(Deserialize, Debug)]
+pub struct ShowResponse {
+    #[serde(default)]
+    model_info: HashMap<String, serde_json::Value>,
 }
 #[derive(Deserialize, Debug)]
 pub struct ModelTags {
     models: Vec<ModelMetadata>,
 }
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct RequestOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_ctx: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<Vec<String>>,
+}
 #[derive(Serialize, Debug)]
-pub struct GenerateRequest {
+pub struct ChatRequest {
     model: String,
-    prompt: String,
+    messages: Vec<Message>,
     stream: bool,
-    system: String,
-    context: Vec<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<RequestOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<String>,
 }
 #[derive(Deserialize, Debug)]
-pub struct GenerateResponse {
-    model: String,
-    response: String,
-    context: Vec<usize>,
+pub struct ChatResponse {
+    message: Message,
+    // left out when the prompt was cached
+    #[serde(default)]
+    prompt_eval_count: u64,
+    #[serde(default)]
+    eval_count: u64,
 }
-pub async fn get_model() -> BottResult<String> {
-    let body: ModelTags;
-    if let Ok(req) = reqwest::get("http://localhost:11434/api/tags").await {
-        if let Ok(_body) = req.json::<ModelTags>().await {
-            body = _body;
-        } else {
-            return Err(BottError::OllamaErr(BottOllamaError::InvalidResponse));
+#[derive(Serialize, Debug)]
+pub struct PullRequest {
+    name: String,
+    stream: bool,
+}
+#[derive(Deserialize, Debug, Default)]
+pub struct PullProgress {
+    #[serde(default)]
+    pub status: String,
+    pub total: Option<u64>,
+    pub completed: Option<u64>,
+    pub error: Option<String>,
+}
+// Ollama being down shows up as a refused connection
+fn map_http_error(error: BottError) -> BottError {
+    match error {
+        BottError::HttpErr(BottHttpError::Connect(_)) => {
+            BottError::OllamaErr(BottOllamaError::NotRunning)
         }
-    } else {
-        return Err(BottError::OllamaErr(BottOllamaError::NotRunning));
+        e => e,
+    }
+}
+impl From<&GenerationOptions> for RequestOptions {
+    fn from(options: &GenerationOptions) -> Self {
+        Self {
+            temperature: options.temperature,
+            top_p: options.top_p,
+            num_predict: options.max_tokens,
+            num_ctx: options.num_ctx,
+            seed: options.seed,
+            stop: options.stop.clone(),
+        }
+    }
+}
+impl ShowResponse {
+    // the key is prefixed with the architecture, e.g. llama.context_length
+    fn get_context_length(&self) -> Option<u64> {
+        self.model_info
+            .iter()
+            .find(|(k, _)| k.ends_with(".context_length"))
+            .and_then(|(_, v)| v.as_u64())
+    }
+}
+impl ModelMetadata {
+    fn to_model_info(&self, context_length: Option<u64>) -> ModelInfo {
+        let non_empty = |s: &String| Some(s.clone()).filter(|s| !s.is_empty());
+        ModelInfo {
+            name: self.name.clone(),
+            size: Some(self.size).filter(|s| *s > 0),
+            family: non_empty(&self.details.family),
+            parameters: non_empty(&self.details.parameter_size),
+            quantization: non_empty(&self.details.quantization_level),
+            context_length,
+            ..ModelInfo::default()
+        }
+    }
+}
+async fn get_tags(policy: &HttpPolicy, base_url: &str) -> BottResult<ModelTags> {
+    let client = policy.client()?;
+    let response = policy
+        .send(client.get(format!("{}/api/tags", base_url)))
+        .await
+        .map_err(map_http_error)?;
+    match response.json::<ModelTags>().await {
+        Ok(b) => Ok(b),
+        Err(_) => Err(BottError::OllamaErr(BottOllamaError::InvalidResponse)),
+    }
+}
+async fn get_details(policy: &HttpPolicy, base_url: &str, model: &str) -> BottResult<ShowResponse> {
+    let client = policy.client()?;
+    let response = policy
+        .send(
+            client
+                .post(format!("{}/api/show", base_url))
+                .json(&ShowRequest {
+                    name: model.to_string(),
+                }),
+        )
+        .await
+        .map_err(map_http_error)?;
+    if !response.status().is_success() {
+        return Err(BottError::OllamaErr(BottOllamaError::ModelUnavailable(
+            model.to_string(),
+        )));
+    }
+    match response.json::<ShowResponse>().await {
+        Ok(b) => Ok(b),
+        Err(_) => Err(BottError::OllamaErr(BottOllamaError::InvalidResponse)),
     }
+}
+pub async fn list_models(policy: &HttpPolicy, base_url: &str) -> BottResult<Vec<ModelInfo>> {
+    let mut models: Vec<ModelInfo> = vec![];
+    for model in get_tags(policy, base_url).await?.models {
+        // only /api/show knows the context length
+        let context_length = match get_details(policy, base_url, model.name.as_str()).await {
+            Ok(d) => d.get_context_length(),
+            Err(_) => None,
+        };
+        models.push(model.to_model_info(context_length));
+    }
+    Ok(models)
+}
+pub async fn show_model(policy: &HttpPolicy, base_url: &str, name: &str) -> BottResult<ModelInfo> {
+    let tags = get_tags(policy, base_url).await?;
+    let Some(model) = tags.models.iter().find(|m| m.name == name) else {
+        return Err(BottError::OllamaErr(BottOllamaError::ModelUnavailable(
+            name.to_string(),
+        )));
+    };
+    let details = get_details(policy, base_url, name).await?;
+    Ok(model.to_model_info(details.get_context_length()))
+}
+pub async fn get_model() -> BottResult<String> {
     let mut config: BottConfig = BottConfig::load()?;
+    let policy = HttpPolicy::from_config(&config);
+    let body = get_tags(&policy, OLLAMA_URL).await?;
     let chosen_model = config.get_key("ollama:model")?.unwrap();
     if !body.models.iter().any(|m| m.name == chosen_model) {
         return Err(BottError::OllamaErr(BottOllamaError::ModelUnavailable(
@@ -51,88 +212,604 @@ pub async fn get_model() -> BottResult<String> {
     }
     Ok(chosen_model)
 }
+pub async fn check_health(timeout: Duration) -> BottResult<()> {
+    match tokio::time::timeout(timeout, get_model()).await {
+        Ok(r) => r.map(|_| ()),
+        Err(_) => Err(BottError::OllamaErr(BottOllamaError::NotRunning)),
+    }
+}
+// Same as check_health, but offers to pull a missing model instead of giving up
+pub async fn check_health_or_pull(timeout: Duration) -> BottResult<()> {
+    match check_health(timeout).await {
+        Err(BottError::OllamaErr(BottOllamaError::ModelUnavailable(model))) => {
+            offer_pull(model).await
+        }
+        r => r,
+    }
+}
+// Only asks when someone is at the terminal to answer, scripts get the usual error
+async fn offer_pull(model: String) -> BottResult<()> {
+    let unavailable = BottError::OllamaErr(BottOllamaError::ModelUnavailable(model.clone()));
+    if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+        return Err(unavailable);
+    }
+    let confirmed = Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!(
+            "{} is not installed in Ollama, do you want to pull it now?",
+            model
+        ))
+        .default(true)
+        .interact_opt()
+        .unwrap_or(None);
+    if confirmed != Some(true) {
+        return Err(unavailable);
+    }
+    let policy = HttpPolicy::load()?;
+    let mut stderr = io::stderr();
+    let result = pull_model(&policy, OLLAMA_URL, model.as_str(), |progress| {
+        let _ = write!(stderr, "\r\x1b[2K{}", render_progress(progress));
+        let _ = stderr.flush();
+    })
+    .await;
+    eprintln!();
+    result
+}
+pub async fn pull_model<F: FnMut(&PullProgress)>(
+    policy: &HttpPolicy,
+    base_url: &str,
+    model: &str,
+    mut on_progress: F,
+) -> BottResult<()> {
+    let client = policy.client()?;
+    let mut response = policy
+        .send(
+            client
+                .post(format!("{}/api/pull", base_url))
+                .timeout(PULL_TIMEOUT)
+                .json(&PullRequest {
+                    name: model.to_string(),
+                    stream: true,
+                }),
+        )
+        .await
+        .map_err(map_http_error)?;
+    if !response.status().is_success() {
+        let status = response.status().to_string();
+        let message = match response.json::<PullProgress>().await {
+            Ok(PullProgress { error: Some(e), .. }) => e,
+            _ => status,
+        };
+        return Err(BottError::OllamaErr(BottOllamaError::PullFailed(message)));
+    }
+    let mut buffer: Vec<u8> = vec![];
+    let mut success = false;
+    while let Some(chunk) = policy.chunk(&mut response).await? {
+        buffer.extend_from_slice(&chunk);
+        success |= read_pull_progress(&mut buffer, &mut on_progress)?;
+    }
+    buffer.push(b'\n');
+    success |= read_pull_progress(&mut buffer, &mut on_progress)?;
+    if !success {
+        return Err(BottError::OllamaErr(BottOllamaError::PullFailed(
+            String::from("download stopped before it finished"),
+        )));
+    }
+    Ok(())
+}
+// The pull progress is streamed as one json object per line
+// Only complete lines are decoded, a chunk can end in the middle of a multibyte character
+fn read_pull_progress<F: FnMut(&PullProgress)>(
+    buffer: &mut Vec<u8>,
+    on_progress: &mut F,
+) -> BottResult<bool> {
+    let mut success = false;
+    while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+        let line = String::from_utf8_lossy(&buffer[..end]).to_string();
+        buffer.drain(..=end);
+        if line.trim().is_empty() {
+            continue;
+        }
+        let progress = match serde_json::from_str::<PullProgress>(line.trim()) {
+            Ok(p) => p,
+            Err(_) => return Err(BottError::OllamaErr(BottOllamaError::InvalidResponse)),
+        };
+        if let Some(e) = progress.error {
+            return Err(BottError::OllamaErr(BottOllamaError::PullFailed(e)));
+        }
+        success |= progress.status == "success";
+        on_progress(&progress);
+    }
+    Ok(success)
+}
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        return format!("{} B", bytes);
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+// Layer downloads get a bar, the other steps (manifest, verifying, ...) only their status
+fn render_progress(progress: &PullProgress) -> String {
+    match progress.total {
+        Some(total) if total > 0 => {
+            let completed = progress.completed.unwrap_or(0).min(total);
+            let filled = (completed as f64 / total as f64 * PROGRESS_WIDTH as f64) as usize;
+            format!(
+                "{} [{}{}] {:>3}% {}/{}",
+                progress.status,
+                "#".repeat(filled),
+                "-".repeat(PROGRESS_WIDTH - filled),
+                completed * 100 / total,
+                format_size(completed),
+                format_size(total)
+            )
+        }
+        _ => progress.status.clone(),
+    }
+}
 
 pub async fn generate(
     query: &str,
-    distro: &str,
-    shell: &str,
+    prompt_context: &PromptContext,
     debug: bool,
-) -> BottResult<GenerateOutputOllama> {
-    let model: String = get_model().await?;
-    let context: Vec<usize>;
+) -> BottResult<GenerateOutput> {
+    let context: Conversation;
     let prompt: String;
-    let client = reqwest::Client::new();
-    let mut system_prompt = String::from("");
-    if (debug) {
+    let system_prompt: String;
+    if debug {
         let input = env::var("bott_last_run_executed_code").unwrap_or(String::from(""));
         let output = env::var("bott_last_run_output").unwrap_or(String::from(""));
-        prompt = get_debug_prompt(input.as_str(), output.as_str());
-        context = vec![];
-        system_prompt = get_debug_system_prompt(distro, shell);
+        prompt = get_debug_prompt(input.as_str(), output.as_str())?;
+        context = Conversation::default();
+        system_prompt = get_debug_system_prompt(prompt_context)?;
     } else {
         prompt = String::from(query);
-        context = get_context();
-        system_prompt = get_query_system_prompt(distro, shell);
-    }
-    let body: GenerateResponse;
-    if let Ok(req) = client
-        .post("http://localhost:11434/api/generate")
-        .json(&GenerateRequest {
-            model: model,
-            prompt: prompt,
-            stream: false,
-            system: system_prompt,
-            context: context,
-        })
-        .send()
-        .await
-    {
-        if let Ok(_body) = req.json::<GenerateResponse>().await {
-            body = _body;
+        context = Conversation::load();
+        system_prompt = get_query_system_prompt(prompt_context)?;
+    }
+    generate_with_context(prompt, system_prompt, context, debug).await
+}
+pub async fn generate_with_context(
+    prompt: String,
+    system_prompt: String,
+    context: Conversation,
+    debug: bool,
+) -> BottResult<GenerateOutput> {
+    let model: String = get_model().await?;
+    let config = BottConfig::load()?;
+    let policy = HttpPolicy::from_config(&config);
+    complete(
+        &policy,
+        OLLAMA_URL,
+        model,
+        &config.get_ollama_options().generation,
+        prompt,
+        system_prompt,
+        context,
+        debug,
+    )
+    .await
+}
+#[allow(clippy::too_many_arguments)]
+pub async fn complete(
+    policy: &HttpPolicy,
+    base_url: &str,
+    model: String,
+    generation: &GenerationOptions,
+    prompt: String,
+    system_prompt: String,
+    mut context: Conversation,
+    debug: bool,
+) -> BottResult<GenerateOutput> {
+    context.push(Message::user(prompt));
+    // debug answers are free text
+    let structured = !debug && generation.structured.unwrap_or_default();
+    let mut messages = vec![Message {
+        role: String::from("system"),
+        content: if structured {
+            get_structured_system_prompt(system_prompt)
         } else {
-            return Err(BottError::OllamaErr(BottOllamaError::InvalidResponse));
-        }
-    } else {
-        return Err(BottError::OllamaErr(BottOllamaError::NotRunning));
-    }
-    if (debug) {
-        return Ok(GenerateOutputOllama {
-            answer: String::from(body.response),
-            context: body.context,
-        });
-    }
-    let re = Regex::new(r"```bash(?P<bash_code>[\s\S]*?)```").unwrap();
-    let matches = re.captures(body.response.as_str());
-    return match matches {
-        Some(c) => Ok(GenerateOutputOllama {
-            answer: String::from(&c["bash_code"]).trim().to_string(),
-            context: body.context,
-        }),
-        None => Err(BottError::OllamaErr(BottOllamaError::UnableToGetResponse)),
+            system_prompt
+        },
+    }];
+    messages.extend(context.messages.iter().cloned());
+    let client = policy.client()?;
+    // without settings the parameters from the model's modelfile apply
+    let options =
+        Some(RequestOptions::from(generation)).filter(|o| *o != RequestOptions::default());
+    let response = policy
+        .send_generation(
+            client
+                .post(format!("{}/api/chat", base_url))
+                .json(&ChatRequest {
+                    model: model.clone(),
+                    messages,
+                    stream: false,
+                    options,
+                    keep_alive: generation.keep_alive.clone(),
+                    format: Some(String::from("json")).filter(|_| structured),
+                }),
+        )
+        .await
+        .map_err(map_http_error)?;
+    // ollama answers 404 for models that aren't pulled
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(BottError::OllamaErr(BottOllamaError::ModelUnavailable(
+            model,
+        )));
+    }
+    if !response.status().is_success() {
+        return Err(BottError::OllamaErr(BottOllamaError::UnknownError(
+            response.status().to_string(),
+        )));
+    }
+    let body: ChatResponse = match response.json::<ChatResponse>().await {
+        Ok(b) => b,
+        Err(_) => return Err(BottError::OllamaErr(BottOllamaError::InvalidResponse)),
+    };
+    let usage = Usage {
+        model,
+        prompt_tokens: body.prompt_eval_count,
+        completion_tokens: body.eval_count,
     };
+    let content = body.message.content;
+    context.push(Message::assistant(content.clone()));
+    if debug {
+        return Ok(GenerateOutput::text(content, context).with_usage(usage));
+    }
+    match Suggestion::parse(content.as_str()) {
+        Some(s) => Ok(GenerateOutput::suggestion(s, context).with_usage(usage)),
+        None => Err(BottError::OllamaErr(BottOllamaError::UnableToGetResponse)),
+    }
 }
-pub fn get_context() -> Vec<usize> {
-    let context_env = env::var("bott_context").unwrap_or(String::from(""));
-    let context_strings = context_env.split(" ").collect::<Vec<&str>>();
-    let mut context: Vec<usize> = vec![];
-    if !context_strings.get(0).unwrap().is_empty() {
-        context = context_strings
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::test_server::{TestResponse, TestServer};
+
+    #[tokio::test]
+    async fn pull_reports_layer_progress() {
+        let body = [
+            r#"{"status":"pulling manifest"}"#,
+            r#"{"status":"pulling 3a43f93b78ec","digest":"sha256:3a43f93b78ec","total":2000,"completed":500}"#,
+            r#"{"status":"pulling 3a43f93b78ec","digest":"sha256:3a43f93b78ec","total":2000,"completed":2000}"#,
+            r#"{"status":"verifying sha256 digest"}"#,
+            r#"{"status":"success"}"#,
+        ]
+        .join("\n");
+        let server = TestServer::start(200, "application/x-ndjson", &body).await;
+        let mut rendered: Vec<String> = vec![];
+        pull_model(&HttpPolicy::default(), &server.url, "codellama:7b", |p| {
+            rendered.push(render_progress(p))
+        })
+        .await
+        .unwrap();
+        assert_eq!(rendered.len(), 5);
+        assert_eq!(
+            rendered[1],
+            "pulling 3a43f93b78ec [#######-----------------------]  25% 500 B/2.0 KB"
+        );
+        assert_eq!(rendered[4], "success");
+
+        let requests = server.requests();
+        assert!(requests[0].request_line.starts_with("POST /api/pull"));
+        assert_eq!(requests[0].json()["name"], "codellama:7b");
+    }
+
+    #[tokio::test]
+    async fn pull_surfaces_errors() {
+        let body = r#"{"status":"pulling manifest"}
+{"error":"pull model manifest: file does not exist"}"#;
+        let server = TestServer::start(200, "application/x-ndjson", body).await;
+        let result = pull_model(&HttpPolicy::default(), &server.url, "nope", |_| {}).await;
+        match result {
+            Err(BottError::OllamaErr(BottOllamaError::PullFailed(m))) => {
+                assert_eq!(m, "pull model manifest: file does not exist")
+            }
+            _ => panic!("expected a pull error"),
+        }
+    }
+
+    #[test]
+    fn decodes_progress_split_across_chunks() {
+        let body = "{\"status\":\"pulling manifest\"}\r\n\n{\"status\":\"tête 🦙\"}\n{\"status\":\"success\"}";
+        let mut buffer: Vec<u8> = vec![];
+        let mut statuses: Vec<String> = vec![];
+        let mut success = false;
+        for byte in body.as_bytes() {
+            buffer.push(*byte);
+            success |= read_pull_progress(&mut buffer, &mut |p: &PullProgress| {
+                statuses.push(p.status.clone())
+            })
+            .unwrap();
+        }
+        assert!(!success);
+        buffer.push(b'\n');
+        success |= read_pull_progress(&mut buffer, &mut |p: &PullProgress| {
+            statuses.push(p.status.clone())
+        })
+        .unwrap();
+        assert!(success);
+        assert_eq!(statuses, vec!["pulling manifest", "tête 🦙", "success"]);
+    }
+
+    #[tokio::test]
+    async fn lists_models_with_details() {
+        let tags = serde_json::json!({
+            "models": [{
+                "name": "codellama:7b-instruct",
+                "size": 3825910662u64,
+                "details": {
+                    "format": "gguf",
+                    "family": "llama",
+                    "parameter_size": "7B",
+                    "quantization_level": "Q4_0",
+                },
+            }],
+        })
+        .to_string();
+        let show = r#"{"details":{"family":"llama"},"model_info":{"general.architecture":"llama","llama.context_length":16384}}"#;
+        let server = TestServer::start_with(vec![
+            TestResponse::new(200, "application/json", &tags),
+            TestResponse::new(200, "application/json", show),
+        ])
+        .await;
+        let models = list_models(&HttpPolicy::default(), &server.url)
+            .await
+            .unwrap();
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].name, "codellama:7b-instruct");
+        assert_eq!(models[0].size, Some(3825910662));
+        assert_eq!(models[0].family.as_deref(), Some("llama"));
+        assert_eq!(models[0].parameters.as_deref(), Some("7B"));
+        assert_eq!(models[0].quantization.as_deref(), Some("Q4_0"));
+        assert_eq!(models[0].context_length, Some(16384));
+
+        let requests = server.requests();
+        assert!(requests[1].request_line.starts_with("POST /api/show"));
+        assert_eq!(requests[1].json()["name"], "codellama:7b-instruct");
+    }
+
+    fn chat_body(content: &str) -> String {
+        serde_json::json!({
+            "model": "codellama",
+            "created_at": "2024-05-01T10:00:00Z",
+            "message": {"role": "assistant", "content": content},
+            "done": true,
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn chat_sends_history() {
+        let body = chat_body("Here:\n```bash\ngit status\n```");
+        let server = TestServer::start(200, "application/json", &body).await;
+        let history = Conversation::new(vec![
+            Message::user(String::from("show branches")),
+            Message::assistant(String::from("```bash\ngit branch\n```")),
+        ]);
+        let output = complete(
+            &HttpPolicy::default(),
+            &server.url,
+            String::from("codellama"),
+            &GenerationOptions::default(),
+            String::from("and the status?"),
+            String::from("be helpful"),
+            history,
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.answer, "git status");
+        assert_eq!(output.context.messages.len(), 4);
+        assert_eq!(output.context.messages[3].role, "assistant");
+
+        let requests = server.requests();
+        assert!(requests[0].request_line.starts_with("POST /api/chat"));
+        let json = requests[0].json();
+        let roles = json["messages"]
+            .as_array()
+            .unwrap()
             .iter()
-            .map(|x| x.parse::<usize>().unwrap())
-            .collect::<Vec<usize>>();
-    }
-    return context;
-}
-pub fn print_answer_and_context(output: GenerateOutputOllama) {
-    let context = output
-        .context
-        .iter()
-        .map(|x| x.to_string())
-        .collect::<Vec<String>>()
-        .join(" ");
-    print!(
-        "<ANSWER>{answer}</ANSWER><CONTEXT>{context}</CONTEXT>",
-        answer = output.answer.trim(),
-        context = context
-    );
+            .map(|m| m["role"].as_str().unwrap().to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(roles, vec!["system", "user", "assistant", "user"]);
+        assert_eq!(json["messages"][3]["content"], "and the status?");
+    }
+
+    #[tokio::test]
+    async fn waits_for_slow_generations() {
+        let body = chat_body("```bash\nls -la\n```");
+        let server =
+            TestServer::start_with(vec![
+                TestResponse::new(200, "application/json", &body).delay(Duration::from_millis(600))
+            ])
+            .await;
+        let policy = HttpPolicy {
+            read_timeout: Duration::from_millis(200),
+            ..HttpPolicy::default()
+        };
+        let output = complete(
+            &policy,
+            &server.url,
+            String::from("codellama"),
+            &GenerationOptions::default(),
+            String::from("list files"),
+            String::from("be helpful"),
+            Conversation::default(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.answer, "ls -la");
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn sends_generation_options() {
+        let body = chat_body("```bash\nls -la\n```");
+        let server = TestServer::start(200, "application/json", &body).await;
+        let generation = GenerationOptions {
+            temperature: Some(0.5),
+            max_tokens: Some(256),
+            seed: Some(42),
+            stop: Some(vec![String::from("\n\n")]),
+            keep_alive: Some(String::from("10m")),
+            ..GenerationOptions::default()
+        };
+        let output = complete(
+            &HttpPolicy::default(),
+            &server.url,
+            String::from("codellama"),
+            &generation,
+            String::from("list files"),
+            String::from("be helpful"),
+            Conversation::default(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.answer, "ls -la");
+        assert_eq!(output.context.messages.len(), 2);
+
+        let json = server.requests()[0].json();
+        assert_eq!(
+            json["options"],
+            serde_json::json!({"temperature": 0.5, "num_predict": 256, "seed": 42, "stop": ["\n\n"]})
+        );
+        assert_eq!(json["keep_alive"], "10m");
+    }
+
+    #[tokio::test]
+    async fn leaves_options_out_when_unset() {
+        let body = chat_body("```bash\nls\n```");
+        let server = TestServer::start(200, "application/json", &body).await;
+        complete(
+            &HttpPolicy::default(),
+            &server.url,
+            String::from("codellama"),
+            &GenerationOptions::default(),
+            String::from("list files"),
+            String::from("be helpful"),
+            Conversation::default(),
+            false,
+        )
+        .await
+        .unwrap();
+        let json = server.requests()[0].json();
+        assert!(json.get("options").is_none());
+        assert!(json.get("keep_alive").is_none());
+        assert!(json.get("format").is_none());
+    }
+
+    #[tokio::test]
+    async fn asks_for_structured_answers() {
+        let body = chat_body(
+            r#"{"command":"rm -rf build","explanation":"Deletes the build directory","assumptions":[],"requires_sudo":false,"destructive":true}"#,
+        );
+        let server = TestServer::start(200, "application/json", &body).await;
+        let generation = GenerationOptions {
+            structured: Some(true),
+            ..GenerationOptions::default()
+        };
+        let output = complete(
+            &HttpPolicy::default(),
+            &server.url,
+            String::from("codellama"),
+            &generation,
+            String::from("clean the build"),
+            String::from("be helpful"),
+            Conversation::default(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.answer, "rm -rf build");
+        let suggestion = output.suggestion.unwrap();
+        assert_eq!(suggestion.explanation, "Deletes the build directory");
+        assert!(suggestion.destructive);
+
+        let json = server.requests()[0].json();
+        assert_eq!(json["format"], "json");
+        assert!(json["messages"][0]["content"]
+            .as_str()
+            .unwrap()
+            .contains("requires_sudo"));
+    }
+
+    // Fixtures are recorded against a local ollama with BOTT_RECORD_FIXTURES=1
+    async fn chat_fixture(
+        server: &TestServer,
+        model: &str,
+        query: &str,
+    ) -> BottResult<GenerateOutput> {
+        let result = complete(
+            &HttpPolicy::default(),
+            &server.url,
+            model.to_string(),
+            &GenerationOptions::default(),
+            query.to_string(),
+            String::from("be helpful"),
+            Conversation::default(),
+            false,
+        )
+        .await;
+        server.assert_replayed();
+        result
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_answer() {
+        let server = TestServer::fixture("ollama/chat_success", OLLAMA_URL).await;
+        let output = chat_fixture(&server, "codellama:7b-instruct", "list files by size")
+            .await
+            .unwrap();
+        assert_eq!(output.answer, "ls -lS");
+        assert_eq!(output.usage.prompt_tokens, 31);
+        assert_eq!(output.usage.completion_tokens, 24);
+    }
+
+    #[tokio::test]
+    async fn replays_missing_model() {
+        let server = TestServer::fixture("ollama/chat_missing_model", OLLAMA_URL).await;
+        match chat_fixture(&server, "llama9:latest", "list files by size").await {
+            Err(BottError::OllamaErr(BottOllamaError::ModelUnavailable(m))) => {
+                assert_eq!(m, "llama9:latest")
+            }
+            _ => panic!("expected a missing model"),
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_answer_without_command() {
+        let server = TestServer::fixture("ollama/chat_no_code_block", OLLAMA_URL).await;
+        let result = chat_fixture(&server, "codellama:7b-instruct", "make me a sandwich").await;
+        assert!(matches!(
+            result,
+            Err(BottError::OllamaErr(BottOllamaError::UnableToGetResponse))
+        ));
+    }
+
+    #[tokio::test]
+    async fn replays_malformed_json() {
+        // written by hand, ollama can't be made to send this
+        let server = TestServer::replay("ollama/chat_malformed").await;
+        let result = chat_fixture(&server, "codellama:7b-instruct", "list files by size").await;
+        assert!(matches!(
+            result,
+            Err(BottError::OllamaErr(BottOllamaError::InvalidResponse))
+        ));
+    }
+
+    #[test]
+    fn formats_sizes() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(3_800_000_000), "3.8 GB");
+    }
 }
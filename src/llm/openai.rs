@@ -1,133 +1,588 @@
-use crate::config::BottConfig;
-use crate::errors::{BottError, BottOllamaError, BottOpenaiError};
+use crate::config::{BottConfig, GenerationOptions};
+use crate::context::PromptContext;
+use crate::errors::{BottError, BottOpenaiError};
+use crate::llm::conversation::{Conversation, Message, ROLE_ASSISTANT};
+use crate::llm::http::HttpPolicy;
+use crate::llm::models::ModelInfo;
+use crate::llm::suggestion::{get_structured_system_prompt, get_suggestion_schema, Suggestion};
+use crate::llm::usage::Usage;
 use crate::llm::{
-    get_debug_prompt, get_debug_system_prompt, get_query_system_prompt, GenerateOutputOpenai,
+    get_debug_prompt, get_debug_system_prompt, get_query_system_prompt, GenerateOutput,
 };
 use crate::result::BottResult;
 use async_openai::{
-    config::OpenAIConfig,
+    config::{Config, OpenAIConfig},
     types::{
         ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
         ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
-        CreateChatCompletionRequestArgs,
+        CreateChatCompletionRequestArgs, CreateChatCompletionResponse, Stop,
     },
-    Client,
 };
-use base64::{
-    alphabet,
-    engine::{self, general_purpose},
-    Engine as _,
-};
-use regex::Regex;
+use serde_derive::Deserialize;
+use serde_json::json;
 use std::env;
 
-fn remove_control_characters(input: &str) -> String {
-    input.chars().filter(|&c| c > '\u{001F}').collect()
+#[derive(Deserialize, Debug)]
+struct ErrorDetail {
+    message: String,
+    #[serde(default)]
+    code: Option<String>,
+}
+#[derive(Deserialize, Debug)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+#[derive(Deserialize, Debug)]
+struct ModelObject {
+    id: String,
+    #[serde(default)]
+    owned_by: String,
+}
+#[derive(Deserialize, Debug)]
+struct ModelList {
+    data: Vec<ModelObject>,
 }
 
-pub fn get_context(distro: &str, shell: &str, debug: bool) -> Vec<ChatCompletionRequestMessage> {
-    let mut system_prompt: String;
-    if debug {
-        system_prompt = get_debug_system_prompt(distro, shell);
+fn get_request_message(message: &Message) -> ChatCompletionRequestMessage {
+    if message.role == ROLE_ASSISTANT {
+        ChatCompletionRequestMessage::Assistant(
+            ChatCompletionRequestAssistantMessageArgs::default()
+                .content(message.content.clone())
+                .build()
+                .unwrap(),
+        )
     } else {
-        system_prompt = get_query_system_prompt(distro, shell);
-    }
-    let default_messages = vec![ChatCompletionRequestSystemMessageArgs::default()
-        .content(system_prompt)
-        .build()
-        .unwrap()];
-    let default_bott_context = serde_json::to_string(&default_messages).unwrap();
-    let mut context_env: String;
-    let mut need_to_decode = false;
-    if (debug) {
-        context_env = default_bott_context;
-    } else {
-        context_env = env::var("bott_context").unwrap_or(default_bott_context.clone());
-        if context_env.is_empty() {
-            context_env = default_bott_context
-        } else {
-            need_to_decode = true;
-        }
-    }
-    let context: Vec<ChatCompletionRequestMessage> =
-        serde_json::from_str(context_env.as_str()).unwrap();
-    if need_to_decode {
-        return GenerateOutputOpenai::decode_context(&context);
+        ChatCompletionRequestMessage::User(
+            ChatCompletionRequestUserMessageArgs::default()
+                .content(message.content.clone())
+                .build()
+                .unwrap(),
+        )
     }
-    return context;
 }
 pub async fn get_model() -> BottResult<String> {
     let mut config = BottConfig::load()?;
     let model = config.get_key("openai:model")?.unwrap();
     Ok(model)
 }
+pub async fn check_health() -> BottResult<()> {
+    let mut config = BottConfig::load()?;
+    match config.get_key("openai:api_key") {
+        Ok(Some(_)) => Ok(()),
+        _ => Err(BottError::OpenaiErr(BottOpenaiError::MissingApiKey)),
+    }
+}
 pub async fn generate(
     query: &str,
-    distro: &str,
-    shell: &str,
+    prompt_context: &PromptContext,
     debug: bool,
-) -> BottResult<GenerateOutputOpenai> {
-    let model: String = get_model().await?;
-    let mut config = BottConfig::load()?;
-    let api_key = config.get_key("openai:api_key")?.unwrap();
-    let openai_config = OpenAIConfig::new().with_api_key(api_key);
-    let mut context: Vec<ChatCompletionRequestMessage> = get_context(distro, shell, debug);
-    let mut prompt: String;
-    if (debug) {
+) -> BottResult<GenerateOutput> {
+    let context: Conversation;
+    let prompt: String;
+    let system_prompt: String;
+    if debug {
         let input = env::var("bott_last_run_executed_code").unwrap_or(String::from(""));
         let output = env::var("bott_last_run_output").unwrap_or(String::from(""));
-        prompt = get_debug_prompt(input.as_str(), output.as_str());
+        prompt = get_debug_prompt(input.as_str(), output.as_str())?;
+        context = Conversation::default();
+        system_prompt = get_debug_system_prompt(prompt_context)?;
+    } else {
+        prompt = String::from(query);
+        context = Conversation::load();
+        system_prompt = get_query_system_prompt(prompt_context)?;
+    }
+    generate_with_context(prompt, system_prompt, context, debug).await
+}
+// The response formats answers in json can be asked for, `none` leaves it to the system prompt
+pub const RESPONSE_FORMATS: [&str; 3] = ["json_schema", "json_object", "none"];
+fn get_response_format(format: &str) -> Option<serde_json::Value> {
+    match format {
+        "json_schema" => Some(json!({
+            "type": "json_schema",
+            "json_schema": {
+                "name": "suggestion",
+                "strict": true,
+                "schema": get_suggestion_schema(),
+            },
+        })),
+        "json_object" => Some(json!({"type": "json_object"})),
+        _ => None,
+    }
+}
+// Structured outputs need a recent model, older ones only know json mode and the first
+// gpt-4 models reject both. Those are still asked for json in the system prompt.
+fn get_model_response_format(model: &str) -> &'static str {
+    const JSON_SCHEMA_MODELS: [&str; 8] = [
+        "gpt-4o",
+        "chatgpt-4o",
+        "gpt-4.1",
+        "gpt-4.5",
+        "gpt-5",
+        "o1",
+        "o3",
+        "o4",
+    ];
+    const JSON_OBJECT_MODELS: [&str; 4] =
+        ["gpt-4-turbo", "gpt-4-1106", "gpt-4-0125", "gpt-3.5-turbo"];
+    // snapshots that predate structured outputs, and reasoning models without response formats
+    const NO_FORMAT_MODELS: [&str; 3] = ["gpt-4o-2024-05-13", "o1-mini", "o1-preview"];
+    let matches = |prefixes: &[&str]| prefixes.iter().any(|p| model.starts_with(p));
+    if matches(&NO_FORMAT_MODELS) {
+        "none"
+    } else if matches(&JSON_SCHEMA_MODELS) {
+        "json_schema"
+    } else if matches(&JSON_OBJECT_MODELS) {
+        "json_object"
     } else {
-        prompt = query.to_string();
+        "none"
     }
-    context.push(ChatCompletionRequestMessage::User(
-        ChatCompletionRequestUserMessageArgs::default()
-            .content(prompt)
+}
+pub async fn generate_with_context(
+    prompt: String,
+    system_prompt: String,
+    context: Conversation,
+    debug: bool,
+) -> BottResult<GenerateOutput> {
+    let model: String = get_model().await?;
+    let mut config = BottConfig::load()?;
+    let api_key = match config.get_key("openai:api_key") {
+        Ok(Some(k)) => k,
+        _ => return Err(BottError::OpenaiErr(BottOpenaiError::MissingApiKey)),
+    };
+    let openai_config = OpenAIConfig::new().with_api_key(api_key);
+    let policy = HttpPolicy::from_config(&config);
+    let response_format = get_model_response_format(&model);
+    chat(
+        &policy,
+        &openai_config,
+        model,
+        &config.get_openai_options().generation,
+        response_format,
+        prompt,
+        system_prompt,
+        context,
+        debug,
+    )
+    .await
+}
+// Runs a chat completion against any OpenAI compatible service, like Azure OpenAI.
+// The config only provides urls and headers, requests go through bott's http policy.
+// The caller knows which response format the model takes, see RESPONSE_FORMATS.
+#[allow(clippy::too_many_arguments)]
+pub async fn chat<C: Config>(
+    policy: &HttpPolicy,
+    config: &C,
+    model: String,
+    generation: &GenerationOptions,
+    response_format: &str,
+    prompt: String,
+    system_prompt: String,
+    mut context: Conversation,
+    debug: bool,
+) -> BottResult<GenerateOutput> {
+    context.push(Message::user(prompt));
+    // debug answers are free text
+    let structured = !debug && generation.structured.unwrap_or_default();
+    let system_prompt = if structured {
+        get_structured_system_prompt(system_prompt)
+    } else {
+        system_prompt
+    };
+    let mut messages = vec![ChatCompletionRequestMessage::System(
+        ChatCompletionRequestSystemMessageArgs::default()
+            .content(system_prompt)
             .build()
             .unwrap(),
-    ));
-    let client = Client::with_config(openai_config);
+    )];
+    messages.extend(context.messages.iter().map(get_request_message));
 
-    let request = CreateChatCompletionRequestArgs::default()
-        .model(model)
-        .messages(context.clone())
-        .build()
-        .unwrap();
+    let mut request = CreateChatCompletionRequestArgs::default();
+    request.model(model.clone()).messages(messages);
+    if let Some(t) = generation.temperature {
+        request.temperature(t);
+    }
+    if let Some(p) = generation.top_p {
+        request.top_p(p);
+    }
+    if let Some(m) = generation.max_tokens {
+        request.max_tokens(u16::try_from(m).unwrap_or(u16::MAX));
+    }
+    if let Some(s) = generation.seed {
+        request.seed(s);
+    }
+    if let Some(s) = &generation.stop {
+        request.stop(Stop::StringArray(s.clone()));
+    }
+    // async-openai doesn't know json schemas as a response format yet
+    let mut request = serde_json::to_value(request.build().unwrap()).unwrap();
+    if let Some(format) = get_response_format(response_format).filter(|_| structured) {
+        request["response_format"] = format;
+    }
 
-    let response = client.chat().create(request).await.unwrap();
-    let output = response.choices.get(0).unwrap();
+    let client = policy.client()?;
+    let response = policy
+        .send_generation(
+            client
+                .post(config.url("/chat/completions"))
+                .query(&config.query())
+                .headers(config.headers())
+                .json(&request),
+        )
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        return Err(match response.json::<ErrorResponse>().await {
+            Ok(e) if e.error.code.as_deref() == Some("model_not_found") => {
+                BottError::OpenaiErr(BottOpenaiError::ModelUnavailable(model))
+            }
+            Ok(e) => BottError::OpenaiErr(BottOpenaiError::ApiError(e.error.message)),
+            Err(_) => BottError::OpenaiErr(BottOpenaiError::ApiError(status.to_string())),
+        });
+    }
+    let response = match response.json::<CreateChatCompletionResponse>().await {
+        Ok(r) => r,
+        Err(_) => return Err(BottError::OpenaiErr(BottOpenaiError::InvalidResponse)),
+    };
+    let output = response
+        .choices
+        .first()
+        .ok_or(BottError::OpenaiErr(BottOpenaiError::UnableToGetResponse))?;
     let content = output.message.content.clone().unwrap_or("".to_string());
-    context.push(ChatCompletionRequestMessage::Assistant(
-        ChatCompletionRequestAssistantMessageArgs::default()
-            .content(content.clone().as_str())
-            .tool_calls(output.message.tool_calls.clone().unwrap_or(vec![]))
-            .build()
-            .unwrap(),
-    ));
+    // the requested model, responses name a dated snapshot that has no price
+    let usage = Usage {
+        model,
+        prompt_tokens: response
+            .usage
+            .as_ref()
+            .map_or(0, |u| u.prompt_tokens as u64),
+        completion_tokens: response
+            .usage
+            .as_ref()
+            .map_or(0, |u| u.completion_tokens as u64),
+    };
+    context.push(Message::assistant(content.clone()));
     if debug {
-        return Ok(GenerateOutputOpenai {
-            answer: content,
-            context,
-        });
+        return Ok(GenerateOutput::text(content, context).with_usage(usage));
     }
-    let re = Regex::new(r"```bash(?P<bash_code>[\s\S]*?)```").unwrap();
-    let matches = re.captures(content.as_str());
-
-    return match matches {
-        Some(c) => Ok(GenerateOutputOpenai {
-            answer: String::from(&c["bash_code"]).trim().to_string(),
-            context,
-        }),
+    match Suggestion::parse(content.as_str()) {
+        Some(s) => Ok(GenerateOutput::suggestion(s, context).with_usage(usage)),
         None => Err(BottError::OpenaiErr(BottOpenaiError::UnableToGetResponse)),
+    }
+}
+pub async fn list_models<C: Config>(policy: &HttpPolicy, config: &C) -> BottResult<Vec<ModelInfo>> {
+    let client = policy.client()?;
+    let response = policy
+        .send(
+            client
+                .get(config.url("/models"))
+                .query(&config.query())
+                .headers(config.headers()),
+        )
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let message = match response.json::<ErrorResponse>().await {
+            Ok(e) => e.error.message,
+            Err(_) => status.to_string(),
+        };
+        return Err(BottError::OpenaiErr(BottOpenaiError::ApiError(message)));
+    }
+    let body = match response.json::<ModelList>().await {
+        Ok(b) => b,
+        Err(_) => return Err(BottError::OpenaiErr(BottOpenaiError::InvalidResponse)),
     };
+    let mut models = body
+        .data
+        .into_iter()
+        .map(|m| ModelInfo {
+            name: m.id,
+            owned_by: Some(m.owned_by).filter(|o| !o.is_empty()),
+            ..ModelInfo::default()
+        })
+        .collect::<Vec<ModelInfo>>();
+    models.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(models)
 }
 
-pub fn print_answer_and_context(output: GenerateOutputOpenai) {
-    let encoded_context = GenerateOutputOpenai::encode_context(&output.context);
-    let context = serde_json::to_string(&encoded_context).unwrap();
-    print!(
-        "<ANSWER>{answer}</ANSWER><CONTEXT>{context}</CONTEXT>",
-        answer = output.answer.trim(),
-        context = context
-    );
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::test_server::{TestResponse, TestServer};
+    use std::time::Duration;
+
+    fn response_body(content: &str) -> String {
+        serde_json::json!({
+            "id": "chatcmpl-test",
+            "object": "chat.completion",
+            "created": 1700000000,
+            "model": "gpt-test",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": content},
+                "finish_reason": "stop",
+            }],
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn retries_and_extracts_command() {
+        let server = TestServer::start_with(vec![
+            TestResponse::new(503, "application/json", r#"{"error":{"message":"busy"}}"#),
+            TestResponse::new(
+                200,
+                "application/json",
+                &response_body("```bash\nls -la\n```"),
+            ),
+        ])
+        .await;
+        let config = OpenAIConfig::new()
+            .with_api_base(&server.url)
+            .with_api_key("test-key");
+        let policy = HttpPolicy {
+            base_delay: Duration::from_millis(1),
+            ..HttpPolicy::default()
+        };
+        let output = chat(
+            &policy,
+            &config,
+            String::from("gpt-test"),
+            &GenerationOptions::default(),
+            "json_schema",
+            String::from("list files"),
+            String::from("be helpful"),
+            Conversation::default(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.answer, "ls -la");
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1]
+            .request_line
+            .starts_with("POST /chat/completions"));
+        assert_eq!(
+            requests[1].header("authorization").unwrap(),
+            "Bearer test-key"
+        );
+        assert_eq!(requests[1].json()["model"], "gpt-test");
+        assert_eq!(requests[1].json()["messages"][0]["role"], "system");
+        assert_eq!(output.context.messages.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn surfaces_api_errors() {
+        let body =
+            r#"{"error":{"message":"Incorrect API key provided","type":"invalid_request_error"}}"#;
+        let server = TestServer::start(401, "application/json", body).await;
+        let config = OpenAIConfig::new()
+            .with_api_base(&server.url)
+            .with_api_key("bad-key");
+        let result = chat(
+            &HttpPolicy::default(),
+            &config,
+            String::from("gpt-test"),
+            &GenerationOptions::default(),
+            "json_schema",
+            String::from("list files"),
+            String::from("be helpful"),
+            Conversation::default(),
+            false,
+        )
+        .await;
+        match result {
+            Err(BottError::OpenaiErr(BottOpenaiError::ApiError(m))) => {
+                assert_eq!(m, "Incorrect API key provided")
+            }
+            _ => panic!("expected an api error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn lists_models_sorted() {
+        let body = r#"{"object":"list","data":[{"id":"gpt-4o","object":"model","created":1715367049,"owned_by":"system"},{"id":"gpt-4","object":"model","created":1687882411,"owned_by":"openai"}]}"#;
+        let server = TestServer::start(200, "application/json", body).await;
+        let config = OpenAIConfig::new()
+            .with_api_base(&server.url)
+            .with_api_key("test-key");
+        let models = list_models(&HttpPolicy::default(), &config).await.unwrap();
+        let names = models
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(names, vec!["gpt-4", "gpt-4o"]);
+        assert_eq!(models[0].owned_by.as_deref(), Some("openai"));
+        let requests = server.requests();
+        assert!(requests[0].request_line.starts_with("GET /models"));
+        assert_eq!(
+            requests[0].header("authorization").unwrap(),
+            "Bearer test-key"
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_generation_options() {
+        let server =
+            TestServer::start(200, "application/json", &response_body("```bash\nls\n```")).await;
+        let config = OpenAIConfig::new()
+            .with_api_base(&server.url)
+            .with_api_key("test-key");
+        let generation = GenerationOptions {
+            temperature: Some(0.0),
+            top_p: Some(0.5),
+            max_tokens: Some(300),
+            seed: Some(7),
+            stop: Some(vec![String::from("```\n\n")]),
+            ..GenerationOptions::default()
+        };
+        chat(
+            &HttpPolicy::default(),
+            &config,
+            String::from("gpt-test"),
+            &generation,
+            "json_schema",
+            String::from("list files"),
+            String::from("be helpful"),
+            Conversation::default(),
+            false,
+        )
+        .await
+        .unwrap();
+        let json = server.requests()[0].json();
+        assert_eq!(json["temperature"], 0.0);
+        assert_eq!(json["top_p"], 0.5);
+        assert_eq!(json["max_tokens"], 300);
+        assert_eq!(json["seed"], 7);
+        assert_eq!(json["stop"], serde_json::json!(["```\n\n"]));
+    }
+
+    #[tokio::test]
+    async fn asks_for_structured_answers() {
+        let content = r#"{"command":"sudo apt update","explanation":"Refreshes the package lists","assumptions":["the system uses apt"],"requires_sudo":true,"destructive":false}"#;
+        let server = TestServer::start(200, "application/json", &response_body(content)).await;
+        let config = OpenAIConfig::new()
+            .with_api_base(&server.url)
+            .with_api_key("test-key");
+        let generation = GenerationOptions {
+            structured: Some(true),
+            ..GenerationOptions::default()
+        };
+        let output = chat(
+            &HttpPolicy::default(),
+            &config,
+            String::from("gpt-4o-mini"),
+            &generation,
+            "json_schema",
+            String::from("update packages"),
+            String::from("be helpful"),
+            Conversation::default(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.answer, "sudo apt update");
+        let suggestion = output.suggestion.unwrap();
+        assert_eq!(suggestion.assumptions, vec!["the system uses apt"]);
+        assert!(suggestion.requires_sudo);
+
+        let json = server.requests()[0].json();
+        assert_eq!(json["response_format"]["type"], "json_schema");
+        assert_eq!(
+            json["response_format"]["json_schema"]["schema"],
+            get_suggestion_schema()
+        );
+    }
+
+    #[test]
+    fn picks_a_response_format_the_model_supports() {
+        let format = get_model_response_format;
+        assert_eq!(format("gpt-4o-mini"), "json_schema");
+        assert_eq!(format("gpt-4.1-2025-04-14"), "json_schema");
+        assert_eq!(format("o3-mini"), "json_schema");
+        assert_eq!(format("gpt-4-turbo"), "json_object");
+        assert_eq!(format("gpt-3.5-turbo-0125"), "json_object");
+        assert_eq!(format("gpt-4"), "none");
+        assert_eq!(format("gpt-4-0613"), "none");
+        assert_eq!(format("gpt-4o-2024-05-13"), "none");
+        assert_eq!(format("o1-mini"), "none");
+        assert_eq!(
+            get_response_format("json_object").unwrap()["type"],
+            "json_object"
+        );
+        assert_eq!(get_response_format("none"), None);
+    }
+
+    // Fixtures are recorded against the real api with BOTT_RECORD_FIXTURES=1,
+    // the key comes from OPENAI_API_KEY and is never written to them
+    const OPENAI_URL: &str = "https://api.openai.com/v1";
+
+    async fn chat_fixture(
+        server: &TestServer,
+        model: &str,
+        query: &str,
+    ) -> BottResult<GenerateOutput> {
+        let config = OpenAIConfig::new()
+            .with_api_base(&server.url)
+            .with_api_key(env::var("OPENAI_API_KEY").unwrap_or(String::from("test-key")));
+        let result = chat(
+            &HttpPolicy::default(),
+            &config,
+            model.to_string(),
+            &GenerationOptions::default(),
+            "json_schema",
+            query.to_string(),
+            String::from("be helpful"),
+            Conversation::default(),
+            false,
+        )
+        .await;
+        server.assert_replayed();
+        result
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_answer() {
+        let server = TestServer::fixture("openai/chat_success", OPENAI_URL).await;
+        let output = chat_fixture(&server, "gpt-4o-mini", "list files by size")
+            .await
+            .unwrap();
+        assert_eq!(output.answer, "ls -lS");
+        assert_eq!(output.usage.model, "gpt-4o-mini");
+        assert_eq!(output.usage.prompt_tokens, 19);
+        assert_eq!(output.usage.completion_tokens, 22);
+    }
+
+    #[tokio::test]
+    async fn replays_missing_model() {
+        let server = TestServer::fixture("openai/chat_missing_model", OPENAI_URL).await;
+        match chat_fixture(&server, "gpt-9", "list files by size").await {
+            Err(BottError::OpenaiErr(BottOpenaiError::ModelUnavailable(m))) => {
+                assert_eq!(m, "gpt-9")
+            }
+            _ => panic!("expected a missing model"),
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_answer_without_command() {
+        let server = TestServer::fixture("openai/chat_no_code_block", OPENAI_URL).await;
+        let result = chat_fixture(&server, "gpt-4o-mini", "make me a sandwich").await;
+        assert!(matches!(
+            result,
+            Err(BottError::OpenaiErr(BottOpenaiError::UnableToGetResponse))
+        ));
+    }
+
+    #[tokio::test]
+    async fn replays_malformed_json() {
+        // written by hand, the api can't be made to send this
+        let server = TestServer::replay("openai/chat_malformed").await;
+        let result = chat_fixture(&server, "gpt-4o-mini", "list files by size").await;
+        assert!(matches!(
+            result,
+            Err(BottError::OpenaiErr(BottOpenaiError::InvalidResponse))
+        ));
+    }
 }
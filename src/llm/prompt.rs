@@ -0,0 +1,303 @@
+use crate::config::BottConfig;
+use crate::context::examples::Example;
+use crate::context::PromptContext;
+use crate::errors::BottError;
+use crate::result::BottResult;
+use minijinja::{Environment, UndefinedBehavior};
+use serde_derive::Serialize;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+const PROMPTS_DIR: &str = "prompts";
+pub const PROMPT_QUERY_SYSTEM: &str = "query_system";
+pub const PROMPT_DEBUG_SYSTEM: &str = "debug_system";
+pub const PROMPT_DEBUG: &str = "debug";
+pub const PROMPTS: [&str; 3] = [PROMPT_QUERY_SYSTEM, PROMPT_DEBUG_SYSTEM, PROMPT_DEBUG];
+
+fn get_default(name: &str) -> &'static str {
+    match name {
+        PROMPT_QUERY_SYSTEM => include_str!("prompts/query_system.j2"),
+        PROMPT_DEBUG_SYSTEM => include_str!("prompts/debug_system.j2"),
+        _ => include_str!("prompts/debug.j2"),
+    }
+}
+
+// What a template can use. The sections are rendered the way they appear in `bott! context show`
+#[derive(Serialize, Debug)]
+pub struct PromptVariables {
+    distro: String,
+    kernel: String,
+    arch: String,
+    shell: String,
+    shell_version: Option<String>,
+    package_managers: Vec<String>,
+    cwd: String,
+    tools: Vec<String>,
+    system: String,
+    project: Option<String>,
+    history: Option<String>,
+    examples: Vec<Example>,
+    structured: bool,
+    context: String,
+}
+impl PromptVariables {
+    pub fn from_context(prompt_context: &PromptContext) -> Self {
+        let system = &prompt_context.system;
+        Self {
+            distro: system.distro.clone(),
+            kernel: system.kernel.clone(),
+            arch: system.arch.clone(),
+            shell: system.shell_name(),
+            shell_version: system.shell_version.clone(),
+            package_managers: system.package_managers.clone(),
+            cwd: env::current_dir()
+                .map(|d| d.display().to_string())
+                .unwrap_or_default(),
+            tools: prompt_context
+                .tools
+                .as_ref()
+                .map(|t| t.get_installed())
+                .unwrap_or_default(),
+            system: system.render(),
+            project: prompt_context.project.as_ref().map(|p| p.render()),
+            history: prompt_context.history.as_ref().map(|h| h.render()),
+            examples: prompt_context
+                .examples
+                .as_ref()
+                .map(|e| e.get_examples().to_vec())
+                .unwrap_or_default(),
+            structured: prompt_context.structured,
+            context: prompt_context.render(),
+        }
+    }
+}
+// The command that failed and what it printed, for the debug prompt
+#[derive(Serialize, Debug)]
+pub struct DebugVariables {
+    pub input: String,
+    pub output: String,
+}
+
+// Prompt templates, bott's own unless the user saved one in ~/.bott/prompts
+pub struct PromptTemplates {
+    dir: PathBuf,
+}
+impl Default for PromptTemplates {
+    fn default() -> Self {
+        Self {
+            dir: BottConfig::get_dir().join(PROMPTS_DIR),
+        }
+    }
+}
+impl PromptTemplates {
+    fn get_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.j2", name))
+    }
+    fn is_customized(&self, name: &str) -> bool {
+        self.get_path(name).is_file()
+    }
+    pub fn get(&self, name: &str) -> String {
+        fs::read_to_string(self.get_path(name)).unwrap_or(get_default(name).to_string())
+    }
+    pub fn render<S: serde::Serialize>(&self, name: &str, variables: S) -> BottResult<String> {
+        render_template(name, self.get(name).as_str(), variables)
+    }
+    // Copies the default template so there is something to edit, and returns its path
+    fn customize(&self, name: &str) -> BottResult<PathBuf> {
+        let path = self.get_path(name);
+        if path.is_file() {
+            return Ok(path);
+        }
+        if fs::create_dir_all(&self.dir).is_err() || fs::write(&path, get_default(name)).is_err() {
+            return Err(BottError::PromptStoreErr);
+        }
+        Ok(path)
+    }
+    // Goes back to bott's templates, returns the names that were customized
+    fn reset(&self, names: &[&str]) -> BottResult<Vec<String>> {
+        let mut reset: Vec<String> = vec![];
+        for name in names.iter().filter(|n| self.is_customized(n)) {
+            if fs::remove_file(self.get_path(name)).is_err() {
+                return Err(BottError::PromptStoreErr);
+            }
+            reset.push(name.to_string());
+        }
+        Ok(reset)
+    }
+}
+
+fn render_template<S: serde::Serialize>(
+    name: &str,
+    template: &str,
+    variables: S,
+) -> BottResult<String> {
+    let mut environment = Environment::new();
+    // a misspelled variable in a custom template should fail instead of leaving a hole
+    environment.set_undefined_behavior(UndefinedBehavior::Strict);
+    environment
+        .template_from_named_str(name, template)
+        .and_then(|t| t.render(variables))
+        .map_err(|e| BottError::PromptTemplateErr(name.to_string(), e.to_string()))
+}
+// Renders a prompt with what bott knows right now, the way the next query would see it
+fn render_current(templates: &PromptTemplates, name: &str) -> BottResult<String> {
+    if name == PROMPT_DEBUG {
+        return templates.render(
+            name,
+            DebugVariables {
+                input: env::var("bott_last_run_executed_code").unwrap_or_default(),
+                output: env::var("bott_last_run_output").unwrap_or_default(),
+            },
+        );
+    }
+    let config = BottConfig::load()?;
+    let prompt_context = PromptContext::collect(&config, "")?;
+    templates.render(name, PromptVariables::from_context(&prompt_context))
+}
+
+pub fn show(name: &str, rendered: bool) -> BottResult<String> {
+    let templates = PromptTemplates::default();
+    if rendered {
+        return render_current(&templates, name);
+    }
+    Ok(templates.get(name))
+}
+// Opens the template in $VISUAL or $EDITOR and checks that it still renders
+pub fn edit(name: &str) -> BottResult<String> {
+    let templates = PromptTemplates::default();
+    let path = templates.customize(name)?;
+    let editor = env::var("VISUAL")
+        .or(env::var("EDITOR"))
+        .unwrap_or(String::from("vi"));
+    // editors are often configured with arguments, like `code --wait`
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("vi");
+    match Command::new(program).args(parts).arg(&path).status() {
+        Ok(s) if s.success() => {}
+        _ => return Err(BottError::EditorErr(editor)),
+    }
+    render_current(&templates, name)?;
+    Ok(format!("Saved the {} prompt in {}", name, path.display()))
+}
+pub fn reset(name: Option<&str>) -> BottResult<String> {
+    let names = match name {
+        Some(n) => vec![n],
+        None => PROMPTS.to_vec(),
+    };
+    let reset = PromptTemplates::default().reset(&names)?;
+    if reset.is_empty() {
+        return Ok(String::from("Already using the default prompts"));
+    }
+    Ok(format!(
+        "Back to the default {} prompt{}",
+        reset.join(", "),
+        if reset.len() > 1 { "s" } else { "" }
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::system::SystemContext;
+    use crate::llm::suggestion::get_structured_system_prompt;
+
+    fn temp_templates() -> PromptTemplates {
+        let dir = std::env::temp_dir().join(format!("bott-prompts-{}", rand::random::<u64>()));
+        PromptTemplates { dir }
+    }
+    fn prompt_context() -> PromptContext {
+        PromptContext {
+            system: SystemContext {
+                distro: String::from("Ubuntu 22.04.3 LTS"),
+                kernel: String::from("6.5.0"),
+                arch: String::from("x86_64"),
+                shell: String::from("/bin/zsh"),
+                shell_version: Some(String::from("5.9")),
+                package_managers: vec![String::from("apt")],
+                coreutils: String::from("GNU"),
+                container: None,
+                wsl: false,
+            },
+            project: None,
+            tools: None,
+            history: None,
+            examples: None,
+            structured: false,
+        }
+    }
+
+    #[test]
+    fn renders_the_default_prompts() {
+        let templates = temp_templates();
+        let variables = PromptVariables::from_context(&prompt_context());
+        let query = templates.render(PROMPT_QUERY_SYSTEM, &variables).unwrap();
+        assert!(query.contains("terminal usage. Bash code"));
+        assert!(query.ends_with("Shell: zsh 5.9\nPackage manager: apt\nCoreutils: GNU"));
+        let debug = templates
+            .render(
+                PROMPT_DEBUG,
+                DebugVariables {
+                    input: String::from("cat /etc/os-release"),
+                    output: String::from("No such file or directory"),
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            debug,
+            "input: cat /etc/os-release\noutput: No such file or directory"
+        );
+    }
+
+    #[test]
+    fn leaves_code_blocks_out_of_structured_prompts() {
+        let templates = temp_templates();
+        let prompt_context = prompt_context().with_structured(true);
+        let variables = PromptVariables::from_context(&prompt_context);
+        let query = templates.render(PROMPT_QUERY_SYSTEM, &variables).unwrap();
+        let query = get_structured_system_prompt(query);
+        assert!(!query.contains("```bash"));
+        assert!(query.contains("Reply only with a JSON object"));
+    }
+
+    #[test]
+    fn prefers_customized_prompts() {
+        let templates = temp_templates();
+        let path = templates.customize(PROMPT_QUERY_SYSTEM).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            get_default(PROMPT_QUERY_SYSTEM)
+        );
+        fs::write(
+            &path,
+            "Answer for {{ shell }} on {{ distro }}.{% for t in tools %} {{ t }}{% endfor %}",
+        )
+        .unwrap();
+        let variables = PromptVariables::from_context(&prompt_context());
+        assert_eq!(
+            templates.render(PROMPT_QUERY_SYSTEM, &variables).unwrap(),
+            "Answer for zsh on Ubuntu 22.04.3 LTS."
+        );
+
+        assert_eq!(
+            templates.reset(&PROMPTS).unwrap(),
+            vec![PROMPT_QUERY_SYSTEM]
+        );
+        assert!(!templates.is_customized(PROMPT_QUERY_SYSTEM));
+        assert!(templates.reset(&PROMPTS).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_variables() {
+        let result = render_template(
+            PROMPT_QUERY_SYSTEM,
+            "Answer for {{ shel }}",
+            PromptVariables::from_context(&prompt_context()),
+        );
+        match result {
+            Err(BottError::PromptTemplateErr(name, _)) => assert_eq!(name, PROMPT_QUERY_SYSTEM),
+            _ => panic!("expected a template error"),
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use regex::Regex;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+// A suggested command together with what the llm knows about it.
+// Answers that aren't json only have a command.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Suggestion {
+    pub command: String,
+    #[serde(default)]
+    pub explanation: String,
+    #[serde(default)]
+    pub assumptions: Vec<String>,
+    #[serde(default)]
+    pub requires_sudo: bool,
+    #[serde(default)]
+    pub destructive: bool,
+}
+impl Suggestion {
+    pub fn new(command: String) -> Self {
+        Self {
+            command,
+            ..Suggestion::default()
+        }
+    }
+    // Reads a json answer, falling back to the first bash block for llms that ignored the format
+    pub fn parse(content: &str) -> Option<Suggestion> {
+        parse_json(content).or_else(|| get_bash_block(content).map(Suggestion::new))
+    }
+}
+
+fn get_bash_block(content: &str) -> Option<String> {
+    let re = Regex::new(r"```bash(?P<bash_code>[\s\S]*?)```").unwrap();
+    re.captures(content)
+        .map(|c| String::from(&c["bash_code"]).trim().to_string())
+}
+fn parse_json(content: &str) -> Option<Suggestion> {
+    // models in json mode sometimes still wrap the object in a code block
+    let start = content.find('{')?;
+    let end = content.rfind('}')?;
+    let mut suggestion: Suggestion = serde_json::from_str(content.get(start..=end)?).ok()?;
+    if let Some(command) = get_bash_block(suggestion.command.as_str()) {
+        suggestion.command = command;
+    }
+    suggestion.command = suggestion.command.trim().to_string();
+    Some(suggestion).filter(|s| !s.command.is_empty())
+}
+
+// The json schema of a suggestion, for providers that can constrain their output with one
+pub fn get_suggestion_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "command": {"type": "string"},
+            "explanation": {"type": "string"},
+            "assumptions": {"type": "array", "items": {"type": "string"}},
+            "requires_sudo": {"type": "boolean"},
+            "destructive": {"type": "boolean"},
+        },
+        "required": ["command", "explanation", "assumptions", "requires_sudo", "destructive"],
+        "additionalProperties": false,
+    })
+}
+pub fn get_structured_system_prompt(system_prompt: String) -> String {
+    format!(
+        r#"{system_prompt}
+    Reply only with a JSON object with these fields:
+    command: the single line bash command, without ``` tags
+    explanation: one sentence about what the command does
+    assumptions: what you assumed about the user's system or intent, can be empty
+    requires_sudo: whether the command needs root privileges
+    destructive: whether the command deletes or overwrites data, or can't be undone
+    "#,
+        system_prompt = system_prompt.trim_end(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_json_answers() {
+        let content = r#"{"command":"sudo rm -rf /tmp/cache","explanation":"Removes the cache","assumptions":["the cache is in /tmp"],"requires_sudo":true,"destructive":true}"#;
+        assert_eq!(
+            Suggestion::parse(content).unwrap(),
+            Suggestion {
+                command: String::from("sudo rm -rf /tmp/cache"),
+                explanation: String::from("Removes the cache"),
+                assumptions: vec![String::from("the cache is in /tmp")],
+                requires_sudo: true,
+                destructive: true,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_wrapped_json() {
+        let content = "```json\n{\"command\": \"```bash\\nls -la\\n```\"}\n```";
+        assert_eq!(
+            Suggestion::parse(content).unwrap(),
+            Suggestion::new(String::from("ls -la"))
+        );
+    }
+
+    #[test]
+    fn falls_back_to_bash_blocks() {
+        let content = "Sure:\n```bash\ngit status\n```";
+        assert_eq!(
+            Suggestion::parse(content).unwrap(),
+            Suggestion::new(String::from("git status"))
+        );
+        assert_eq!(Suggestion::parse("{\"command\": \"\"}"), None);
+        assert_eq!(Suggestion::parse("I can't help with that"), None);
+    }
+}
@@ -0,0 +1,316 @@
+use serde_derive::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+const FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/llm/fixtures");
+// Set to 1 to record fixtures from the real providers instead of replaying them
+const RECORD_ENV: &str = "BOTT_RECORD_FIXTURES";
+// Hop-by-hop headers and encodings the recorder couldn't read are left out when forwarding
+const SKIPPED_HEADERS: [&str; 4] = ["host", "content-length", "connection", "accept-encoding"];
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub request_line: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<String> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.clone())
+    }
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_str(&self.body).unwrap()
+    }
+    fn method_and_path(&self) -> (String, String) {
+        let mut parts = self.request_line.split_whitespace();
+        (
+            parts.next().unwrap_or_default().to_string(),
+            parts.next().unwrap_or_default().to_string(),
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TestResponse {
+    status: u16,
+    content_type: String,
+    body: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<(String, String)>,
+    // how long the server thinks before answering, like an llm generating without streaming
+    #[serde(skip)]
+    delay: Duration,
+}
+impl TestResponse {
+    pub fn new(status: u16, content_type: &str, body: &str) -> Self {
+        Self {
+            status,
+            content_type: content_type.to_string(),
+            body: body.to_string(),
+            headers: vec![],
+            delay: Duration::ZERO,
+        }
+    }
+    pub fn delay(self, delay: Duration) -> Self {
+        Self { delay, ..self }
+    }
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+    fn to_http(&self) -> String {
+        let headers = self
+            .headers
+            .iter()
+            .map(|(k, v)| format!("{}: {}\r\n", k, v))
+            .collect::<String>();
+        format!(
+            "HTTP/1.1 {} OK\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+            self.status,
+            self.content_type,
+            self.body.len(),
+            headers,
+            self.body
+        )
+    }
+}
+
+// What was sent in a recorded exchange. Headers are left out so api keys never end up in a fixture
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct FixtureRequest {
+    method: String,
+    path: String,
+    #[serde(default)]
+    body: serde_json::Value,
+}
+impl From<&RecordedRequest> for FixtureRequest {
+    fn from(request: &RecordedRequest) -> Self {
+        let (method, path) = request.method_and_path();
+        Self {
+            method,
+            path,
+            body: match request.body.is_empty() {
+                true => serde_json::Value::Null,
+                false => serde_json::from_str(&request.body)
+                    .unwrap_or(serde_json::Value::String(request.body.clone())),
+            },
+        }
+    }
+}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Exchange {
+    request: FixtureRequest,
+    response: TestResponse,
+}
+// The http exchanges of one test, stored as src/llm/fixtures/<name>.json
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct Fixture {
+    exchanges: Vec<Exchange>,
+}
+impl Fixture {
+    fn get_path(name: &str) -> PathBuf {
+        PathBuf::from(FIXTURES_DIR).join(format!("{}.json", name))
+    }
+    fn load(name: &str) -> Self {
+        let path = Fixture::get_path(name);
+        let contents = fs::read_to_string(&path)
+            .unwrap_or_else(|_| panic!("missing fixture {}", path.display()));
+        serde_json::from_str(&contents)
+            .unwrap_or_else(|e| panic!("invalid fixture {}: {}", path.display(), e))
+    }
+    fn save(&self, name: &str) {
+        let path = Fixture::get_path(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, serde_json::to_string_pretty(self).unwrap() + "\n").unwrap();
+    }
+}
+
+async fn forward(
+    client: &reqwest::Client,
+    upstream: &str,
+    request: &RecordedRequest,
+) -> TestResponse {
+    let (method, path) = request.method_and_path();
+    let mut builder = client.request(
+        reqwest::Method::from_bytes(method.as_bytes()).unwrap(),
+        format!("{}{}", upstream.trim_end_matches('/'), path),
+    );
+    for (name, value) in request.headers.iter() {
+        if !SKIPPED_HEADERS.contains(&name.to_lowercase().as_str()) {
+            builder = builder.header(name, value);
+        }
+    }
+    let response = match builder.body(request.body.clone()).send().await {
+        Ok(r) => r,
+        Err(e) => return TestResponse::new(502, "text/plain", &e.to_string()),
+    };
+    let status = response.status().as_u16();
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("application/json")
+        .to_string();
+    let body = response.text().await.unwrap_or_default();
+    TestResponse::new(status, &content_type, &body)
+}
+
+// A minimal HTTP server that answers requests with canned responses
+// and remembers what it was sent, so backends can be tested without network access
+pub struct TestServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+    // the requests a fixture was recorded with, when replaying one
+    expected: Option<Vec<FixtureRequest>>,
+}
+
+async fn read_request(stream: &mut tokio::net::TcpStream) -> Option<RecordedRequest> {
+    let mut buffer: Vec<u8> = vec![];
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if let Some(position) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break position + 4;
+        }
+    };
+    let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let mut lines = head.lines();
+    let request_line = lines.next().unwrap_or_default().to_string();
+    let headers = lines
+        .filter_map(|l| l.split_once(':'))
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .collect::<Vec<(String, String)>>();
+    let content_length = headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, v)| v.parse::<usize>().ok())
+        .unwrap_or(0);
+    while buffer.len() < header_end + content_length {
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+    let body = String::from_utf8_lossy(&buffer[header_end..]).to_string();
+    Some(RecordedRequest {
+        request_line,
+        headers,
+        body,
+    })
+}
+
+impl TestServer {
+    pub async fn start(status: u16, content_type: &str, body: &str) -> Self {
+        TestServer::start_with(vec![TestResponse::new(status, content_type, body)]).await
+    }
+    // Answers with the responses in order, repeating the last one once they run out
+    pub async fn start_with(responses: Vec<TestResponse>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests: Arc<Mutex<Vec<RecordedRequest>>> = Arc::new(Mutex::new(vec![]));
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            let mut served = 0;
+            while let Ok((mut stream, _)) = listener.accept().await {
+                if let Some(request) = read_request(&mut stream).await {
+                    recorded.lock().unwrap().push(request);
+                }
+                let response = &responses[served.min(responses.len() - 1)];
+                served += 1;
+                tokio::time::sleep(response.delay).await;
+                let _ = stream.write_all(response.to_http().as_bytes()).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+        Self {
+            url,
+            requests,
+            expected: None,
+        }
+    }
+    // Answers with the responses of a recorded fixture, see fixture()
+    pub async fn replay(name: &str) -> Self {
+        let fixture = Fixture::load(name);
+        let responses = fixture
+            .exchanges
+            .iter()
+            .map(|e| e.response.clone())
+            .collect::<Vec<TestResponse>>();
+        let mut server = TestServer::start_with(responses).await;
+        server.expected = Some(fixture.exchanges.into_iter().map(|e| e.request).collect());
+        server
+    }
+    // Replays a fixture, or with BOTT_RECORD_FIXTURES=1 forwards the requests to the real
+    // provider at upstream and records what it answers. Fixtures that can't be recorded,
+    // like malformed responses, are written by hand and only ever replayed.
+    pub async fn fixture(name: &str, upstream: &str) -> Self {
+        if env::var(RECORD_ENV).is_ok_and(|v| v == "1") {
+            return TestServer::record(name, upstream).await;
+        }
+        TestServer::replay(name).await
+    }
+    async fn record(name: &str, upstream: &str) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests: Arc<Mutex<Vec<RecordedRequest>>> = Arc::new(Mutex::new(vec![]));
+        let recorded = requests.clone();
+        let name = name.to_string();
+        let upstream = upstream.to_string();
+        tokio::spawn(async move {
+            let client = reqwest::Client::new();
+            let mut fixture = Fixture::default();
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let Some(request) = read_request(&mut stream).await else {
+                    continue;
+                };
+                let response = forward(&client, &upstream, &request).await;
+                fixture.exchanges.push(Exchange {
+                    request: FixtureRequest::from(&request),
+                    response: response.clone(),
+                });
+                // saved after every exchange, the test may end right after its last request
+                fixture.save(&name);
+                recorded.lock().unwrap().push(request);
+                let _ = stream.write_all(response.to_http().as_bytes()).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+        Self {
+            url,
+            requests,
+            expected: None,
+        }
+    }
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+    // Checks the requests went to the same endpoints as when the fixture was recorded
+    pub fn assert_replayed(&self) {
+        let Some(expected) = &self.expected else {
+            return;
+        };
+        let sent = self
+            .requests()
+            .iter()
+            .map(|r| r.method_and_path())
+            .collect::<Vec<(String, String)>>();
+        let expected = expected
+            .iter()
+            .map(|r| (r.method.clone(), r.path.clone()))
+            .collect::<Vec<(String, String)>>();
+        assert_eq!(sent, expected);
+    }
+}
@@ -0,0 +1,270 @@
+use crate::config::{BottConfig, UsageOptions};
+use crate::errors::BottError;
+use crate::result::BottResult;
+use chrono::{DateTime, Datelike, Local, TimeZone};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+const USAGE_FILE: &str = "usage.jsonl";
+pub const USAGE_BY: [&str; 3] = ["day", "model", "session"];
+
+// Tokens spent on a single request, as reported by the provider
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Usage {
+    pub model: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UsageRecord {
+    pub timestamp: i64,
+    pub session: String,
+    pub llm: String,
+    pub model: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    // None for models without a price
+    pub cost: Option<f64>,
+}
+#[derive(Debug, Default)]
+struct UsageTotal {
+    requests: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    cost: Option<f64>,
+}
+impl UsageTotal {
+    fn add(&mut self, record: &UsageRecord) {
+        self.requests += 1;
+        self.prompt_tokens += record.prompt_tokens;
+        self.completion_tokens += record.completion_tokens;
+        if let Some(c) = record.cost {
+            self.cost = Some(self.cost.unwrap_or_default() + c);
+        }
+    }
+}
+
+fn get_usage_path() -> PathBuf {
+    BottConfig::get_dir().join(USAGE_FILE)
+}
+fn get_cost(options: &UsageOptions, usage: &Usage) -> Option<f64> {
+    options.prices.get(&usage.model).map(|p| {
+        (usage.prompt_tokens as f64 * p.input + usage.completion_tokens as f64 * p.output)
+            / 1_000_000.0
+    })
+}
+fn format_cost(cost: Option<f64>) -> String {
+    match cost {
+        Some(c) => format!("${:.4}", c),
+        None => String::from("-"),
+    }
+}
+
+// Appends the usage of a request to the usage log, bott_init gives every shell session an id
+pub fn record(config: &BottConfig, llm: &str, usage: &Usage) -> BottResult<()> {
+    let record = UsageRecord {
+        timestamp: Local::now().timestamp(),
+        session: env::var("bott_session").unwrap_or_default(),
+        llm: llm.to_string(),
+        model: usage.model.clone(),
+        prompt_tokens: usage.prompt_tokens,
+        completion_tokens: usage.completion_tokens,
+        cost: get_cost(&config.get_usage_options(), usage),
+    };
+    let path = get_usage_path();
+    if let Some(dir) = path.parent() {
+        if fs::create_dir_all(dir).is_err() {
+            return Err(BottError::UsageStoreErr);
+        }
+    }
+    let line = format!("{}\n", serde_json::to_string(&record).unwrap());
+    match OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut f| f.write_all(line.as_bytes()))
+    {
+        Ok(_) => Ok(()),
+        Err(_) => Err(BottError::UsageStoreErr),
+    }
+}
+fn load_records() -> Vec<UsageRecord> {
+    fs::read_to_string(get_usage_path())
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|l| serde_json::from_str::<UsageRecord>(l).ok())
+        .collect()
+}
+fn get_date(timestamp: i64) -> Option<DateTime<Local>> {
+    Local.timestamp_opt(timestamp, 0).single()
+}
+fn get_month_cost(records: &[UsageRecord], now: DateTime<Local>) -> f64 {
+    records
+        .iter()
+        .filter(|r| {
+            get_date(r.timestamp)
+                .is_some_and(|d| d.year() == now.year() && d.month() == now.month())
+        })
+        .filter_map(|r| r.cost)
+        // sum() of no costs is -0.0
+        .fold(0.0, |total, c| total + c)
+}
+
+// Checked before asking an llm. Over budget is an error when blocking, a warning otherwise
+pub fn check_budget(config: &BottConfig) -> BottResult<Option<String>> {
+    let options = config.get_usage_options();
+    let Some(budget) = options.monthly_budget else {
+        return Ok(None);
+    };
+    let spent = get_month_cost(&load_records(), Local::now());
+    if spent < budget {
+        return Ok(None);
+    }
+    if options.budget_action == "block" {
+        return Err(BottError::BudgetExceeded(spent, budget));
+    }
+    Ok(Some(format!(
+        "the monthly budget of ${:.2} is spent (${:.2} so far)",
+        budget, spent
+    )))
+}
+
+fn render_report(
+    records: &[UsageRecord],
+    by: &str,
+    budget: Option<f64>,
+    now: DateTime<Local>,
+) -> String {
+    let mut totals: BTreeMap<String, UsageTotal> = BTreeMap::new();
+    for record in records {
+        let key = match by {
+            "model" => format!("{}/{}", record.llm, record.model),
+            "session" if record.session.is_empty() => String::from("-"),
+            "session" => record.session.clone(),
+            _ => get_date(record.timestamp)
+                .map(|d| d.format("%Y-%m-%d").to_string())
+                .unwrap_or(String::from("-")),
+        };
+        totals.entry(key).or_default().add(record);
+    }
+    let mut rows = vec![[
+        by.to_uppercase(),
+        String::from("REQUESTS"),
+        String::from("PROMPT"),
+        String::from("COMPLETION"),
+        String::from("COST"),
+    ]];
+    for (key, total) in totals.iter() {
+        rows.push([
+            key.clone(),
+            total.requests.to_string(),
+            total.prompt_tokens.to_string(),
+            total.completion_tokens.to_string(),
+            format_cost(total.cost),
+        ]);
+    }
+    let widths = (0..5)
+        .map(|c| rows.iter().map(|r| r[c].chars().count()).max().unwrap_or(0))
+        .collect::<Vec<usize>>();
+    let mut lines = rows
+        .iter()
+        .map(|r| {
+            r.iter()
+                .zip(widths.iter())
+                .map(|(v, &w)| format!("{:<w$}", v, w = w))
+                .collect::<Vec<String>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<String>>();
+    let spent = get_month_cost(records, now);
+    lines.push(match budget {
+        Some(b) => format!("This month: {} of ${:.2}", format_cost(Some(spent)), b),
+        None => format!("This month: {}", format_cost(Some(spent))),
+    });
+    lines.join("\n")
+}
+// Tokens and estimated cost of every request bott made, grouped by day, model or session
+pub fn report(by: &str) -> BottResult<String> {
+    let config = BottConfig::load()?;
+    let records = load_records();
+    if records.is_empty() {
+        return Ok(String::from("No usage recorded yet"));
+    }
+    Ok(render_report(
+        &records,
+        by,
+        config.get_usage_options().monthly_budget,
+        Local::now(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage_record(timestamp: i64, session: &str, model: &str, cost: Option<f64>) -> UsageRecord {
+        UsageRecord {
+            timestamp,
+            session: session.to_string(),
+            llm: String::from("openai"),
+            model: model.to_string(),
+            prompt_tokens: 1000,
+            completion_tokens: 100,
+            cost,
+        }
+    }
+
+    #[test]
+    fn prices_per_million_tokens() {
+        let usage = Usage {
+            model: String::from("gpt-4o"),
+            prompt_tokens: 2_000,
+            completion_tokens: 500,
+        };
+        let cost = get_cost(&UsageOptions::default(), &usage).unwrap();
+        assert!((cost - 0.01).abs() < 1e-9);
+        let local = Usage {
+            model: String::from("codellama:7b-instruct"),
+            ..usage
+        };
+        assert_eq!(get_cost(&UsageOptions::default(), &local), None);
+    }
+
+    #[test]
+    fn counts_only_this_month() {
+        let now = Local.with_ymd_and_hms(2024, 5, 20, 12, 0, 0).unwrap();
+        let last_month = Local.with_ymd_and_hms(2024, 4, 30, 12, 0, 0).unwrap();
+        let records = vec![
+            usage_record(now.timestamp(), "a", "gpt-4o", Some(1.5)),
+            usage_record(now.timestamp(), "a", "codellama", None),
+            usage_record(last_month.timestamp(), "b", "gpt-4o", Some(4.0)),
+        ];
+        assert_eq!(get_month_cost(&records, now), 1.5);
+    }
+
+    #[test]
+    fn groups_by_session() {
+        let now = Local.with_ymd_and_hms(2024, 5, 20, 12, 0, 0).unwrap();
+        let records = vec![
+            usage_record(now.timestamp(), "a", "gpt-4o", Some(0.5)),
+            usage_record(now.timestamp(), "a", "gpt-4o", Some(0.25)),
+            usage_record(now.timestamp(), "", "codellama", None),
+        ];
+        assert_eq!(
+            render_report(&records, "session", Some(10.0), now),
+            [
+                "SESSION  REQUESTS  PROMPT  COMPLETION  COST",
+                "-        1         1000    100         -",
+                "a        2         2000    200         $0.7500",
+                "This month: $0.7500 of $10.00",
+            ]
+            .join("\n")
+        );
+    }
+}
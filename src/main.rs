@@ -1,10 +1,21 @@
 mod config;
+mod context;
 mod errors;
 mod keychain;
 mod llm;
 mod result;
+mod validate;
 
 use crate::config::BottConfig;
+use crate::context::examples::{self, Example};
+use crate::context::system::SystemContext;
+use crate::context::tools::ToolInventory;
+use crate::context::PromptContext;
+use crate::llm::cache::ResponseCache;
+use crate::llm::doctor::check_network;
+use crate::llm::models;
+use crate::llm::prompt::{self, PROMPTS};
+use crate::llm::usage::{report as usage_report, USAGE_BY};
 use crate::llm::{generate, print_answer_and_context};
 use clap::{arg, Command};
 use dialoguer::{theme::ColorfulTheme, Confirm};
@@ -20,21 +31,12 @@ fn cli() -> Command {
             Command::new("query")
                 .about("Query")
                 .arg_required_else_help(true)
-                .arg(
-                    arg!(distro: -d --distro <DISTRO> "distro")
-                        .required(true)
-                        .value_parser(clap::value_parser!(String)),
-                )
-                .arg(
-                    arg!(shell: -s --shell <SHELL> "shell")
-                        .required(true)
-                        .value_parser(clap::value_parser!(String)),
-                )
                 .arg(
                     arg!(query: -q --query <QUERY> "query text")
                         .required(true)
                         .value_parser(clap::value_parser!(String)),
-                ),
+                )
+                .arg(arg!(no_cache: --"no-cache" "ask the llm even when the answer is cached")),
         )
         .subcommand(
             Command::new("confirm").about("Confirm").arg(
@@ -43,21 +45,127 @@ fn cli() -> Command {
                     .value_parser(clap::value_parser!(String)),
             ),
         )
+        .subcommand(Command::new("debug").about("Debug"))
+        .subcommand(
+            Command::new("context")
+                .about("Context")
+                .subcommand(Command::new("show").about("Show"))
+                .subcommand(Command::new("refresh").about("Refresh installed tools")),
+        )
         .subcommand(
-            Command::new("debug")
-                .about("Debug")
+            Command::new("examples")
+                .about("Examples")
                 .arg_required_else_help(true)
-                .arg(
-                    arg!(distro: -d --distro <DISTRO> "distro")
-                        .required(true)
-                        .value_parser(clap::value_parser!(String)),
+                .subcommand(
+                    Command::new("add")
+                        .about("Add")
+                        .arg_required_else_help(true)
+                        .arg(
+                            arg!(query: -q --query <QUERY> "question")
+                                .required(true)
+                                .value_parser(clap::value_parser!(String)),
+                        )
+                        .arg(
+                            arg!(command: -c --command <COMMAND> "command that answers it")
+                                .required(true)
+                                .value_parser(clap::value_parser!(String)),
+                        )
+                        .arg(
+                            arg!(shells: -s --shells <SHELLS> "comma separated, defaults to any shell")
+                                .value_parser(clap::value_parser!(String)),
+                        )
+                        .arg(
+                            arg!(distros: -d --distros <DISTROS> "comma separated, defaults to any distro")
+                                .value_parser(clap::value_parser!(String)),
+                        ),
                 )
-                .arg(
-                    arg!(shell: -s --shell <SHELL> "shell")
-                        .required(true)
-                        .value_parser(clap::value_parser!(String)),
+                .subcommand(Command::new("list").about("List the examples for this system")),
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("Doctor")
+                .arg_required_else_help(true)
+                .subcommand(Command::new("net").about("Check connectivity to the configured llms")),
+        )
+        .subcommand(
+            Command::new("models")
+                .about("Models")
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("list").about("List").arg(
+                        arg!(llm: -l --llm <LLM> "llm, defaults to the first configured one")
+                            .value_parser(clap::value_parser!(String)),
+                    ),
+                )
+                .subcommand(
+                    Command::new("show")
+                        .about("Show")
+                        .arg_required_else_help(true)
+                        .arg(
+                            arg!(model: -m --model <MODEL> "model")
+                                .required(true)
+                                .value_parser(clap::value_parser!(String)),
+                        )
+                        .arg(
+                            arg!(llm: -l --llm <LLM> "llm, defaults to the first configured one")
+                                .value_parser(clap::value_parser!(String)),
+                        ),
+                )
+                .subcommand(
+                    Command::new("use")
+                        .about("Use")
+                        .arg(
+                            arg!(model: -m --model <MODEL> "model, asks when not given")
+                                .value_parser(clap::value_parser!(String)),
+                        )
+                        .arg(
+                            arg!(llm: -l --llm <LLM> "llm, defaults to the first configured one")
+                                .value_parser(clap::value_parser!(String)),
+                        ),
                 ),
         )
+        .subcommand(
+            Command::new("cache")
+                .about("Cache")
+                .arg_required_else_help(true)
+                .subcommand(Command::new("stats").about("Stats"))
+                .subcommand(Command::new("clear").about("Clear")),
+        )
+        .subcommand(
+            Command::new("prompt")
+                .about("Prompt templates")
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("show")
+                        .about("Show")
+                        .arg(
+                            arg!(name: -n --name <NAME> "prompt")
+                                .default_value("query_system")
+                                .value_parser(PROMPTS),
+                        )
+                        .arg(arg!(rendered: -r --rendered "fill in what bott knows right now")),
+                )
+                .subcommand(
+                    Command::new("edit").about("Edit").arg(
+                        arg!(name: -n --name <NAME> "prompt")
+                            .default_value("query_system")
+                            .value_parser(PROMPTS),
+                    ),
+                )
+                .subcommand(
+                    Command::new("reset").about("Reset").arg(
+                        arg!(name: -n --name <NAME> "prompt, defaults to all of them")
+                            .value_parser(PROMPTS),
+                    ),
+                ),
+        )
+        .subcommand(
+            Command::new("usage").about("Usage").arg(
+                arg!(by: -b --by <BY> "group by day, model or session")
+                    .default_value("day")
+                    .value_parser(USAGE_BY),
+            ),
+        )
         .subcommand(
             Command::new("config")
                 .about("Config")
@@ -107,10 +215,9 @@ async fn main() {
         Some(("query", sub_matches)) => {
             let mut sp = Spinner::new(Spinners::Dots, "Thinking...".into());
             let query = sub_matches.get_one::<String>("query").unwrap().trim();
-            let distro = sub_matches.get_one::<String>("distro").unwrap().trim();
-            let shell = sub_matches.get_one::<String>("shell").unwrap().trim();
+            let no_cache = sub_matches.get_flag("no_cache");
 
-            match generate(query, distro, shell, false).await {
+            match generate(query, false, no_cache).await {
                 Ok(output) => {
                     sp.stop_with_message("".to_string());
                     print_answer_and_context(output);
@@ -118,16 +225,14 @@ async fn main() {
                 }
                 Err(e) => {
                     sp.stop_with_message("".to_string());
-                    print!("{}", e.to_string());
+                    print!("{}", e);
                     exit(exitcode::UNAVAILABLE);
                 }
             }
         }
-        Some(("debug", sub_matches)) => {
+        Some(("debug", _)) => {
             let mut sp = Spinner::new(Spinners::Dots, "Thinking...".into());
-            let distro = sub_matches.get_one::<String>("distro").unwrap().trim();
-            let shell = sub_matches.get_one::<String>("shell").unwrap().trim();
-            match generate("", distro, shell, true).await {
+            match generate("", true, true).await {
                 Ok(output) => {
                     sp.stop_with_message("".to_string());
                     print_answer_and_context(output);
@@ -135,7 +240,7 @@ async fn main() {
                 }
                 Err(e) => {
                     sp.stop_with_message("".to_string());
-                    print!("{}", e.to_string());
+                    print!("{}", e);
                     exit(exitcode::UNAVAILABLE);
                 }
             }
@@ -155,6 +260,214 @@ async fn main() {
             }
             exit(exitcode::UNAVAILABLE)
         }
+        Some(("context", sub_matches)) => match sub_matches.subcommand() {
+            Some(("refresh", _)) => {
+                let mut sp = Spinner::new(Spinners::Dots, "Looking for tools...".into());
+                match ToolInventory::refresh() {
+                    Ok(inventory) => {
+                        sp.stop_with_message("".to_string());
+                        print!("{}", inventory.render());
+                        exit(exitcode::OK)
+                    }
+                    Err(e) => {
+                        sp.stop_with_message("".to_string());
+                        print!("{}", e);
+                        exit(exitcode::UNAVAILABLE);
+                    }
+                }
+            }
+            _ => {
+                let config: BottConfig = match BottConfig::load() {
+                    Ok(c) => c,
+                    Err(e) => {
+                        print!("{}", e);
+                        exit(exitcode::UNAVAILABLE);
+                    }
+                };
+                match PromptContext::collect(&config, "") {
+                    Ok(prompt_context) => {
+                        print!("{}", prompt_context.render());
+                        exit(exitcode::OK)
+                    }
+                    Err(e) => {
+                        print!("{}", e);
+                        exit(exitcode::UNAVAILABLE);
+                    }
+                }
+            }
+        },
+        Some(("examples", sub_matches)) => match sub_matches.subcommand() {
+            Some(("add", sub_matches)) => {
+                let list = |key: &str| {
+                    sub_matches
+                        .get_one::<String>(key)
+                        .map(|v| {
+                            v.split(',')
+                                .map(|s| s.trim().to_string())
+                                .filter(|s| !s.is_empty())
+                                .collect::<Vec<String>>()
+                        })
+                        .unwrap_or_default()
+                };
+                let example = Example {
+                    query: sub_matches
+                        .get_one::<String>("query")
+                        .unwrap()
+                        .trim()
+                        .to_string(),
+                    command: sub_matches
+                        .get_one::<String>("command")
+                        .unwrap()
+                        .trim()
+                        .to_string(),
+                    shells: list("shells"),
+                    distros: list("distros"),
+                };
+                match examples::add(example) {
+                    Ok(_) => {
+                        print!("Example added");
+                        exit(exitcode::OK)
+                    }
+                    Err(e) => {
+                        print!("{}", e);
+                        exit(exitcode::UNAVAILABLE);
+                    }
+                }
+            }
+            _ => match examples::list(&SystemContext::detect()) {
+                Ok(list) => {
+                    print!("{}", list);
+                    exit(exitcode::OK)
+                }
+                Err(e) => {
+                    print!("{}", e);
+                    exit(exitcode::UNAVAILABLE);
+                }
+            },
+        },
+        Some(("doctor", sub_matches)) => match sub_matches.subcommand() {
+            Some(("net", _)) => {
+                let mut sp = Spinner::new(Spinners::Dots, "Checking network...".into());
+                match check_network().await {
+                    Ok(report) => {
+                        sp.stop_with_message("".to_string());
+                        print!("{}", report.lines.join("\n"));
+                        if report.ok {
+                            exit(exitcode::OK)
+                        }
+                        exit(exitcode::UNAVAILABLE)
+                    }
+                    Err(e) => {
+                        sp.stop_with_message("".to_string());
+                        print!("{}", e);
+                        exit(exitcode::UNAVAILABLE);
+                    }
+                }
+            }
+            _ => unreachable!(),
+        },
+        Some(("models", sub_matches)) => {
+            let (command, command_matches) = sub_matches.subcommand().unwrap();
+            let llm = command_matches.get_one::<String>("llm").map(|l| l.trim());
+            // list has no model argument
+            let model = command_matches
+                .try_get_one::<String>("model")
+                .ok()
+                .flatten()
+                .map(|m| m.trim());
+            if command == "use" && model.is_none() {
+                // no spinner, it would draw over the selection
+                match models::select(llm, None).await {
+                    Ok(Some(message)) => {
+                        println!("{}", message);
+                        exit(exitcode::OK)
+                    }
+                    Ok(None) => println!("Ok, keeping the current model"),
+                    Err(e) => println!("{}", e),
+                }
+                exit(exitcode::UNAVAILABLE)
+            }
+            let mut sp = Spinner::new(Spinners::Dots, "Looking for models...".into());
+            let output = match command {
+                "list" => models::list(llm).await,
+                "show" => models::show(llm, model.unwrap()).await,
+                _ => models::select(llm, model)
+                    .await
+                    .map(|m| m.unwrap_or_default()),
+            };
+            sp.stop_with_message("".to_string());
+            match output {
+                Ok(o) => {
+                    println!("{}", o);
+                    exit(exitcode::OK)
+                }
+                Err(e) => {
+                    println!("{}", e);
+                    exit(exitcode::UNAVAILABLE);
+                }
+            }
+        }
+        Some(("cache", sub_matches)) => {
+            let config: BottConfig = match BottConfig::load() {
+                Ok(c) => c,
+                Err(e) => {
+                    print!("{}", e);
+                    exit(exitcode::UNAVAILABLE);
+                }
+            };
+            let cache = ResponseCache::from_config(&config);
+            match sub_matches.subcommand() {
+                Some(("stats", _)) => {
+                    print!("{}", cache.stats().render());
+                    exit(exitcode::OK)
+                }
+                Some(("clear", _)) => match cache.clear() {
+                    Ok(entries) => {
+                        print!("Removed {} cached answers", entries);
+                        exit(exitcode::OK)
+                    }
+                    Err(e) => {
+                        print!("{}", e);
+                        exit(exitcode::UNAVAILABLE);
+                    }
+                },
+                _ => unreachable!(),
+            }
+        }
+        Some(("prompt", sub_matches)) => {
+            let (command, command_matches) = sub_matches.subcommand().unwrap();
+            let name = command_matches
+                .get_one::<String>("name")
+                .map(|n| n.as_str());
+            let output = match command {
+                "show" => prompt::show(name.unwrap(), command_matches.get_flag("rendered")),
+                "edit" => prompt::edit(name.unwrap()),
+                _ => prompt::reset(name),
+            };
+            match output {
+                Ok(o) => {
+                    println!("{}", o);
+                    exit(exitcode::OK)
+                }
+                Err(e) => {
+                    println!("{}", e);
+                    exit(exitcode::UNAVAILABLE);
+                }
+            }
+        }
+        Some(("usage", sub_matches)) => {
+            let by = sub_matches.get_one::<String>("by").unwrap();
+            match usage_report(by) {
+                Ok(report) => {
+                    print!("{}", report);
+                    exit(exitcode::OK)
+                }
+                Err(e) => {
+                    print!("{}", e);
+                    exit(exitcode::UNAVAILABLE);
+                }
+            }
+        }
         Some(("config", sub_matches)) => {
             let config_command = sub_matches.subcommand().unwrap_or(("get", sub_matches));
             match config_command {
@@ -165,13 +478,13 @@ async fn main() {
                     let mut config: BottConfig = match BottConfig::load() {
                         Ok(c) => c,
                         Err(e) => {
-                            print!("{}", e.to_string());
+                            print!("{}", e);
                             exit(exitcode::UNAVAILABLE);
                         }
                     };
 
                     if let Err(e) = config.set_key(key, value) {
-                        print!("{}", e.to_string());
+                        print!("{}", e);
                         exit(exitcode::UNAVAILABLE);
                     }
                 }
@@ -181,7 +494,7 @@ async fn main() {
                     let mut config: BottConfig = match BottConfig::load() {
                         Ok(c) => c,
                         Err(e) => {
-                            print!("{}", e.to_string());
+                            print!("{}", e);
                             exit(exitcode::UNAVAILABLE);
                         }
                     };
@@ -199,7 +512,7 @@ async fn main() {
                             };
                         }
                         Err(e) => {
-                            print!("{}", e.to_string());
+                            print!("{}", e);
                             exit(exitcode::UNAVAILABLE);
                         }
                     }
@@ -210,12 +523,12 @@ async fn main() {
                     let mut config: BottConfig = match BottConfig::load() {
                         Ok(c) => c,
                         Err(e) => {
-                            print!("{}", e.to_string());
+                            print!("{}", e);
                             exit(exitcode::UNAVAILABLE);
                         }
                     };
                     if let Err(e) = config.delete_key(key) {
-                        print!("{}", e.to_string());
+                        print!("{}", e);
                         exit(exitcode::UNAVAILABLE);
                     }
                     exit(exitcode::OK);
@@ -224,7 +537,7 @@ async fn main() {
             }
         }
         _ => {
-            if (matches.args_present() && matches.get_flag("version")) {
+            if matches.args_present() && matches.get_flag("version") {
                 print!("{}", std::env!("CARGO_PKG_VERSION"));
                 return;
             }
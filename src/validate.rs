@@ -0,0 +1,495 @@
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const SHELL_KEYWORDS: [&str; 22] = [
+    "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac",
+    "in", "function", "select", "time", "{", "}", "[[", "]]", "!", "coproc",
+];
+const SHELL_BUILTINS: [&str; 58] = [
+    ".",
+    ":",
+    "[",
+    "alias",
+    "bg",
+    "bind",
+    "break",
+    "builtin",
+    "caller",
+    "cd",
+    "command",
+    "compgen",
+    "complete",
+    "continue",
+    "declare",
+    "dirs",
+    "disown",
+    "echo",
+    "enable",
+    "eval",
+    "exec",
+    "exit",
+    "export",
+    "false",
+    "fc",
+    "fg",
+    "getopts",
+    "hash",
+    "help",
+    "history",
+    "jobs",
+    "kill",
+    "let",
+    "local",
+    "logout",
+    "mapfile",
+    "popd",
+    "printf",
+    "pushd",
+    "pwd",
+    "read",
+    "readarray",
+    "readonly",
+    "return",
+    "set",
+    "shift",
+    "shopt",
+    "source",
+    "test",
+    "times",
+    "trap",
+    "true",
+    "type",
+    "typeset",
+    "ulimit",
+    "umask",
+    "unalias",
+    "unset",
+];
+// Commands that run the word following them (after their own flags) as another command
+const COMMAND_WRAPPERS: [&str; 9] = [
+    "sudo", "env", "nohup", "nice", "exec", "command", "builtin", "xargs", "time",
+];
+// Wrapper flags that consume the following word, e.g. `sudo -u root` or `xargs -n 1`
+const WRAPPER_VALUE_FLAGS: [&str; 6] = ["-u", "-g", "-n", "-I", "-C", "-p"];
+// Commands whose path arguments are usually outputs rather than inputs
+const FILE_CREATORS: [&str; 4] = ["touch", "mkdir", "tee", "truncate"];
+const FILE_COPIERS: [&str; 6] = ["cp", "mv", "ln", "rsync", "install", "scp"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationIssue {
+    MissingExecutable(String),
+    SyntaxError(String),
+    MissingFile(String),
+}
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationIssue::MissingExecutable(s) => write!(f, "`{}` is not found on $PATH", s),
+            ValidationIssue::SyntaxError(s) => write!(f, "syntax error: {}", s),
+            ValidationIssue::MissingFile(s) => write!(f, "file `{}` does not exist", s),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Separator,
+    Redirect { input: bool },
+}
+
+fn tokenize(command: &str) -> Vec<Token> {
+    let mut tokens: Vec<Token> = vec![];
+    let mut word = String::new();
+    // delimiters of the heredocs whose bodies start on the next line
+    let mut heredocs: Vec<String> = vec![];
+    let mut chars = command.chars().peekable();
+    let flush = |word: &mut String, tokens: &mut Vec<Token>| {
+        if !word.is_empty() {
+            tokens.push(Token::Word(word.clone()));
+            word.clear();
+        }
+    };
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                for n in chars.by_ref() {
+                    if n == '\'' {
+                        break;
+                    }
+                    word.push(n);
+                }
+            }
+            '"' => {
+                while let Some(n) = chars.next() {
+                    match n {
+                        '"' => break,
+                        '\\' => {
+                            if let Some(e) = chars.next() {
+                                word.push(e);
+                            }
+                        }
+                        _ => word.push(n),
+                    }
+                }
+            }
+            '\\' => {
+                if let Some(e) = chars.next() {
+                    word.push(e);
+                }
+            }
+            '$' if chars.peek() == Some(&'(') => {
+                chars.next();
+                flush(&mut word, &mut tokens);
+                tokens.push(Token::Separator);
+            }
+            '\n' if !heredocs.is_empty() => {
+                flush(&mut word, &mut tokens);
+                tokens.push(Token::Separator);
+                // heredoc bodies are text, not commands
+                for delimiter in heredocs.drain(..) {
+                    loop {
+                        let line: String = chars.by_ref().take_while(|n| *n != '\n').collect();
+                        if line.trim() == delimiter || chars.peek().is_none() {
+                            break;
+                        }
+                    }
+                }
+            }
+            '|' | '&' | ';' | '(' | ')' | '`' | '\n' => {
+                flush(&mut word, &mut tokens);
+                if c == '&' && chars.peek() == Some(&'>') {
+                    chars.next();
+                    if chars.peek() == Some(&'>') {
+                        chars.next();
+                    }
+                    tokens.push(Token::Redirect { input: false });
+                } else {
+                    tokens.push(Token::Separator);
+                }
+            }
+            '>' | '<' => {
+                // file descriptor prefixes like `2>` belong to the redirect, not the command
+                if !word.is_empty() && word.chars().all(|d| d.is_ascii_digit()) {
+                    word.clear();
+                }
+                flush(&mut word, &mut tokens);
+                let mut length = 1;
+                while chars.peek() == Some(&'>') || chars.peek() == Some(&'<') {
+                    chars.next();
+                    length += 1;
+                }
+                if c == '<' && length == 2 {
+                    // `<<EOF`, `<<-EOF` or `<< 'EOF'`, the body follows on the next lines
+                    if chars.peek() == Some(&'-') {
+                        chars.next();
+                    }
+                    while chars.peek().is_some_and(|n| *n == ' ' || *n == '\t') {
+                        chars.next();
+                    }
+                    let mut delimiter = String::new();
+                    while let Some(n) = chars.peek() {
+                        if n.is_whitespace() || [';', '|', '&', ')'].contains(n) {
+                            break;
+                        }
+                        if *n != '\'' && *n != '"' && *n != '\\' {
+                            delimiter.push(*n);
+                        }
+                        chars.next();
+                    }
+                    heredocs.push(delimiter);
+                    continue;
+                }
+                if c == '<' && length == 3 {
+                    // the word after a `<<<` here-string is text, not a file
+                    tokens.push(Token::Redirect { input: false });
+                    continue;
+                }
+                if chars.peek() == Some(&'&') {
+                    // `>&2` style duplication has no file target
+                    chars.next();
+                    while chars
+                        .peek()
+                        .is_some_and(|d| d.is_ascii_digit() || *d == '-')
+                    {
+                        chars.next();
+                    }
+                    continue;
+                }
+                tokens.push(Token::Redirect { input: c == '<' });
+            }
+            c if c.is_whitespace() => flush(&mut word, &mut tokens),
+            _ => word.push(c),
+        }
+    }
+    flush(&mut word, &mut tokens);
+    tokens
+}
+
+// Splits tokens into simple commands, returning them along with the input redirect targets
+fn split_segments(tokens: Vec<Token>) -> (Vec<Vec<String>>, Vec<String>) {
+    let mut segments: Vec<Vec<String>> = vec![];
+    let mut inputs: Vec<String> = vec![];
+    let mut current: Vec<String> = vec![];
+    let mut redirect: Option<bool> = None;
+    for token in tokens {
+        match token {
+            Token::Separator => {
+                if !current.is_empty() {
+                    segments.push(current.clone());
+                    current.clear();
+                }
+            }
+            Token::Redirect { input } => redirect = Some(input),
+            Token::Word(w) => match redirect.take() {
+                Some(true) => inputs.push(w),
+                Some(false) => {}
+                None => current.push(w),
+            },
+        }
+    }
+    if !current.is_empty() {
+        segments.push(current);
+    }
+    (segments, inputs)
+}
+
+fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    }
+}
+
+fn expand_home(word: &str) -> PathBuf {
+    if let Some(rest) = word.strip_prefix("~/") {
+        if let Ok(home) = env::var("HOME") {
+            return Path::new(home.as_str()).join(rest);
+        }
+    }
+    PathBuf::from(word)
+}
+
+fn find_executable(name: &str) -> bool {
+    if name.contains('/') {
+        return expand_home(name).is_file();
+    }
+    let path = env::var("PATH").unwrap_or_default();
+    env::split_paths(path.as_str()).any(|dir| dir.join(name).is_file())
+}
+
+fn looks_like_path(word: &str) -> bool {
+    let prefixed = ["/", "./", "../", "~/"].iter().any(|p| word.starts_with(p));
+    let dynamic = word.contains(['*', '?', '[', '{', '$']);
+    prefixed && !dynamic
+}
+
+fn check_syntax(command: &str, shell: &str) -> Option<ValidationIssue> {
+    let shell_name = Path::new(shell)
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let checker = match shell_name.as_str() {
+        "bash" | "zsh" | "sh" | "dash" | "ksh" | "fish" => shell.to_string(),
+        _ => String::from("bash"),
+    };
+    let output = Command::new(checker)
+        .arg("-n")
+        .arg("-c")
+        .arg(command)
+        .output();
+    match output {
+        Ok(o) if !o.status.success() => {
+            let stderr = String::from_utf8_lossy(&o.stderr).trim().to_string();
+            let message = stderr
+                .lines()
+                .next()
+                .unwrap_or("invalid syntax")
+                .to_string();
+            Some(ValidationIssue::SyntaxError(message))
+        }
+        // a missing checker is not the command's fault
+        _ => None,
+    }
+}
+
+// Aliases and functions of the user's shell, reported by bott.sh as they aren't on $PATH
+fn get_shell_commands() -> Vec<String> {
+    env::var("bott_shell_commands")
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+fn check_segment(words: &[String], shell_commands: &[String], issues: &mut Vec<ValidationIssue>) {
+    let mut position = 0;
+    while position < words.len() && is_assignment(words[position].as_str()) {
+        position += 1;
+    }
+    // skip wrappers such as `sudo -u root` to find the command that actually runs
+    while position < words.len() && COMMAND_WRAPPERS.contains(&words[position].as_str()) {
+        position += 1;
+        while position < words.len()
+            && (words[position].starts_with('-') || is_assignment(words[position].as_str()))
+        {
+            if WRAPPER_VALUE_FLAGS.contains(&words[position].as_str()) {
+                position += 1;
+            }
+            position += 1;
+        }
+    }
+    let Some(program) = words.get(position) else {
+        return;
+    };
+    let is_builtin = SHELL_KEYWORDS.contains(&program.as_str())
+        || SHELL_BUILTINS.contains(&program.as_str())
+        || shell_commands.contains(program);
+    if !is_builtin && !program.starts_with('$') && !find_executable(program) {
+        issues.push(ValidationIssue::MissingExecutable(program.clone()));
+    }
+    if FILE_CREATORS.contains(&program.as_str()) {
+        return;
+    }
+    let mut arguments: Vec<&String> = words[position + 1..]
+        .iter()
+        .filter(|w| looks_like_path(w))
+        .collect();
+    if FILE_COPIERS.contains(&program.as_str()) {
+        arguments.pop();
+    }
+    for argument in arguments {
+        if !expand_home(argument).exists() {
+            issues.push(ValidationIssue::MissingFile(argument.clone()));
+        }
+    }
+}
+
+pub fn validate_command(command: &str, shell: &str) -> Vec<ValidationIssue> {
+    let mut issues: Vec<ValidationIssue> = vec![];
+    if let Some(issue) = check_syntax(command, shell) {
+        // the tokenizer can't make sense of code the shell itself rejects
+        issues.push(issue);
+        return issues;
+    }
+    let shell_commands = get_shell_commands();
+    let (segments, inputs) = split_segments(tokenize(command));
+    for segment in segments {
+        check_segment(&segment, &shell_commands, &mut issues);
+    }
+    for input in inputs {
+        if looks_like_path(input.as_str()) && !expand_home(input.as_str()).exists() {
+            issues.push(ValidationIssue::MissingFile(input));
+        }
+    }
+    issues.dedup();
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(command: &str) -> Vec<Vec<String>> {
+        split_segments(tokenize(command)).0
+    }
+    fn check(command: &str, shell_commands: &[&str]) -> Vec<ValidationIssue> {
+        let shell_commands = shell_commands
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>();
+        let mut issues: Vec<ValidationIssue> = vec![];
+        for segment in words(command) {
+            check_segment(&segment, &shell_commands, &mut issues);
+        }
+        issues
+    }
+
+    #[test]
+    fn tokenizes_quotes_and_escapes() {
+        assert_eq!(
+            words(r#"grep -r "hello world" 'it''s' a\ b"#),
+            vec![vec!["grep", "-r", "hello world", "its", "a b"]]
+        );
+        assert_eq!(
+            words(r#"echo "a \"quoted\" word""#),
+            vec![vec!["echo", "a \"quoted\" word"]]
+        );
+    }
+
+    #[test]
+    fn splits_pipes_lists_and_substitutions() {
+        assert_eq!(
+            words("ps aux | grep ssh && echo found; ls $(pwd) 2>/dev/null"),
+            vec![
+                vec!["ps", "aux"],
+                vec!["grep", "ssh"],
+                vec!["echo", "found"],
+                vec!["ls"],
+                vec!["pwd"],
+            ]
+        );
+        let (segments, inputs) = split_segments(tokenize("sort < ./names.txt > ./sorted.txt"));
+        assert_eq!(segments, vec![vec!["sort"]]);
+        assert_eq!(inputs, vec!["./names.txt"]);
+    }
+
+    #[test]
+    fn skips_heredoc_bodies_and_here_strings() {
+        let command =
+            "cat <<'EOF' > ./notes.txt\nhello there\nnot-a-command\nEOF\nwc -l ./notes.txt";
+        assert_eq!(
+            words(command),
+            vec![vec!["cat"], vec!["wc", "-l", "./notes.txt"]]
+        );
+        assert_eq!(words("cat <<-END\n\tbody\n\tEND"), vec![vec!["cat"]]);
+        let (segments, inputs) = split_segments(tokenize("grep -c a <<< ./not/a/file"));
+        assert_eq!(segments, vec![vec!["grep", "-c", "a"]]);
+        assert!(inputs.is_empty());
+    }
+
+    #[test]
+    fn finds_the_program_behind_wrappers() {
+        assert!(check("sudo -u root ls /", &[]).is_empty());
+        assert!(check("env LANG=C FOO=1 ls /", &[]).is_empty());
+        assert!(check("DEBUG=1 nice -n 10 ls", &[]).is_empty());
+        assert_eq!(
+            check("sudo bott-missing-program --flag", &[]),
+            vec![ValidationIssue::MissingExecutable(String::from(
+                "bott-missing-program"
+            ))]
+        );
+    }
+
+    #[test]
+    fn accepts_builtins_aliases_and_functions() {
+        assert!(check("cd /tmp && export A=1 && type ls | read -r line", &[]).is_empty());
+        assert!(check("if true; then echo yes; fi", &[]).is_empty());
+        assert_eq!(
+            check("ll && gco main", &[]),
+            vec![
+                ValidationIssue::MissingExecutable(String::from("ll")),
+                ValidationIssue::MissingExecutable(String::from("gco")),
+            ]
+        );
+        assert!(check("ll && gco main", &["ll", "gco"]).is_empty());
+    }
+
+    #[test]
+    fn reports_missing_input_files() {
+        assert_eq!(
+            check("cat ./bott-missing-file", &[]),
+            vec![ValidationIssue::MissingFile(String::from(
+                "./bott-missing-file"
+            ))]
+        );
+        // the destination of a copy doesn't have to exist yet
+        assert!(check("cp -r /tmp ./bott-missing-copy", &[]).is_empty());
+        assert!(check("touch ./bott-missing-file", &[]).is_empty());
+        assert_eq!(validate_command("echo 'unterminated", "/bin/bash").len(), 1);
+    }
+}